@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use rocket::tokio;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::auth::oauth::{self, TokenIssuer};
+use crate::models::api_key::{Role, Scope};
+use crate::request_guards::basic::AuthError;
+use crate::request_guards::bearer::BearerToken;
+
+/// Signature algorithms accepted in bearer tokens.
+const ALGORITHMS: [Algorithm; 3] = [Algorithm::RS256, Algorithm::ES256, Algorithm::HS256];
+/// An unknown `kid` reloads the key set at most this often.
+const MIN_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// `[default.auth.jwt]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct JwtConfig {
+    /// Accept `Authorization: Bearer` tokens next to API keys.
+    pub enabled: bool,
+    /// JWKS file holding the keys tokens are signed with.
+    pub jwks_path: String,
+    /// JWKS endpoint of the identity provider.
+    pub jwks_url: String,
+    /// Seconds the key set is used before it is loaded again.
+    pub refresh_secs: u64,
+    /// Required `iss` claim.
+    pub issuer: String,
+    /// Required in the `aud` claim.
+    pub audience: String,
+    /// Seconds of clock skew tolerated on `exp` and `nbf`.
+    pub leeway_secs: u64,
+    /// Claim holding the granted scopes, space separated or as an array.
+    pub scope_claim: String,
+    /// Scopes granted for a value of `scope_claim`, for values that are
+    /// not already scopes of this API such as `customer:read`.
+    pub scope_map: HashMap<String, Vec<Scope>>,
+    /// Claim holding the roles, `viewer`, `editor` or `admin`, space
+    /// separated or as an array.
+    pub role_claim: String,
+    /// Claim holding the team owning the customers the token may access.
+    pub team_claim: String,
+    /// Claim holding the tenant the token is bound to.
+    pub tenant_claim: String,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        JwtConfig {
+            enabled: false,
+            jwks_path: String::new(),
+            jwks_url: String::new(),
+            refresh_secs: 300,
+            issuer: String::new(),
+            audience: String::new(),
+            leeway_secs: 60,
+            scope_claim: "scope".to_owned(),
+            scope_map: HashMap::new(),
+            role_claim: "roles".to_owned(),
+            team_claim: "team".to_owned(),
+            tenant_claim: "tenant".to_owned(),
+        }
+    }
+}
+
+impl JwtConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if self.jwks_path.is_empty() == self.jwks_url.is_empty() {
+            return Err("auth.jwt needs one of jwks_path and jwks_url".into());
+        }
+        if self.issuer.is_empty() || self.audience.is_empty() {
+            return Err("auth.jwt.issuer and auth.jwt.audience are required".into());
+        }
+        Ok(())
+    }
+}
+
+/// The values of a claim, space separated or as an array.
+fn values(claim: Option<&Value>) -> Vec<&str> {
+    match claim {
+        Some(Value::String(values)) => values.split_whitespace().collect(),
+        Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+struct CachedKeys {
+    keys: JwkSet,
+    fetched: Instant,
+}
+
+/// Verifies bearer tokens issued by `/oauth/token` and, when `auth.jwt` is
+/// enabled, tokens signed with a JWKS read from `auth.jwt.jwks_path` or
+/// fetched from `auth.jwt.jwks_url`. Clones share the same key set.
+///
+/// The key set is loaded again every `auth.jwt.refresh_secs`, and sooner when
+/// a token is signed with a key it does not hold yet, so that keys rotated by
+/// the identity provider are picked up.
+#[derive(Clone)]
+pub struct JwtVerifier {
+    config: JwtConfig,
+    issuer: TokenIssuer,
+    client: reqwest::Client,
+    keys: Arc<Mutex<Option<CachedKeys>>>,
+    /// Held while loading, so that concurrent requests load the keys once.
+    loading: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl JwtVerifier {
+    pub fn new(config: JwtConfig, issuer: TokenIssuer) -> JwtVerifier {
+        JwtVerifier {
+            config,
+            issuer,
+            client: reqwest::Client::new(),
+            keys: Arc::default(),
+            loading: Arc::default(),
+        }
+    }
+
+    pub async fn verify(&self, token: &str) -> Result<BearerToken, AuthError> {
+        let header = decode_header(token).map_err(|_| AuthError::Invalid)?;
+        if header.kid.as_deref() == Some(oauth::KEY_ID) {
+            return self.issuer.verify(token).await;
+        }
+        if !self.config.enabled || !ALGORITHMS.contains(&header.alg) {
+            return Err(AuthError::Invalid);
+        }
+
+        let jwk = match self.find(header.kid.as_deref(), false).await? {
+            Some(jwk) => jwk,
+            None => self
+                .find(header.kid.as_deref(), true)
+                .await?
+                .ok_or(AuthError::Invalid)?,
+        };
+        // a key published for another algorithm must not verify this one
+        if jwk
+            .common
+            .key_algorithm
+            .is_some_and(|alg| alg.to_string() != format!("{:?}", header.alg))
+        {
+            return Err(AuthError::Invalid);
+        }
+        let key = DecodingKey::from_jwk(&jwk).map_err(|_| AuthError::Invalid)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        validation.leeway = self.config.leeway_secs;
+        validation.validate_nbf = true;
+
+        let claims = decode::<Map<String, Value>>(token, &key, &validation)
+            .map_err(|_| AuthError::Invalid)?
+            .claims;
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .ok_or(AuthError::Invalid)?;
+        Ok(BearerToken::new(
+            subject,
+            &self.config.issuer,
+            self.scopes(claims.get(&self.config.scope_claim)),
+            values(claims.get(&self.config.role_claim))
+                .into_iter()
+                .filter_map(Role::parse)
+                .collect(),
+            claims
+                .get(&self.config.team_claim)
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+            claims
+                .get(&self.config.tenant_claim)
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+        ))
+    }
+
+    /// The scopes granted by the value of `scope_claim`.
+    fn scopes(&self, claim: Option<&Value>) -> Vec<Scope> {
+        let mut scopes = Vec::new();
+        for value in values(claim) {
+            let granted = match self.config.scope_map.get(value) {
+                Some(mapped) => mapped.clone(),
+                None => Scope::parse(value).into_iter().collect(),
+            };
+            for scope in granted {
+                if !scopes.contains(&scope) {
+                    scopes.push(scope);
+                }
+            }
+        }
+        scopes
+    }
+
+    /// The key a token names with `kid`, or the only key of the set when the
+    /// token names none.
+    async fn find(&self, kid: Option<&str>, reload: bool) -> Result<Option<Jwk>, AuthError> {
+        let keys = self.keys(reload).await?;
+        Ok(match kid {
+            Some(kid) => keys.find(kid).cloned(),
+            None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+            None => None,
+        })
+    }
+
+    /// The current key set, loaded again when stale or when `reload` asks
+    /// for keys the set may not hold yet.
+    async fn keys(&self, reload: bool) -> Result<JwkSet, AuthError> {
+        let refresh = Duration::from_secs(self.config.refresh_secs);
+        let current = |max_age: Duration| {
+            self.keys
+                .lock()
+                .unwrap()
+                .as_ref()
+                .filter(|cached| cached.fetched.elapsed() < max_age)
+                .map(|cached| cached.keys.clone())
+        };
+        let max_age = if reload {
+            MIN_RELOAD_INTERVAL.min(refresh)
+        } else {
+            refresh
+        };
+        if let Some(keys) = current(max_age) {
+            return Ok(keys);
+        }
+
+        let _loading = self.loading.lock().await;
+        // loaded by another request in the meantime
+        if let Some(keys) = current(max_age) {
+            return Ok(keys);
+        }
+        match self.load().await {
+            Ok(keys) => {
+                *self.keys.lock().unwrap() = Some(CachedKeys {
+                    keys: keys.clone(),
+                    fetched: Instant::now(),
+                });
+                Ok(keys)
+            }
+            Err(error) => {
+                let mut cached = self.keys.lock().unwrap();
+                match cached.as_mut() {
+                    // keep verifying with the keys we have, and retry later
+                    Some(cached) => {
+                        tracing::warn!(%error, "cannot reload the JWKS, keeping the previous keys");
+                        cached.fetched = Instant::now();
+                        Ok(cached.keys.clone())
+                    }
+                    None => {
+                        tracing::error!(%error, "cannot load the JWKS");
+                        Err(AuthError::Unavailable)
+                    }
+                }
+            }
+        }
+    }
+
+    async fn load(&self) -> Result<JwkSet, String> {
+        if !self.config.jwks_path.is_empty() {
+            let keys = tokio::fs::read_to_string(&self.config.jwks_path)
+                .await
+                .map_err(|error| error.to_string())?;
+            return serde_json::from_str(&keys).map_err(|error| error.to_string());
+        }
+        let keys = self
+            .client
+            .get(&self.config.jwks_url)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|error| error.to_string())?
+            .text()
+            .await
+            .map_err(|error| error.to_string())?;
+        serde_json::from_str(&keys).map_err(|error| error.to_string())
+    }
+
+    /// Load the key set ahead of the first request.
+    pub async fn preload(&self) {
+        if self.config.enabled {
+            // failures are logged by `keys`
+            let _ = self.keys(false).await;
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::stream::TryStreamExt;
+use hmac::{Hmac, Mac};
+use mongodb::{
+    bson::{self, oid::ObjectId},
+    Database,
+};
+use rocket::fairing::AdHoc;
+use sha2::Sha256;
+use uuid::Uuid;
+
+pub mod jwt;
+pub mod mtls;
+pub mod oauth;
+pub mod signature;
+
+use crate::auth::jwt::JwtVerifier;
+use crate::auth::oauth::TokenIssuer;
+use crate::auth::signature::SignatureVerifier;
+use crate::config::{AppConfig, AuthConfig};
+use crate::db::api_key;
+use crate::models::api_key::ApiKeyDocument;
+use crate::request_guards::authorized::{forbidden, unauthorized};
+use crate::request_guards::basic::{ApiKey, AuthError};
+
+/// Issued keys look like `rrs_<prefix>_<secret>`.
+const KEY_PREFIX: &str = "rrs_";
+/// Stale entries are dropped once the cache holds this many keys.
+const MAX_CACHED_KEYS: usize = 10_000;
+/// `lastUsedAt` is written at most once per key in this many milliseconds.
+const TOUCH_INTERVAL_MS: i64 = 60_000;
+
+/// A key as handed out on creation; only its hash is stored.
+pub struct GeneratedKey {
+    /// The full key, shown to its owner once.
+    pub key: String,
+    pub prefix: String,
+    pub hash: String,
+    /// The secret to sign requests with, shown with the key.
+    pub signing_secret: String,
+}
+
+struct CachedKey {
+    key: ApiKeyDocument,
+    fetched: Instant,
+}
+
+/// Verifies API keys against the `api_keys` collection and the optional
+/// bootstrap key of `auth.api_key`. Clones share the same cache.
+///
+/// Changes to a key, such as a revocation, evict it from the cache of every
+/// instance through a change stream, a cached key being trusted for
+/// `auth.cache_ttl_secs` at most. Without a change stream, as on a standalone
+/// MongoDB, keys are read again on every use so that a revocation takes
+/// effect at once on every instance.
+#[derive(Clone)]
+pub struct ApiKeyStore {
+    db: Option<Database>,
+    config: AuthConfig,
+    cache: Arc<Mutex<HashMap<String, CachedKey>>>,
+    /// Whether a change stream currently evicts changed keys.
+    watching: Arc<AtomicBool>,
+}
+
+impl ApiKeyStore {
+    pub fn new(config: AuthConfig, db: Option<Database>) -> ApiKeyStore {
+        ApiKeyStore {
+            db,
+            config,
+            cache: Arc::default(),
+            watching: Arc::default(),
+        }
+    }
+
+    /// How long a key read from the database is trusted, zero while no
+    /// change stream reports changes made by other instances.
+    pub fn cache_ttl(&self) -> Duration {
+        if self.watching.load(Ordering::SeqCst) {
+            Duration::from_secs(self.config.cache_ttl_secs)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// A new random key with the hash to store for it.
+    pub fn generate(&self) -> GeneratedKey {
+        let prefix = Uuid::new_v4().simple().to_string()[..12].to_owned();
+        let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let hash = self.hash(&secret);
+        GeneratedKey {
+            key: format!("{}{}_{}", KEY_PREFIX, prefix, secret),
+            signing_secret: self.signing_secret(&hash),
+            hash,
+            prefix,
+        }
+    }
+
+    /// The secret signing requests made with the key hashed as `hash`,
+    /// derived rather than stored so that the collection alone cannot
+    /// forge signatures.
+    pub fn signing_secret(&self, hash: &str) -> String {
+        let mut mac = self.mac("request signing:");
+        mac.update(hash.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// HMAC-SHA256 of a secret keyed with the pepper, so that a leaked
+    /// collection is useless without the configuration.
+    pub fn hash(&self, secret: &str) -> String {
+        hex::encode(self.mac(secret).finalize().into_bytes())
+    }
+
+    fn mac(&self, secret: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.config.pepper.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(secret.as_bytes());
+        mac
+    }
+
+    /// Compare in constant time against a hex encoded hash.
+    fn matches(&self, secret: &str, hash: &str) -> bool {
+        match hex::decode(hash) {
+            Ok(expected) => self.mac(secret).verify_slice(&expected).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Resolve the key sent by a client, wherever it was sent.
+    pub async fn verify(&self, key: &str) -> Result<ApiKey, AuthError> {
+        if !self.config.api_key.is_empty() && self.matches(key, &self.hash(&self.config.api_key)) {
+            return Ok(ApiKey::bootstrap(key));
+        }
+
+        let (Some(db), Some((prefix, secret))) = (
+            &self.db,
+            key.strip_prefix(KEY_PREFIX)
+                .and_then(|rest| rest.split_once('_')),
+        ) else {
+            return Err(AuthError::Invalid);
+        };
+        let document = match self.find(db, prefix).await {
+            Ok(Some(document)) => document,
+            Ok(None) => return Err(AuthError::Invalid),
+            Err(error) => {
+                tracing::error!(%error, "cannot look up API key");
+                return Err(AuthError::Unavailable);
+            }
+        };
+
+        let now = bson::DateTime::now();
+        // expired and revoked keys are reported like unknown ones
+        if !self.matches(secret, &document.hash) || !document.is_active(now) {
+            return Err(AuthError::Invalid);
+        }
+        self.touch(db, &document, now);
+        Ok(ApiKey::stored(&document))
+    }
+
+    async fn find(
+        &self,
+        db: &Database,
+        prefix: &str,
+    ) -> mongodb::error::Result<Option<ApiKeyDocument>> {
+        let ttl = self.cache_ttl();
+        if let Some(cached) = self.cache.lock().unwrap().get(prefix) {
+            if cached.fetched.elapsed() < ttl {
+                return Ok(Some(cached.key.clone()));
+            }
+        }
+
+        let document = api_key::find_api_key_by_prefix(db, prefix).await?;
+        let mut cache = self.cache.lock().unwrap();
+        match &document {
+            Some(document) => {
+                if cache.len() >= MAX_CACHED_KEYS {
+                    cache.retain(|_, cached| cached.fetched.elapsed() < ttl);
+                }
+                cache.insert(
+                    prefix.to_owned(),
+                    CachedKey {
+                        key: document.clone(),
+                        fetched: Instant::now(),
+                    },
+                );
+            }
+            None => {
+                cache.remove(prefix);
+            }
+        }
+        Ok(document)
+    }
+
+    /// Record the use of a key without delaying the request.
+    fn touch(&self, db: &Database, document: &ApiKeyDocument, now: bson::DateTime) {
+        let recent = document.last_used_at.is_some_and(|last_used_at| {
+            now.timestamp_millis() - last_used_at.timestamp_millis() < TOUCH_INTERVAL_MS
+        });
+        if recent {
+            return;
+        }
+        if let Some(cached) = self.cache.lock().unwrap().get_mut(&document.prefix) {
+            cached.key.last_used_at = Some(now);
+        }
+
+        let (db, oid) = (db.clone(), document.id);
+        rocket::tokio::spawn(async move {
+            if let Err(error) = api_key::touch_api_key(&db, oid, now).await {
+                tracing::warn!(%error, "cannot record API key use");
+            }
+        });
+    }
+
+    /// Forget a key, so that its next use reads it again.
+    pub fn evict(&self, oid: ObjectId) {
+        self.cache
+            .lock()
+            .unwrap()
+            .retain(|_, cached| cached.key.id != oid);
+    }
+
+    /// Evict keys changed by any instance until the change stream ends.
+    /// Returns an error right away when change streams are unsupported.
+    async fn watch(&self, db: &Database) -> mongodb::error::Result<()> {
+        let collection = db.collection::<ApiKeyDocument>("api_keys");
+        let mut stream = collection.watch(None, None).await?;
+        // keys cached before may have changed unnoticed
+        self.cache.lock().unwrap().clear();
+        self.watching.store(true, Ordering::SeqCst);
+
+        let watched = async {
+            while let Some(change) = stream.try_next().await? {
+                if let Some(oid) = change
+                    .document_key
+                    .as_ref()
+                    .and_then(|key| key.get_object_id("_id").ok())
+                {
+                    self.evict(oid);
+                }
+            }
+            Ok(())
+        }
+        .await;
+        self.watching.store(false, Ordering::SeqCst);
+        watched
+    }
+}
+
+/// Manage the `ApiKeyStore`, the `TokenIssuer`, the `JwtVerifier` and the
+/// `SignatureVerifier`, so it has to be attached after `db::init`, and
+/// answer 401 and 403 with `MyError`.
+pub fn init() -> AdHoc {
+    AdHoc::on_ignite("API keys and tokens", |rocket| async {
+        let config = rocket
+            .state::<AppConfig>()
+            .map(|config| config.auth.clone())
+            .unwrap_or_default();
+        let db = rocket.state::<Database>().cloned();
+        // readable with the `secrets` feature of Rocket
+        let secret_key = rocket.figment().extract_inner::<String>("secret_key").ok();
+        let store = ApiKeyStore::new(config.clone(), db.clone());
+        let issuer = TokenIssuer::new(
+            config.oauth.clone(),
+            &config.oauth.key(secret_key.as_deref()),
+            store.clone(),
+            db.clone(),
+        );
+        let verifier = JwtVerifier::new(config.jwt, issuer.clone());
+        let signatures = SignatureVerifier::new(config.signing, store.clone(), db);
+        rocket
+            .manage(store)
+            .manage(issuer)
+            .manage(verifier)
+            .manage(signatures)
+            .register("/", catchers![unauthorized, forbidden])
+            .attach(AdHoc::on_liftoff("Loading the JWKS", |rocket| {
+                Box::pin(async move {
+                    if let Some(verifier) = rocket.state::<JwtVerifier>() {
+                        verifier.preload().await;
+                    }
+                })
+            }))
+            .attach(AdHoc::on_liftoff("Watching API key changes", |rocket| {
+                Box::pin(async move {
+                    let (Some(db), Some(store)) =
+                        (rocket.state::<Database>(), rocket.state::<ApiKeyStore>())
+                    else {
+                        return;
+                    };
+                    let (db, store) = (db.clone(), store.clone());
+                    rocket::tokio::spawn(async move {
+                        if let Err(error) = store.watch(&db).await {
+                            tracing::warn!(
+                                %error,
+                                "API key change stream unavailable, keys are read on every use"
+                            );
+                        }
+                    });
+                })
+            }))
+    })
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::models::api_key::{Role, Scope};
+use crate::request_guards::client_certificate::ClientCertificate;
+
+/// What a client certificate grants, like a stored API key.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct CertificateClient {
+    pub scopes: Vec<Scope>,
+    pub roles: Vec<Role>,
+    /// Team owning the customers the client may access, unless an admin.
+    pub team: Option<String>,
+    /// Tenant the client is bound to; when absent, any for admins, the
+    /// default one otherwise.
+    pub tenant: Option<String>,
+}
+
+/// `[default.auth.mtls]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct MtlsConfig {
+    /// Accept client certificates verified against `tls.mutual.ca_certs`
+    /// in place of API keys.
+    pub enabled: bool,
+    /// The clients, keyed by the common name of their certificate subject.
+    pub clients: HashMap<String, CertificateClient>,
+}
+
+impl MtlsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if self.clients.is_empty() {
+            return Err("auth.mtls.clients needs at least one client".into());
+        }
+        if let Some(name) = self
+            .clients
+            .iter()
+            .find(|(_, client)| client.scopes.is_empty() && client.roles.is_empty())
+            .map(|(name, _)| name)
+        {
+            return Err(format!("auth.mtls client {:?} needs scopes or roles", name));
+        }
+        Ok(())
+    }
+
+    /// The principal of a verified certificate whose subject has
+    /// `common_name`, if it is one of the clients.
+    pub fn principal(&self, common_name: &str) -> Option<ClientCertificate> {
+        if !self.enabled {
+            return None;
+        }
+        let client = self.clients.get(common_name)?;
+        Some(ClientCertificate::new(common_name, client))
+    }
+}
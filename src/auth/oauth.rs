@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use mongodb::{bson, Database};
+use serde::Deserialize;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::auth::ApiKeyStore;
+use crate::db::oauth;
+use crate::errors::oauth::OAuthError;
+use crate::models::api_key::Scope;
+use crate::models::oauth::{AccessClaims, OAuthClientDocument, TokenResponse};
+use crate::request_guards::basic::AuthError;
+use crate::request_guards::bearer::BearerToken;
+
+/// `kid` of the tokens issued here, telling them apart from tokens signed
+/// with keys of the JWKS.
+pub const KEY_ID: &str = "rrs-oauth";
+/// Client ids look like `rrc_<12 hex digits>`.
+const CLIENT_PREFIX: &str = "rrc_";
+/// Stale entries are dropped once the cache holds this many tokens.
+const MAX_CACHED_TOKENS: usize = 10_000;
+
+/// `[default.auth.oauth]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct OAuthConfig {
+    /// HS256 key signing issued tokens, derived from Rocket's `secret_key`
+    /// when empty.
+    pub signing_key: String,
+    /// `iss` claim of issued tokens.
+    pub issuer: String,
+    /// `aud` claim of issued tokens.
+    pub audience: String,
+    /// Seconds an issued token is valid for.
+    pub token_ttl_secs: u64,
+}
+
+impl Default for OAuthConfig {
+    fn default() -> Self {
+        OAuthConfig {
+            signing_key: String::new(),
+            issuer: "rust-rocket-sample".to_owned(),
+            audience: "rust-rocket-sample".to_owned(),
+            token_ttl_secs: 900,
+        }
+    }
+}
+
+impl OAuthConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.signing_key.is_empty() && self.signing_key.len() < 32 {
+            return Err("auth.oauth.signing_key must be at least 32 characters".into());
+        }
+        if self.issuer.is_empty() || self.audience.is_empty() || self.token_ttl_secs == 0 {
+            return Err("auth.oauth needs an issuer, an audience and a token_ttl_secs".into());
+        }
+        Ok(())
+    }
+
+    /// The key to sign tokens with: `signing_key`, else a key derived from
+    /// Rocket's `secret_key` so that the cookie key is not reused as such,
+    /// else a random key that tokens do not survive a restart with.
+    pub fn key(&self, secret_key: Option<&str>) -> Vec<u8> {
+        if !self.signing_key.is_empty() {
+            return self.signing_key.as_bytes().to_vec();
+        }
+        match secret_key {
+            Some(secret_key) => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret_key.as_bytes())
+                    .expect("HMAC accepts keys of any size");
+                mac.update(b"oauth token signing");
+                mac.finalize().into_bytes().to_vec()
+            }
+            None => {
+                tracing::warn!(
+                    "neither auth.oauth.signing_key nor secret_key is set, \
+                     issued tokens are lost on restart"
+                );
+                format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()).into_bytes()
+            }
+        }
+    }
+}
+
+struct CachedRevocation {
+    revoked: bool,
+    checked: Instant,
+}
+
+/// Issues and verifies the access tokens of the client-credentials grant.
+/// Clones share the same revocation cache.
+///
+/// A revoked token is remembered at once by the instance revoking it, and
+/// within `auth.cache_ttl_secs` by the others.
+#[derive(Clone)]
+pub struct TokenIssuer {
+    db: Option<Database>,
+    config: OAuthConfig,
+    keys: ApiKeyStore,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    cache_ttl: Duration,
+    revocations: Arc<Mutex<HashMap<String, CachedRevocation>>>,
+}
+
+impl TokenIssuer {
+    /// `keys` hashes client secrets with the same pepper as API keys.
+    pub fn new(
+        config: OAuthConfig,
+        key: &[u8],
+        keys: ApiKeyStore,
+        db: Option<Database>,
+    ) -> TokenIssuer {
+        TokenIssuer {
+            cache_ttl: Duration::from_secs(keys.config.cache_ttl_secs),
+            db,
+            config,
+            keys,
+            encoding: EncodingKey::from_secret(key),
+            decoding: DecodingKey::from_secret(key),
+            revocations: Arc::default(),
+        }
+    }
+
+    /// A new client id, and a random secret with the hash to store for it.
+    pub fn generate_client(&self) -> (String, String, String) {
+        let client_id = format!(
+            "{}{}",
+            CLIENT_PREFIX,
+            &Uuid::new_v4().simple().to_string()[..12]
+        );
+        let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let hash = self.keys.hash(&secret);
+        (client_id, secret, hash)
+    }
+
+    /// The active client registered as `client_id` with `client_secret`.
+    pub async fn authenticate(
+        &self,
+        client_id: &str,
+        client_secret: &str,
+    ) -> Result<OAuthClientDocument, OAuthError> {
+        let Some(db) = &self.db else {
+            return Err(OAuthError::temporarily_unavailable());
+        };
+        match oauth::find_oauth_client_by_client_id(db, client_id).await {
+            Ok(Some(client))
+                if client.revoked_at.is_none()
+                    && self.keys.matches(client_secret, &client.secret_hash) =>
+            {
+                Ok(client)
+            }
+            Ok(_) => {
+                tracing::info!(client_id, "OAuth2 client authentication failed");
+                Err(OAuthError::invalid_client())
+            }
+            Err(error) => {
+                tracing::error!(%error, "cannot look up OAuth2 client");
+                Err(OAuthError::temporarily_unavailable())
+            }
+        }
+    }
+
+    /// A signed access token granting `scopes` to `client`.
+    pub fn issue(&self, client: &OAuthClientDocument, scopes: &[Scope]) -> TokenResponse {
+        let now = chrono::Utc::now().timestamp();
+        let scope = scopes
+            .iter()
+            .map(Scope::as_str)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let claims = AccessClaims {
+            iss: self.config.issuer.clone(),
+            aud: self.config.audience.clone(),
+            sub: client.client_id.clone(),
+            client_id: client.client_id.clone(),
+            scope: scope.clone(),
+            team: client.team.clone(),
+            tenant: client.tenant.clone(),
+            iat: now,
+            exp: now + self.config.token_ttl_secs as i64,
+            jti: Uuid::new_v4().to_string(),
+        };
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(KEY_ID.to_owned());
+        // a token just issued is not revoked, no need to ask the database
+        self.remember(&claims.jti, false);
+
+        TokenResponse {
+            access_token: encode(&header, &claims, &self.encoding)
+                .expect("HS256 signing does not fail"),
+            token_type: "Bearer".to_owned(),
+            expires_in: self.config.token_ttl_secs,
+            scope,
+        }
+    }
+
+    /// The claims of a token issued here that has not expired, revoked or not.
+    pub fn decode(&self, token: &str) -> Option<AccessClaims> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.audience]);
+        validation.leeway = 0;
+        decode::<AccessClaims>(token, &self.decoding, &validation)
+            .ok()
+            .map(|data| data.claims)
+    }
+
+    pub async fn verify(&self, token: &str) -> Result<BearerToken, AuthError> {
+        let claims = self.decode(token).ok_or(AuthError::Invalid)?;
+        if self.is_revoked(&claims).await? {
+            return Err(AuthError::Invalid);
+        }
+        let scopes = claims
+            .scope
+            .split_whitespace()
+            .filter_map(Scope::parse)
+            .collect();
+        Ok(BearerToken::new(
+            &claims.sub,
+            &claims.iss,
+            scopes,
+            Vec::new(),
+            claims.team,
+            claims.tenant,
+        ))
+    }
+
+    pub async fn is_revoked(&self, claims: &AccessClaims) -> Result<bool, AuthError> {
+        if let Some(cached) = self.revocations.lock().unwrap().get(&claims.jti) {
+            // a revocation is never undone
+            if cached.revoked || cached.checked.elapsed() < self.cache_ttl {
+                return Ok(cached.revoked);
+            }
+        }
+        let Some(db) = &self.db else {
+            return Ok(false);
+        };
+        let revoked = match oauth::is_token_revoked(db, &claims.jti).await {
+            Ok(revoked) => revoked,
+            Err(error) => {
+                tracing::error!(%error, "cannot look up token revocation");
+                return Err(AuthError::Unavailable);
+            }
+        };
+        self.remember(&claims.jti, revoked);
+        Ok(revoked)
+    }
+
+    /// Reject the token from now on, until it expires.
+    pub async fn revoke(&self, claims: &AccessClaims) -> mongodb::error::Result<()> {
+        if let Some(db) = &self.db {
+            let expires_at = bson::DateTime::from_millis(claims.exp * 1000);
+            oauth::insert_revoked_token(db, &claims.jti, expires_at).await?;
+        }
+        self.remember(&claims.jti, true);
+        Ok(())
+    }
+
+    fn remember(&self, jti: &str, revoked: bool) {
+        let mut revocations = self.revocations.lock().unwrap();
+        if revocations.len() >= MAX_CACHED_TOKENS {
+            // tokens are short-lived, so are their entries
+            let max_age = Duration::from_secs(self.config.token_ttl_secs).max(self.cache_ttl);
+            revocations.retain(|_, cached| cached.checked.elapsed() < max_age);
+        }
+        revocations.insert(
+            jti.to_owned(),
+            CachedRevocation {
+                revoked,
+                checked: Instant::now(),
+            },
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use hmac::{Hmac, Mac};
+use mongodb::{bson, Database};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::auth::ApiKeyStore;
+use crate::db::api_key;
+use crate::models::api_key::Scope;
+use crate::request_guards::basic::{ApiKey, AuthError};
+
+/// Scheme of the `Authorization` header of signed requests.
+pub const SCHEME: &str = "HMAC-SHA256";
+/// Expired nonces are dropped once the cache holds this many.
+const MAX_CACHED_NONCES: usize = 100_000;
+
+/// `[default.auth.signing]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SigningConfig {
+    /// Accept requests signed with the secret of an API key.
+    pub enabled: bool,
+    /// Seconds a signed request may be early or late, and its nonce is
+    /// remembered for.
+    pub window_secs: u64,
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        SigningConfig {
+            enabled: true,
+            window_secs: 300,
+        }
+    }
+}
+
+impl SigningConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window_secs == 0 || self.window_secs > 3600 {
+            return Err("auth.signing.window_secs must be between 1 and 3600".into());
+        }
+        Ok(())
+    }
+}
+
+/// The parameters of an `Authorization: HMAC-SHA256 KeyId=<prefix>,
+/// Timestamp=<unix seconds>, Nonce=<nonce>, Signature=<hex>` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureHeader {
+    /// The public part of the API key, as in `rrs_<prefix>_<secret>`.
+    pub key_id: String,
+    pub timestamp: i64,
+    pub nonce: String,
+    pub signature: String,
+}
+
+impl SignatureHeader {
+    /// The parameters of an `Authorization` header value, if it carries a
+    /// signature.
+    pub fn parse(authorization: &str) -> Option<SignatureHeader> {
+        let (scheme, parameters) = authorization.split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case(SCHEME) {
+            return None;
+        }
+        let mut parameters: HashMap<_, _> = parameters
+            .split(',')
+            .filter_map(|parameter| parameter.trim().split_once('='))
+            .collect();
+        Some(SignatureHeader {
+            key_id: parameters.remove("KeyId")?.to_owned(),
+            timestamp: parameters.remove("Timestamp")?.parse().ok()?,
+            nonce: parameters.remove("Nonce")?.to_owned(),
+            signature: parameters.remove("Signature")?.to_owned(),
+        })
+    }
+}
+
+/// Hex encoded SHA-256 of a request body, sent in `X-Content-SHA256`.
+pub fn body_hash(body: &[u8]) -> String {
+    hex::encode(Sha256::digest(body))
+}
+
+/// The lines signed by the client: the scheme, the method, the path with its
+/// query, the timestamp, the nonce and the body hash.
+pub fn string_to_sign(
+    method: &str,
+    path: &str,
+    timestamp: i64,
+    nonce: &str,
+    body_hash: &str,
+) -> String {
+    format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        SCHEME, method, path, timestamp, nonce, body_hash
+    )
+}
+
+/// Verifies requests signed with the secret of a stored API key, which
+/// grants the scopes of the key but `admin`. Clones share the same nonces.
+///
+/// Nonces are remembered by every instance through the `request_nonces`
+/// collection for as long as their timestamp is accepted.
+#[derive(Clone)]
+pub struct SignatureVerifier {
+    config: SigningConfig,
+    keys: ApiKeyStore,
+    db: Option<Database>,
+    /// Expiry of the nonces seen by this instance, in unix seconds.
+    nonces: Arc<Mutex<HashMap<String, i64>>>,
+}
+
+impl SignatureVerifier {
+    pub fn new(
+        config: SigningConfig,
+        keys: ApiKeyStore,
+        db: Option<Database>,
+    ) -> SignatureVerifier {
+        SignatureVerifier {
+            config,
+            keys,
+            db,
+            nonces: Arc::default(),
+        }
+    }
+
+    pub async fn verify(
+        &self,
+        method: &str,
+        path: &str,
+        header: &SignatureHeader,
+        body_hash: &str,
+    ) -> Result<ApiKey, AuthError> {
+        let now = chrono::Utc::now().timestamp();
+        let valid_nonce = !header.nonce.is_empty()
+            && header.nonce.len() <= 64
+            && header
+                .nonce
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !self.config.enabled
+            || !valid_nonce
+            || header.timestamp.abs_diff(now) > self.config.window_secs
+        {
+            return Err(AuthError::Invalid);
+        }
+        // the bootstrap key has no secret to sign with
+        let Some(db) = &self.db else {
+            return Err(AuthError::Invalid);
+        };
+        let document = match self.keys.find(db, &header.key_id).await {
+            Ok(Some(document)) => document,
+            Ok(None) => return Err(AuthError::Invalid),
+            Err(error) => {
+                tracing::error!(%error, "cannot look up API key");
+                return Err(AuthError::Unavailable);
+            }
+        };
+        let bson_now = bson::DateTime::now();
+        if !document.is_active(bson_now) {
+            return Err(AuthError::Invalid);
+        }
+
+        let secret = self.keys.signing_secret(&document.hash);
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .expect("HMAC accepts keys of any size");
+        mac.update(
+            string_to_sign(method, path, header.timestamp, &header.nonce, body_hash).as_bytes(),
+        );
+        let signed = hex::decode(&header.signature)
+            .is_ok_and(|signature| mac.verify_slice(&signature).is_ok());
+        if !signed {
+            return Err(AuthError::Invalid);
+        }
+        // only once the signature holds, so that forged requests cannot
+        // use up the nonces of the key
+        self.claim_nonce(db, header, now).await?;
+        self.keys.touch(db, &document, bson_now);
+
+        let mut key = ApiKey::stored(&document);
+        key.scopes.retain(|scope| *scope != Scope::Admin);
+        Ok(key)
+    }
+
+    /// Reject a nonce seen before within the window.
+    async fn claim_nonce(
+        &self,
+        db: &Database,
+        header: &SignatureHeader,
+        now: i64,
+    ) -> Result<(), AuthError> {
+        let id = format!("{}:{}", header.key_id, header.nonce);
+        // later the timestamp is rejected anyway
+        let expires_at = header.timestamp + self.config.window_secs as i64;
+        {
+            let mut nonces = self.nonces.lock().unwrap();
+            if nonces.len() >= MAX_CACHED_NONCES {
+                nonces.retain(|_, expiry| *expiry > now);
+            }
+            if nonces.get(&id).is_some_and(|expiry| *expiry > now) {
+                tracing::info!(key = %header.key_id, "replayed signed request");
+                return Err(AuthError::Invalid);
+            }
+            nonces.insert(id.clone(), expires_at);
+        }
+
+        let expires_at = bson::DateTime::from_millis(expires_at * 1000);
+        match api_key::insert_request_nonce(db, &id, expires_at).await {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                tracing::info!(key = %header.key_id, "replayed signed request");
+                Err(AuthError::Invalid)
+            }
+            Err(error) => {
+                tracing::error!(%error, "cannot record request nonce");
+                Err(AuthError::Unavailable)
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use rocket::fairing::AdHoc;
+use rocket::figment::{providers::Env, Figment};
+use serde::{Deserialize, Deserializer};
+
+use crate::auth::{jwt::JwtConfig, mtls::MtlsConfig, oauth::OAuthConfig, signature::SigningConfig};
+use crate::db::{tenant::TenancyConfig, MongoConfig};
+use crate::fairings::{
+    compression::CompressionConfig, cors::CorsConfig, metrics::MetricsConfig,
+    security_headers::SecurityHeadersConfig,
+};
+use crate::grpc::GrpcConfig;
+use crate::health::HealthConfig;
+use crate::rate_limit::RateLimitConfig;
+use crate::telemetry::TelemetryConfig;
+use crate::tls::TlsConfig;
+use crate::webhooks::WebhookConfig;
+
+/// `[default.auth]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Optional bootstrap key granted every scope, next to the keys
+    /// of the `api_keys` collection.
+    #[serde(deserialize_with = "string")]
+    pub api_key: String,
+    /// Secret mixed into the stored key hashes.
+    pub pepper: String,
+    /// Seconds a key read from the database is trusted without reading it again.
+    pub cache_ttl_secs: u64,
+    /// Seconds a rotated key keeps working next to its replacement.
+    pub rotation_overlap_secs: u64,
+    /// Seconds a WebSocket opened without credentials has to send its auth
+    /// message before it is closed.
+    pub websocket_auth_timeout_secs: u64,
+    /// Bearer tokens accepted next to API keys.
+    pub jwt: JwtConfig,
+    /// Tokens issued by `/oauth/token`.
+    pub oauth: OAuthConfig,
+    /// Requests signed with the secret of an API key.
+    pub signing: SigningConfig,
+    /// Client certificates accepted in place of API keys.
+    pub mtls: MtlsConfig,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            api_key: String::new(),
+            pepper: String::new(),
+            cache_ttl_secs: 30,
+            rotation_overlap_secs: 86400,
+            websocket_auth_timeout_secs: 10,
+            jwt: JwtConfig::default(),
+            oauth: OAuthConfig::default(),
+            signing: SigningConfig::default(),
+            mtls: MtlsConfig::default(),
+        }
+    }
+}
+
+/// Environment variables holding only digits are parsed as numbers,
+/// take them back as they were written.
+pub fn string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        String(String),
+        Unsigned(u64),
+        Signed(i64),
+    }
+
+    Ok(match Raw::deserialize(deserializer)? {
+        Raw::String(value) => value,
+        Raw::Unsigned(value) => value.to_string(),
+        Raw::Signed(value) => value.to_string(),
+    })
+}
+
+/// Error of requests for a page before the first one.
+pub const PAGE_OUT_OF_RANGE: &str = "page cannot be less than 1";
+/// Error of requests for a page too far to be counted.
+pub const PAGE_TOO_LARGE: &str = "page is too large";
+
+/// Number of documents before `page` when pages hold `limit` documents.
+pub fn page_offset(page: i64, limit: i64) -> Result<u64, &'static str> {
+    if page < 1 {
+        return Err(PAGE_OUT_OF_RANGE);
+    }
+    (page - 1)
+        .checked_mul(limit)
+        .and_then(|skip| u64::try_from(skip).ok())
+        .ok_or(PAGE_TOO_LARGE)
+}
+
+/// `[default.pagination]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PaginationConfig {
+    /// Page size when the client does not ask for one.
+    pub default_limit: i64,
+    /// Larger page sizes are cut down to this.
+    pub max_limit: i64,
+}
+
+impl Default for PaginationConfig {
+    fn default() -> Self {
+        PaginationConfig {
+            default_limit: 12,
+            max_limit: 100,
+        }
+    }
+}
+
+impl PaginationConfig {
+    /// The page size to use for a requested `limit`.
+    pub fn limit(&self, limit: Option<i64>) -> i64 {
+        limit
+            .filter(|limit| *limit > 0)
+            .unwrap_or(self.default_limit)
+            .min(self.max_limit)
+    }
+}
+
+/// Every setting of the application, one field per `Rocket.toml` section.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AppConfig {
+    pub mongodb: MongoConfig,
+    pub auth: AuthConfig,
+    pub pagination: PaginationConfig,
+    pub cors: CorsConfig,
+    pub security_headers: SecurityHeadersConfig,
+    pub compression: CompressionConfig,
+    pub rate_limit: RateLimitConfig,
+    pub metrics: MetricsConfig,
+    pub telemetry: TelemetryConfig,
+    pub health: HealthConfig,
+    pub webhooks: WebhookConfig,
+    pub grpc: GrpcConfig,
+    pub tenancy: TenancyConfig,
+    /// Only the settings of this application, Rocket reads the others;
+    /// `None` without TLS, like in Rocket's configuration.
+    pub tls: Option<TlsConfig>,
+}
+
+impl AppConfig {
+    /// `Rocket.toml` for the selected profile, then the `MONGO_URI`,
+    /// `MONGO_DB_NAME` and `API_KEY` variables, then `APP_` variables
+    /// such as `APP_MONGODB__URI` or `APP_PAGINATION__MAX_LIMIT`.
+    /// Variables may come from a `.env` file.
+    pub fn figment(rocket_figment: &Figment) -> Figment {
+        let legacy = Env::raw()
+            .only(&["MONGO_URI", "MONGO_DB_NAME", "API_KEY"])
+            .map(|key| {
+                if key == "MONGO_URI" {
+                    "mongodb.uri".into()
+                } else if key == "MONGO_DB_NAME" {
+                    "mongodb.database".into()
+                } else {
+                    "auth.api_key".into()
+                }
+            })
+            .global();
+        rocket_figment
+            .clone()
+            .merge(legacy)
+            .merge(Env::prefixed("APP_").split("__").global())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.mongodb.uri.is_empty() || self.mongodb.database.is_empty() {
+            return Err("mongodb.uri and mongodb.database are required".into());
+        }
+        if self.auth.pepper.len() < 16 {
+            return Err("auth.pepper must be at least 16 characters".into());
+        }
+        if self.pagination.default_limit < 1
+            || self.pagination.default_limit > self.pagination.max_limit
+        {
+            return Err("pagination.default_limit must be between 1 and max_limit".into());
+        }
+        self.auth.jwt.validate()?;
+        self.auth.oauth.validate()?;
+        self.auth.signing.validate()?;
+        self.auth.mtls.validate()?;
+        self.cors.validate()?;
+        self.rate_limit.validate()?;
+        self.tenancy.validate()?;
+        self.telemetry.validate()
+    }
+}
+
+/// Extract and validate the configuration, aborting ignition when it is invalid.
+pub fn init() -> AdHoc {
+    AdHoc::try_on_ignite("Application configuration", |rocket| async {
+        let config = AppConfig::figment(rocket.figment())
+            .extract::<AppConfig>()
+            .map_err(|error| error.to_string())
+            .and_then(|config| config.validate().map(|()| config));
+        match config {
+            Ok(config) => Ok(rocket.manage(config)),
+            Err(error) => {
+                tracing::error!(%error, "invalid configuration");
+                Err(rocket)
+            }
+        }
+    })
+}
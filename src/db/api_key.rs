@@ -0,0 +1,205 @@
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    error::{ErrorKind, WriteFailure},
+    options::{FindOneAndUpdateOptions, FindOptions, IndexOptions, ReturnDocument},
+    Database, IndexModel,
+};
+use rocket::tokio::sync::OnceCell;
+
+use crate::auth::GeneratedKey;
+use crate::db::tenant::credentials_filter;
+use crate::models::api_key::{ApiKeyDocument, RequestNonceDocument, Role, Scope};
+
+/// Whether the unique index on `prefix` exists, checked once.
+static PREFIX_INDEX: OnceCell<()> = OnceCell::const_new();
+/// Whether the TTL index removing expired nonces exists, checked once.
+static NONCE_EXPIRY_INDEX: OnceCell<()> = OnceCell::const_new();
+
+async fn ensure_prefix_index(db: &Database) -> mongodb::error::Result<()> {
+    PREFIX_INDEX
+        .get_or_try_init(|| async {
+            let index = IndexModel::builder()
+                .keys(doc! {"prefix": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build();
+            db.collection::<ApiKeyDocument>("api_keys")
+                .create_index(index, None)
+                .await
+                .map(|_| ())
+        })
+        .await
+        .copied()
+}
+
+async fn ensure_nonce_expiry_index(db: &Database) -> mongodb::error::Result<()> {
+    NONCE_EXPIRY_INDEX
+        .get_or_try_init(|| async {
+            let index = IndexModel::builder()
+                .keys(doc! {"expiresAt": 1})
+                .options(
+                    IndexOptions::builder()
+                        .expire_after(std::time::Duration::ZERO)
+                        .build(),
+                )
+                .build();
+            db.collection::<RequestNonceDocument>("request_nonces")
+                .create_index(index, None)
+                .await
+                .map(|_| ())
+        })
+        .await
+        .copied()
+}
+
+pub async fn find_api_keys(
+    db: &Database,
+    tenant: Option<&str>,
+) -> mongodb::error::Result<Vec<ApiKeyDocument>> {
+    let collection = db.collection::<ApiKeyDocument>("api_keys");
+
+    let find_options = FindOptions::builder().sort(doc! {"createdAt": -1}).build();
+    collection
+        .find(credentials_filter(tenant, doc! {}), find_options)
+        .await?
+        .try_collect()
+        .await
+}
+
+pub async fn find_api_key_by_id(
+    db: &Database,
+    tenant: Option<&str>,
+    oid: ObjectId,
+) -> mongodb::error::Result<Option<ApiKeyDocument>> {
+    let collection = db.collection::<ApiKeyDocument>("api_keys");
+
+    collection
+        .find_one(credentials_filter(tenant, doc! {"_id": oid}), None)
+        .await
+}
+
+pub async fn find_api_key_by_prefix(
+    db: &Database,
+    prefix: &str,
+) -> mongodb::error::Result<Option<ApiKeyDocument>> {
+    ensure_prefix_index(db).await?;
+    let collection = db.collection::<ApiKeyDocument>("api_keys");
+
+    collection.find_one(doc! {"prefix": prefix}, None).await
+}
+
+pub async fn touch_api_key(
+    db: &Database,
+    oid: ObjectId,
+    now: DateTime,
+) -> mongodb::error::Result<()> {
+    let collection = db.collection::<ApiKeyDocument>("api_keys");
+
+    collection
+        .update_one(doc! {"_id": oid}, doc! {"$set": {"lastUsedAt": now}}, None)
+        .await
+        .map(|_| ())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn insert_api_key(
+    db: &Database,
+    name: &str,
+    owner: &str,
+    scopes: &[Scope],
+    roles: &[Role],
+    team: Option<&str>,
+    tenant: Option<&str>,
+    expires_at: Option<DateTime>,
+    generated: &GeneratedKey,
+) -> mongodb::error::Result<ApiKeyDocument> {
+    ensure_prefix_index(db).await?;
+    let collection = db.collection::<ApiKeyDocument>("api_keys");
+
+    let api_key_doc = ApiKeyDocument {
+        id: ObjectId::new(),
+        name: name.to_owned(),
+        owner: owner.to_owned(),
+        prefix: generated.prefix.clone(),
+        hash: generated.hash.clone(),
+        scopes: scopes.to_vec(),
+        roles: roles.to_vec(),
+        team: team.map(str::to_owned),
+        tenant: tenant.map(str::to_owned),
+        expires_at,
+        last_used_at: None,
+        revoked_at: None,
+        created_at: chrono::Utc::now(),
+    };
+    collection.insert_one(&api_key_doc, None).await?;
+
+    Ok(api_key_doc)
+}
+
+/// Bring the expiry of an active key forward to `expires_at`, never later.
+pub async fn expire_api_key(
+    db: &Database,
+    oid: ObjectId,
+    expires_at: DateTime,
+) -> mongodb::error::Result<Option<ApiKeyDocument>> {
+    let collection = db.collection::<ApiKeyDocument>("api_keys");
+    let find_one_and_update_options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+
+    collection
+        .find_one_and_update(
+            doc! {"_id": oid, "revokedAt": null},
+            vec![doc! {"$set": {"expiresAt": {"$min": [
+                {"$ifNull": ["$expiresAt", expires_at]},
+                expires_at,
+            ]}}}],
+            find_one_and_update_options,
+        )
+        .await
+}
+
+pub async fn revoke_api_key(
+    db: &Database,
+    tenant: Option<&str>,
+    oid: ObjectId,
+) -> mongodb::error::Result<Option<ApiKeyDocument>> {
+    let collection = db.collection::<ApiKeyDocument>("api_keys");
+    let find_one_and_update_options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+
+    collection
+        .find_one_and_update(
+            credentials_filter(tenant, doc! {"_id": oid, "revokedAt": null}),
+            doc! {"$set": {"revokedAt": DateTime::now()}},
+            find_one_and_update_options,
+        )
+        .await
+}
+
+/// Record the nonce `id` of a signed request until `expires_at`; `false`
+/// when it was already recorded, the request being a replay.
+pub async fn insert_request_nonce(
+    db: &Database,
+    id: &str,
+    expires_at: DateTime,
+) -> mongodb::error::Result<bool> {
+    ensure_nonce_expiry_index(db).await?;
+    let collection = db.collection::<RequestNonceDocument>("request_nonces");
+
+    let nonce = RequestNonceDocument {
+        id: id.to_owned(),
+        expires_at,
+    };
+    match collection.insert_one(nonce, None).await {
+        Ok(_) => Ok(true),
+        Err(error) => match *error.kind {
+            // duplicate key
+            ErrorKind::Write(WriteFailure::WriteError(ref failure)) if failure.code == 11000 => {
+                Ok(false)
+            }
+            _ => Err(error),
+        },
+    }
+}
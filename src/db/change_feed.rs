@@ -0,0 +1,198 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::stream::TryStreamExt;
+use mongodb::{
+    change_stream::event::OperationType,
+    options::{ChangeStreamOptions, FullDocumentType},
+    Database,
+};
+use rocket::fairing::AdHoc;
+use rocket::tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::db::tenant::Tenant;
+use crate::models::{
+    customer::{Customer, CustomerDocument},
+    event::{CustomerEvent, CustomerEventKind},
+};
+
+/// Number of events kept around for `Last-Event-ID` resumption.
+const HISTORY_SIZE: usize = 256;
+
+/// In-process feed of customer changes.
+///
+/// Events are fed either by the `db::customer` write functions or, when the
+/// deployment supports it, by a MongoDB change stream on the `customer`
+/// collection. Only one source is active at a time so subscribers never see
+/// the same change twice. Clones share the same feed.
+#[derive(Clone)]
+pub struct ChangeFeed {
+    /// Random prefix of the event ids, so that ids sent before a restart
+    /// are not mistaken for those of this feed.
+    epoch: Arc<str>,
+    sender: broadcast::Sender<CustomerEvent>,
+    history: Arc<Mutex<History>>,
+    change_stream: Arc<AtomicBool>,
+}
+
+struct History {
+    last_id: u64,
+    events: VecDeque<CustomerEvent>,
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(HISTORY_SIZE);
+        ChangeFeed {
+            epoch: Uuid::new_v4().simple().to_string()[..8].into(),
+            sender,
+            history: Arc::new(Mutex::new(History {
+                last_id: 0,
+                events: VecDeque::with_capacity(HISTORY_SIZE),
+            })),
+            change_stream: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl ChangeFeed {
+    /// Subscribe to events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<CustomerEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Events still in history published after the one with the id
+    /// `last_event_id`, none when another feed issued it, such as the one
+    /// running before a restart.
+    pub fn since(&self, last_event_id: &str) -> Vec<CustomerEvent> {
+        let Some(last_seq) = last_event_id
+            .split_once('-')
+            .filter(|(epoch, _)| *epoch == &*self.epoch)
+            .and_then(|(_, seq)| seq.parse::<u64>().ok())
+        else {
+            return Vec::new();
+        };
+        let history = self.history.lock().unwrap();
+        history
+            .events
+            .iter()
+            .filter(|event| event.seq > last_seq)
+            .cloned()
+            .collect()
+    }
+
+    /// Whether events are currently sourced from a MongoDB change stream.
+    pub fn is_change_stream(&self) -> bool {
+        self.change_stream.load(Ordering::Relaxed)
+    }
+
+    /// Record a change made by this process for `tenant`.
+    /// Ignored while a change stream is active, as it reports the same change,
+    /// unless the tenant has a database of its own, which is not watched.
+    pub fn record(
+        &self,
+        tenant: &Tenant,
+        kind: CustomerEventKind,
+        customer_id: String,
+        customer: Option<Customer>,
+    ) {
+        if !self.is_change_stream() || tenant.has_own_database() {
+            self.publish(kind, customer_id, customer, tenant.id().map(str::to_owned));
+        }
+    }
+
+    fn publish(
+        &self,
+        kind: CustomerEventKind,
+        customer_id: String,
+        customer: Option<Customer>,
+        tenant_id: Option<String>,
+    ) {
+        // assign the id and send under the lock so history and subscribers agree on order
+        let mut history = self.history.lock().unwrap();
+        history.last_id += 1;
+        let event = CustomerEvent {
+            id: format!("{}-{}", self.epoch, history.last_id),
+            seq: history.last_id,
+            kind,
+            customer_id,
+            customer,
+            tenant_id,
+        };
+        if history.events.len() == HISTORY_SIZE {
+            history.events.pop_front();
+        }
+        history.events.push_back(event.clone());
+        // an error only means there is no subscriber right now
+        let _ = self.sender.send(event);
+    }
+
+    /// Forward changes from a MongoDB change stream until it ends.
+    /// Returns an error right away when change streams are unsupported
+    /// (e.g. a standalone server), leaving the in-process source active.
+    async fn watch(&self, db: &Database) -> mongodb::error::Result<()> {
+        let collection = db.collection::<CustomerDocument>("customer");
+        let options = ChangeStreamOptions::builder()
+            .full_document(Some(FullDocumentType::UpdateLookup))
+            .build();
+
+        let mut stream = collection.watch(None, options).await?;
+        self.change_stream.store(true, Ordering::Relaxed);
+
+        let result = async {
+            while let Some(change) = stream.try_next().await? {
+                let kind = match change.operation_type {
+                    OperationType::Insert => CustomerEventKind::Created,
+                    OperationType::Update | OperationType::Replace => CustomerEventKind::Updated,
+                    OperationType::Delete => CustomerEventKind::Deleted,
+                    _ => continue,
+                };
+                let Some(customer_id) = change
+                    .document_key
+                    .as_ref()
+                    .and_then(|key| key.get_object_id("_id").ok())
+                else {
+                    continue;
+                };
+                let tenant_id = change
+                    .full_document
+                    .as_ref()
+                    .and_then(|customer_doc| customer_doc.tenant_id.clone());
+                let customer = change.full_document.map(Customer::from);
+                self.publish(kind, customer_id.to_string(), customer, tenant_id);
+            }
+            Ok(())
+        }
+        .await;
+
+        self.change_stream.store(false, Ordering::Relaxed);
+        result
+    }
+}
+
+pub fn init() -> AdHoc {
+    AdHoc::on_ignite("Customer change feed", |rocket| async {
+        rocket
+            .manage(ChangeFeed::default())
+            .attach(AdHoc::on_liftoff("Watching customer changes", |rocket| {
+                Box::pin(async move {
+                    let (Some(db), Some(feed)) =
+                        (rocket.state::<Database>(), rocket.state::<ChangeFeed>())
+                    else {
+                        return;
+                    };
+                    let (db, feed) = (db.clone(), feed.clone());
+                    rocket::tokio::spawn(async move {
+                        if let Err(error) = feed.watch(&db).await {
+                            tracing::warn!(
+                                %error,
+                                "change stream unavailable, using in-process feed"
+                            );
+                        }
+                    });
+                })
+            }))
+    })
+}
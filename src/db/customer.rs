@@ -1,135 +1,248 @@
-use crate::models::customer::{Customer, CustomerDocument, CustomerInput};
+use crate::db::{change_feed::ChangeFeed, outbox::OutboxSession, tenant::Tenant};
+use crate::models::{
+    customer::{Customer, CustomerDocument, CustomerInput, Ownership},
+    event::CustomerEventKind,
+};
+use crate::{metrics, telemetry};
 use chrono::Utc;
 use futures::stream::TryStreamExt;
 use mongodb::{
     bson::{doc, oid::ObjectId, DateTime, Document},
     options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
-    Database,
 };
 use rocket::serde::json::Json;
 
+// Every function is scoped by the `Tenant` and the `Ownership` of the caller:
+// customers out of scope are treated as if they did not exist.
+
 pub async fn find_customer(
-    db: &Database,
+    tenant: &Tenant,
+    ownership: &Ownership,
     limit: i64,
-    page: i64,
+    skip: u64,
 ) -> mongodb::error::Result<Vec<Customer>> {
-    let collection = db.collection::<CustomerDocument>("customer");
-
-    let find_options = FindOptions::builder()
-        .limit(limit)
-        .skip(u64::try_from((page - 1) * limit).unwrap())
-        .build();
-
-    let mut cursor = collection.find(None, find_options).await?;
-
-    let mut customers: Vec<Customer> = vec![];
-    while let Some(result) = cursor.try_next().await? {
-        let _id = result.id;
-        let name = result.name;
-        let created_at = result.created_at;
-        // transform ObjectId to String
-        let customer_json = Customer {
-            id: _id.to_string(),
-            name: name.to_string(),
-            created_at: created_at.to_string(),
-        };
-        customers.push(customer_json);
-    }
-
-    Ok(customers)
+    let _timer = metrics::time_db("find_customer");
+    telemetry::in_span("find_customer", async move {
+        let collection = tenant.db().collection::<CustomerDocument>("customer");
+
+        let find_options = FindOptions::builder().limit(limit).skip(skip).build();
+
+        let mut cursor = collection
+            .find(tenant.filter(ownership.filter(doc! {})), find_options)
+            .await?;
+
+        let mut customers: Vec<Customer> = vec![];
+        while let Some(result) = cursor.try_next().await? {
+            let _id = result.id;
+            let name = result.name;
+            let created_at = result.created_at;
+            // transform ObjectId to String
+            let customer_json = Customer {
+                id: _id.to_string(),
+                name: name.to_string(),
+                created_at: created_at.to_string(),
+                team: result.team,
+            };
+            customers.push(customer_json);
+        }
+
+        Ok(customers)
+    })
+    .await
 }
 
 pub async fn find_customer_by_id(
-    db: &Database,
+    tenant: &Tenant,
+    ownership: &Ownership,
     oid: ObjectId,
 ) -> mongodb::error::Result<Option<Customer>> {
-    let collection = db.collection::<CustomerDocument>("customer");
-
-    let Some(customer_doc) = collection.find_one(doc! {"_id":oid }, None).await? else {
-        return Ok(None);
-    };
+    let _timer = metrics::time_db("find_customer_by_id");
+    telemetry::in_span("find_customer_by_id", async move {
+        let collection = tenant.db().collection::<CustomerDocument>("customer");
+
+        let Some(customer_doc) = collection
+            .find_one(tenant.filter(ownership.filter(doc! {"_id":oid })), None)
+            .await?
+        else {
+            return Ok(None);
+        };
 
-    // transform ObjectId to String
-    let customer_json = Customer {
-        id: customer_doc.id.to_string(),
-        name: customer_doc.name.to_string(),
-        created_at: customer_doc.created_at.to_string(),
-    };
+        // transform ObjectId to String
+        let customer_json = Customer {
+            id: customer_doc.id.to_string(),
+            name: customer_doc.name.to_string(),
+            created_at: customer_doc.created_at.to_string(),
+            team: customer_doc.team,
+        };
 
-    Ok(Some(customer_json))
+        Ok(Some(customer_json))
+    })
+    .await
 }
 
 pub async fn insert_customer(
-    db: &Database,
+    tenant: &Tenant,
+    feed: &ChangeFeed,
+    ownership: &Ownership,
     input: Json<CustomerInput>,
-) -> mongodb::error::Result<String> {
-    let collection = db.collection::<Document>("customer");
-
-    let created_at = Utc::now();
-
-    let insert_one_result = collection
-        .insert_one(
-            doc! {"name": input.name.clone(), "createdAt": created_at},
-            None,
-        )
-        .await?;
+) -> mongodb::error::Result<Customer> {
+    let _timer = metrics::time_db("insert_customer");
+    telemetry::in_span("insert_customer", async move {
+        let collection = tenant.db().collection::<Document>("customer");
+
+        let created_at = Utc::now();
+        let oid = ObjectId::new();
+        let team = ownership.team_for(&input);
+        let customer_json = Customer {
+            id: oid.to_string(),
+            name: input.name.clone(),
+            created_at: created_at.to_string(),
+            team: team.clone(),
+        };
 
-    Ok(insert_one_result.inserted_id.to_string())
+        // the outbox event is committed together with the document
+        let mut session = OutboxSession::start(tenant.db()).await?;
+        collection
+            .insert_one_with_session(
+                tenant.filter(
+                    doc! {"_id": oid, "name": input.name.clone(), "createdAt": created_at, "team": team},
+                ),
+                None,
+                session.session(),
+            )
+            .await?;
+        session
+            .enqueue(
+                tenant.db(),
+                CustomerEventKind::Created,
+                &customer_json.id,
+                Some(&customer_json),
+                tenant.id(),
+            )
+            .await?;
+        session.commit().await?;
+
+        feed.record(
+            tenant,
+            CustomerEventKind::Created,
+            customer_json.id.clone(),
+            Some(customer_json.clone()),
+        );
+
+        Ok(customer_json)
+    })
+    .await
 }
 
 pub async fn update_customer_by_id(
-    db: &Database,
+    tenant: &Tenant,
+    feed: &ChangeFeed,
+    ownership: &Ownership,
     oid: ObjectId,
     input: Json<CustomerInput>,
 ) -> mongodb::error::Result<Option<Customer>> {
-    let collection = db.collection::<CustomerDocument>("customer");
-    let find_one_and_update_options = FindOneAndUpdateOptions::builder()
-        .return_document(ReturnDocument::After)
-        .build();
-
-    let created_at: DateTime = DateTime::now();
-
-    let Some(customer_doc) = collection
-        .find_one_and_update(
-            doc! {"_id":oid },
-            doc! {"name": input.name.clone(), "createdAt": created_at},
-            find_one_and_update_options,
-        )
-        .await?
-    else {
-        return Ok(None);
-    };
-
-    // transform ObjectId to String
-    let customer_json = Customer {
-        id: customer_doc.id.to_string(),
-        name: customer_doc.name.to_string(),
-        created_at: customer_doc.created_at.to_string(),
-    };
-
-    Ok(Some(customer_json))
+    let _timer = metrics::time_db("update_customer_by_id");
+    telemetry::in_span("update_customer_by_id", async move {
+        let collection = tenant.db().collection::<CustomerDocument>("customer");
+        let find_one_and_update_options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+
+        let created_at: DateTime = DateTime::now();
+
+        let mut session = OutboxSession::start(tenant.db()).await?;
+        let Some(customer_doc) = collection
+            .find_one_and_update_with_session(
+                tenant.filter(ownership.filter(doc! {"_id":oid })),
+                // the team and tenant of the customer are kept
+                doc! {"$set": {"name": input.name.clone(), "createdAt": created_at}},
+                find_one_and_update_options,
+                session.session(),
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        // transform ObjectId to String
+        let customer_json = Customer {
+            id: customer_doc.id.to_string(),
+            name: customer_doc.name.to_string(),
+            created_at: customer_doc.created_at.to_string(),
+            team: customer_doc.team,
+        };
+        session
+            .enqueue(
+                tenant.db(),
+                CustomerEventKind::Updated,
+                &customer_json.id,
+                Some(&customer_json),
+                tenant.id(),
+            )
+            .await?;
+        session.commit().await?;
+
+        feed.record(
+            tenant,
+            CustomerEventKind::Updated,
+            customer_json.id.clone(),
+            Some(customer_json.clone()),
+        );
+
+        Ok(Some(customer_json))
+    })
+    .await
 }
 
 pub async fn delete_customer_by_id(
-    db: &Database,
+    tenant: &Tenant,
+    feed: &ChangeFeed,
+    ownership: &Ownership,
     oid: ObjectId,
 ) -> mongodb::error::Result<Option<Customer>> {
-    let collection = db.collection::<CustomerDocument>("customer");
-
-    // if you just unwrap,, when there is no document it results in 500 error.
-    let Some(customer_doc) = collection
-        .find_one_and_delete(doc! {"_id":oid }, None)
-        .await?
-    else {
-        return Ok(None);
-    };
-
-    // transform ObjectId to String
-    let customer_json = Customer {
-        id: customer_doc.id.to_string(),
-        name: customer_doc.name.to_string(),
-        created_at: customer_doc.created_at.to_string(),
-    };
-
-    Ok(Some(customer_json))
+    let _timer = metrics::time_db("delete_customer_by_id");
+    telemetry::in_span("delete_customer_by_id", async move {
+        let collection = tenant.db().collection::<CustomerDocument>("customer");
+
+        // if you just unwrap,, when there is no document it results in 500 error.
+        let mut session = OutboxSession::start(tenant.db()).await?;
+        let Some(customer_doc) = collection
+            .find_one_and_delete_with_session(
+                tenant.filter(ownership.filter(doc! {"_id":oid })),
+                None,
+                session.session(),
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        // transform ObjectId to String
+        let customer_json = Customer {
+            id: customer_doc.id.to_string(),
+            name: customer_doc.name.to_string(),
+            created_at: customer_doc.created_at.to_string(),
+            team: customer_doc.team,
+        };
+        session
+            .enqueue(
+                tenant.db(),
+                CustomerEventKind::Deleted,
+                &customer_json.id,
+                Some(&customer_json),
+                tenant.id(),
+            )
+            .await?;
+        session.commit().await?;
+
+        feed.record(
+            tenant,
+            CustomerEventKind::Deleted,
+            customer_json.id.clone(),
+            Some(customer_json.clone()),
+        );
+
+        Ok(Some(customer_json))
+    })
+    .await
 }
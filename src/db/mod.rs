@@ -1,30 +1,242 @@
-// use mongodb::bson::{doc, Document};
-use mongodb::{options::ClientOptions, Client, Database};
+use mongodb::bson::{doc, Document};
+use mongodb::{options::ClientOptions, Client, Database, IndexModel};
 use rocket::fairing::AdHoc;
-use std::env;
+use rocket::tokio::{
+    select,
+    time::{sleep, timeout, Instant},
+};
+use serde::Deserialize;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use crate::config::AppConfig;
+use crate::request_guards::db_available::service_unavailable;
+use crate::telemetry::mongo::CommandTracer;
+
+pub mod api_key;
+pub mod change_feed;
 pub mod customer;
+pub mod oauth;
+pub mod outbox;
+pub mod rate_limit;
+pub mod tenant;
+pub mod webhook;
+
+/// `[default.mongodb]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct MongoConfig {
+    /// Connection string, `MONGO_URI` in the environment.
+    pub uri: String,
+    /// Database name, `MONGO_DB_NAME` in the environment.
+    #[serde(deserialize_with = "crate::config::string")]
+    pub database: String,
+    pub min_pool_size: Option<u32>,
+    pub max_pool_size: Option<u32>,
+    /// Milliseconds an operation waits for a suitable server.
+    pub server_selection_timeout_ms: u64,
+    /// Delay before the second connection attempt, doubled after each failure.
+    pub retry_initial_ms: u64,
+    /// Upper bound for the delay between attempts.
+    pub retry_max_ms: u64,
+    /// Seconds to keep trying at startup.
+    pub connect_deadline_secs: u64,
+    /// Start anyway once the deadline passed, answering 503 on data routes
+    /// until MongoDB is reachable.
+    pub degraded_start: bool,
+}
+
+impl Default for MongoConfig {
+    fn default() -> Self {
+        MongoConfig {
+            uri: String::new(),
+            database: String::new(),
+            min_pool_size: None,
+            max_pool_size: None,
+            server_selection_timeout_ms: 5000,
+            retry_initial_ms: 500,
+            retry_max_ms: 5000,
+            connect_deadline_secs: 30,
+            degraded_start: false,
+        }
+    }
+}
+
+impl MongoConfig {
+    fn backoff(&self, attempts: u32) -> Duration {
+        let delay = self
+            .retry_initial_ms
+            .saturating_mul(2u64.saturating_pow(attempts.saturating_sub(1)));
+        Duration::from_millis(delay.min(self.retry_max_ms))
+    }
+}
+
+#[derive(Debug)]
+pub enum ConnectError {
+    /// The connection string or options are invalid.
+    Options(mongodb::error::Error),
+    /// No server answered before the deadline.
+    Unreachable { attempts: u32, error: String },
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::Options(error) => write!(f, "invalid MongoDB options: {}", error),
+            ConnectError::Unreachable { attempts, error } => write!(
+                f,
+                "MongoDB unreachable after {} attempts: {}",
+                attempts, error
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+/// Whether MongoDB has answered since startup.
+#[derive(Clone)]
+pub struct DbStatus(Arc<AtomicBool>);
+
+impl DbStatus {
+    pub fn is_available(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set_available(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
 
 pub fn init() -> AdHoc {
-    AdHoc::on_ignite("Connecting to MongoDB", |rocket| async {
-        match connect().await {
-            Ok(database) => rocket.manage(database),
+    AdHoc::try_on_ignite("Connecting to MongoDB", |rocket| async {
+        let config = rocket
+            .state::<AppConfig>()
+            .map(|config| config.mongodb.clone())
+            .unwrap_or_default();
+        let database = match database(&config).await {
+            Ok(database) => database,
             Err(error) => {
-                panic!("Cannot connect to instance:: {:?}", error)
+                tracing::error!(%error, "cannot set up MongoDB");
+                return Err(rocket);
+            }
+        };
+
+        match connect(&database, &config).await {
+            Ok(()) => Ok(rocket
+                .manage(database)
+                .manage(DbStatus(Arc::new(AtomicBool::new(true))))),
+            Err(error) if config.degraded_start => {
+                tracing::warn!(%error, "starting without MongoDB");
+                Ok(rocket
+                    .manage(database)
+                    .manage(DbStatus(Arc::new(AtomicBool::new(false))))
+                    .register("/", catchers![service_unavailable])
+                    .attach(AdHoc::on_liftoff("Reconnecting to MongoDB", |rocket| {
+                        Box::pin(reconnect(rocket))
+                    })))
+            }
+            Err(error) => {
+                tracing::error!(%error, "cannot connect to MongoDB");
+                Err(rocket)
             }
         }
     })
 }
 
-async fn connect() -> mongodb::error::Result<Database> {
-    let mongo_uri = env::var("MONGO_URI").expect("MONGO_URI is not found.");
-    let mongo_db_name = env::var("MONGO_DB_NAME").expect("MONGO_DB_NAME is not found.");
+/// Round trip to the server, for health checks.
+/// Create `index` on `collection` of `db` unless `created` lists the
+/// database, for the collections found in the database of every tenant.
+pub async fn ensure_index(
+    created: &Mutex<Vec<String>>,
+    db: &Database,
+    collection: &str,
+    index: IndexModel,
+) -> mongodb::error::Result<()> {
+    if created.lock().unwrap().iter().any(|name| name == db.name()) {
+        return Ok(());
+    }
+    // creating an existing index is a no-op, so racing callers are harmless
+    db.collection::<Document>(collection)
+        .create_index(index, None)
+        .await?;
+    created.lock().unwrap().push(db.name().to_owned());
+    Ok(())
+}
+
+pub async fn ping(db: &Database) -> mongodb::error::Result<()> {
+    db.run_command(doc! {"ping": 1}, None).await.map(|_| ())
+}
+
+/// A handle on the configured database; no connection is made yet.
+async fn database(config: &MongoConfig) -> Result<Database, ConnectError> {
+    let mut client_options = ClientOptions::parse(&config.uri)
+        .await
+        .map_err(ConnectError::Options)?;
+    client_options.server_selection_timeout =
+        Some(Duration::from_millis(config.server_selection_timeout_ms));
+    client_options.min_pool_size = config.min_pool_size;
+    client_options.max_pool_size = config.max_pool_size;
+    client_options.command_event_handler = Some(Arc::new(CommandTracer::default()));
+    let client = Client::with_options(client_options).map_err(ConnectError::Options)?;
+
+    Ok(client.database(&config.database))
+}
+
+/// Ping until a server answers, with backoff, up to the configured deadline.
+async fn connect(db: &Database, config: &MongoConfig) -> Result<(), ConnectError> {
+    let deadline = Instant::now() + Duration::from_secs(config.connect_deadline_secs);
+    let mut attempts = 0;
+    let mut last_error = "no attempt before the deadline".to_string();
 
-    let client_options = ClientOptions::parse(mongo_uri).await?;
-    let client = Client::with_options(client_options)?;
-    let database = client.database(mongo_db_name.as_str());
+    while Instant::now() < deadline {
+        attempts += 1;
+        // an attempt never outlives the deadline
+        match timeout(deadline - Instant::now(), ping(db)).await {
+            Ok(Ok(())) => {
+                tracing::info!(database = %db.name(), attempts, "MongoDB connected");
+                return Ok(());
+            }
+            Ok(Err(error)) => last_error = error.to_string(),
+            Err(_) => last_error = "deadline reached".to_string(),
+        }
+        let delay = config.backoff(attempts);
+        tracing::warn!(attempts, error = %last_error, ?delay, "MongoDB connection failed");
+        sleep(delay.min(deadline.saturating_duration_since(Instant::now()))).await;
+    }
 
-    println!("MongoDB Connected!");
+    Err(ConnectError::Unreachable {
+        attempts,
+        error: last_error,
+    })
+}
 
-    Ok(database)
+/// Keep pinging in the background after a degraded start.
+async fn reconnect(rocket: &rocket::Rocket<rocket::Orbit>) {
+    let (Some(db), Some(status)) = (rocket.state::<Database>(), rocket.state::<DbStatus>()) else {
+        return;
+    };
+    let config = rocket
+        .state::<AppConfig>()
+        .map(|config| config.mongodb.clone())
+        .unwrap_or_default();
+    let (db, status, mut shutdown) = (db.clone(), status.clone(), rocket.shutdown());
+
+    rocket::tokio::spawn(async move {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            if ping(&db).await.is_ok() {
+                tracing::info!(database = %db.name(), attempts, "MongoDB connected");
+                status.set_available();
+                return;
+            }
+            select! {
+                _ = sleep(config.backoff(attempts)) => {}
+                _ = &mut shutdown => return,
+            }
+        }
+    });
 }
@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime},
+    options::{FindOneAndUpdateOptions, FindOptions, IndexOptions, ReturnDocument, UpdateOptions},
+    Database, IndexModel,
+};
+use rocket::tokio::sync::OnceCell;
+
+use crate::db::tenant::credentials_filter;
+use crate::models::api_key::Scope;
+use crate::models::oauth::{OAuthClientDocument, RevokedTokenDocument};
+
+/// Whether the unique index on `clientId` exists, checked once.
+static CLIENT_ID_INDEX: OnceCell<()> = OnceCell::const_new();
+/// Whether the TTL index removing expired revocations exists, checked once.
+static EXPIRY_INDEX: OnceCell<()> = OnceCell::const_new();
+
+async fn ensure_client_id_index(db: &Database) -> mongodb::error::Result<()> {
+    CLIENT_ID_INDEX
+        .get_or_try_init(|| async {
+            let index = IndexModel::builder()
+                .keys(doc! {"clientId": 1})
+                .options(IndexOptions::builder().unique(true).build())
+                .build();
+            db.collection::<OAuthClientDocument>("oauth_clients")
+                .create_index(index, None)
+                .await
+                .map(|_| ())
+        })
+        .await
+        .copied()
+}
+
+async fn ensure_expiry_index(db: &Database) -> mongodb::error::Result<()> {
+    EXPIRY_INDEX
+        .get_or_try_init(|| async {
+            let index = IndexModel::builder()
+                .keys(doc! {"expiresAt": 1})
+                .options(
+                    IndexOptions::builder()
+                        .expire_after(Duration::from_secs(0))
+                        .build(),
+                )
+                .build();
+            db.collection::<RevokedTokenDocument>("revoked_tokens")
+                .create_index(index, None)
+                .await
+                .map(|_| ())
+        })
+        .await
+        .copied()
+}
+
+pub async fn find_oauth_clients(
+    db: &Database,
+    tenant: Option<&str>,
+) -> mongodb::error::Result<Vec<OAuthClientDocument>> {
+    let collection = db.collection::<OAuthClientDocument>("oauth_clients");
+
+    let find_options = FindOptions::builder().sort(doc! {"createdAt": -1}).build();
+    collection
+        .find(credentials_filter(tenant, doc! {}), find_options)
+        .await?
+        .try_collect()
+        .await
+}
+
+pub async fn find_oauth_client_by_client_id(
+    db: &Database,
+    client_id: &str,
+) -> mongodb::error::Result<Option<OAuthClientDocument>> {
+    ensure_client_id_index(db).await?;
+    let collection = db.collection::<OAuthClientDocument>("oauth_clients");
+
+    collection
+        .find_one(doc! {"clientId": client_id}, None)
+        .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn insert_oauth_client(
+    db: &Database,
+    name: &str,
+    owner: &str,
+    scopes: &[Scope],
+    team: Option<&str>,
+    tenant: Option<&str>,
+    client_id: &str,
+    secret_hash: &str,
+) -> mongodb::error::Result<OAuthClientDocument> {
+    ensure_client_id_index(db).await?;
+    let collection = db.collection::<OAuthClientDocument>("oauth_clients");
+
+    let client_doc = OAuthClientDocument {
+        id: ObjectId::new(),
+        client_id: client_id.to_owned(),
+        name: name.to_owned(),
+        owner: owner.to_owned(),
+        secret_hash: secret_hash.to_owned(),
+        scopes: scopes.to_vec(),
+        team: team.map(str::to_owned),
+        tenant: tenant.map(str::to_owned),
+        revoked_at: None,
+        created_at: chrono::Utc::now(),
+    };
+    collection.insert_one(&client_doc, None).await?;
+
+    Ok(client_doc)
+}
+
+pub async fn revoke_oauth_client(
+    db: &Database,
+    tenant: Option<&str>,
+    oid: ObjectId,
+) -> mongodb::error::Result<Option<OAuthClientDocument>> {
+    let collection = db.collection::<OAuthClientDocument>("oauth_clients");
+    let find_one_and_update_options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+
+    collection
+        .find_one_and_update(
+            credentials_filter(tenant, doc! {"_id": oid, "revokedAt": null}),
+            doc! {"$set": {"revokedAt": DateTime::now()}},
+            find_one_and_update_options,
+        )
+        .await
+}
+
+/// Record the revocation of the token `jti`, forgotten once it has expired.
+pub async fn insert_revoked_token(
+    db: &Database,
+    jti: &str,
+    expires_at: DateTime,
+) -> mongodb::error::Result<()> {
+    ensure_expiry_index(db).await?;
+    let collection = db.collection::<RevokedTokenDocument>("revoked_tokens");
+
+    // revoking twice is not an error
+    collection
+        .update_one(
+            doc! {"_id": jti},
+            doc! {"$set": {"expiresAt": expires_at}},
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await
+        .map(|_| ())
+}
+
+pub async fn is_token_revoked(db: &Database, jti: &str) -> mongodb::error::Result<bool> {
+    let collection = db.collection::<RevokedTokenDocument>("revoked_tokens");
+
+    Ok(collection
+        .find_one(doc! {"_id": jti}, None)
+        .await?
+        .is_some())
+}
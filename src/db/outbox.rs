@@ -0,0 +1,151 @@
+use std::sync::Mutex;
+
+use chrono::{Duration, Utc};
+use mongodb::{
+    bson::{doc, Document},
+    options::{FindOneAndUpdateOptions, IndexOptions},
+    ClientSession, Database, IndexModel,
+};
+use rocket::tokio::sync::OnceCell;
+
+use crate::db::{ensure_index, tenant::Tenant};
+use crate::models::{customer::Customer, event::CustomerEventKind, webhook::OutboxEventDocument};
+
+/// Whether the deployment supports multi-document transactions,
+/// checked once on the first write.
+static TRANSACTIONS: OnceCell<bool> = OnceCell::const_new();
+/// Databases where the TTL index removing dispatched events exists.
+static DISPATCHED_EXPIRY_INDEX: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+async fn supports_transactions(db: &Database) -> mongodb::error::Result<bool> {
+    TRANSACTIONS
+        .get_or_try_init(|| async {
+            // transactions need a replica set member or a mongos router
+            let hello = db.run_command(doc! {"hello": 1}, None).await?;
+            Ok(hello.contains_key("setName") || hello.get_str("msg") == Ok("isdbgrid"))
+        })
+        .await
+        .copied()
+}
+
+/// Session for a customer write and the outbox events it produces.
+///
+/// On replica sets both are committed in one transaction; on a standalone
+/// server the writes are applied one after the other.
+pub struct OutboxSession {
+    session: ClientSession,
+    transaction: bool,
+}
+
+impl OutboxSession {
+    pub async fn start(db: &Database) -> mongodb::error::Result<OutboxSession> {
+        let mut session = db
+            .collection::<Document>("outbox")
+            .client()
+            .start_session(None)
+            .await?;
+        let transaction = supports_transactions(db).await?;
+        if transaction {
+            session.start_transaction(None).await?;
+        }
+        Ok(OutboxSession {
+            session,
+            transaction,
+        })
+    }
+
+    pub fn session(&mut self) -> &mut ClientSession {
+        &mut self.session
+    }
+
+    /// Add a customer event to the outbox as part of this session.
+    pub async fn enqueue(
+        &mut self,
+        db: &Database,
+        kind: CustomerEventKind,
+        customer_id: &str,
+        customer: Option<&Customer>,
+        tenant_id: Option<&str>,
+    ) -> mongodb::error::Result<()> {
+        let collection = db.collection::<Document>("outbox");
+        let customer = match customer {
+            Some(customer) => Some(bson::to_bson(customer)?),
+            None => None,
+        };
+
+        collection
+            .insert_one_with_session(
+                doc! {
+                    "kind": kind.as_str(),
+                    "customerId": customer_id,
+                    "customer": customer,
+                    "tenantId": tenant_id,
+                    "createdAt": Utc::now(),
+                    "dispatched": false,
+                },
+                None,
+                &mut self.session,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn commit(mut self) -> mongodb::error::Result<()> {
+        if self.transaction {
+            self.session.commit_transaction().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Claim the oldest undispatched outbox event of `tenant` for `lease`, so
+/// that other instances skip it while it is fanned out to webhook deliveries.
+pub async fn claim_pending_event(
+    tenant: &Tenant,
+    lease: Duration,
+) -> mongodb::error::Result<Option<OutboxEventDocument>> {
+    let collection = tenant.db().collection::<OutboxEventDocument>("outbox");
+    let now = Utc::now();
+    let options = FindOneAndUpdateOptions::builder()
+        .sort(doc! {"createdAt": 1})
+        .build();
+
+    collection
+        .find_one_and_update(
+            tenant.filter(doc! {
+                "dispatched": false,
+                "$or": [
+                    {"claimedUntil": {"$exists": false}},
+                    {"claimedUntil": {"$lt": now}},
+                ],
+            }),
+            doc! {"$set": {"claimedUntil": now + lease}},
+            options,
+        )
+        .await
+}
+
+/// Mark `event` as fanned out, the event being removed `retention` later.
+pub async fn mark_dispatched(
+    db: &Database,
+    event: &OutboxEventDocument,
+    retention: std::time::Duration,
+) -> mongodb::error::Result<()> {
+    let index = IndexModel::builder()
+        .keys(doc! {"dispatchedAt": 1})
+        .options(IndexOptions::builder().expire_after(retention).build())
+        .build();
+    ensure_index(&DISPATCHED_EXPIRY_INDEX, db, "outbox", index).await?;
+    let collection = db.collection::<Document>("outbox");
+
+    collection
+        .update_one(
+            doc! {"_id": event.id},
+            doc! {"$set": {"dispatched": true, "dispatchedAt": Utc::now()}},
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
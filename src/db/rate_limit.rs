@@ -0,0 +1,78 @@
+use mongodb::{
+    bson::{doc, DateTime, Document},
+    options::{FindOneAndUpdateOptions, IndexOptions, ReturnDocument},
+    Database, IndexModel,
+};
+use rocket::tokio::sync::OnceCell;
+
+use crate::rate_limit::{Decision, Limit};
+
+/// Whether the TTL index removing idle buckets exists, checked once.
+static TTL_INDEX: OnceCell<()> = OnceCell::const_new();
+
+async fn ensure_ttl_index(db: &Database) -> mongodb::error::Result<()> {
+    TTL_INDEX
+        .get_or_try_init(|| async {
+            let index = IndexModel::builder()
+                .keys(doc! {"expiresAt": 1})
+                .options(
+                    IndexOptions::builder()
+                        .expire_after(std::time::Duration::ZERO)
+                        .build(),
+                )
+                .build();
+            db.collection::<Document>("rate_limit")
+                .create_index(index, None)
+                .await
+                .map(|_| ())
+        })
+        .await
+        .copied()
+}
+
+/// Take a token from the bucket `key` in one atomic update,
+/// so that every instance shares the same buckets.
+pub async fn take_token(
+    db: &Database,
+    key: &str,
+    limit: Limit,
+) -> mongodb::error::Result<Decision> {
+    ensure_ttl_index(db).await?;
+    let collection = db.collection::<Document>("rate_limit");
+    let now = DateTime::now();
+    let capacity = f64::from(limit.capacity);
+    let full_after_ms = (capacity / limit.per_second * 1000.0) as i64;
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+
+    let pipeline = vec![
+        // refill for the time elapsed since the last request
+        doc! {"$set": {
+            "tokens": {"$min": [capacity, {"$add": [
+                {"$ifNull": ["$tokens", capacity]},
+                {"$multiply": [
+                    {"$divide": [{"$subtract": [now, {"$ifNull": ["$updatedAt", now]}]}, 1000]},
+                    limit.per_second,
+                ]},
+            ]}]},
+            "updatedAt": now,
+        }},
+        doc! {"$set": {
+            "allowed": {"$gte": ["$tokens", 1]},
+            "tokens": {"$cond": [{"$gte": ["$tokens", 1]}, {"$subtract": ["$tokens", 1]}, "$tokens"]},
+            "expiresAt": DateTime::from_millis(now.timestamp_millis() + full_after_ms),
+        }},
+    ];
+    let bucket = collection
+        .find_one_and_update(doc! {"_id": key}, pipeline, options)
+        .await?
+        .unwrap_or_default();
+
+    Ok(Decision::new(
+        limit,
+        bucket.get_f64("tokens").unwrap_or(0.0),
+        bucket.get_bool("allowed").unwrap_or(true),
+    ))
+}
@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use mongodb::{bson::Document, Database};
+use rocket::fairing::AdHoc;
+use serde::Deserialize;
+
+use crate::config::AppConfig;
+use crate::models::api_key::Scope;
+use crate::rate_limit::RouteLimits;
+use crate::request_guards::{principal::Principal, tenant::bad_request};
+
+/// How the customers of tenants are kept apart.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Isolation {
+    /// One collection, every document and query carrying a `tenantId`.
+    Field,
+    /// A database per tenant.
+    Database,
+}
+
+/// `[default.tenancy.tenants.<id>]` sections of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct TenantConfig {
+    /// Database of the tenant with `database` isolation,
+    /// `<mongodb.database>_<id>` when absent.
+    pub database: Option<String>,
+    /// Limits overriding those of `[default.rate_limit]` for the tenant,
+    /// which then gets buckets of its own.
+    pub rate_limit: RouteLimits,
+}
+
+/// `[default.tenancy]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TenancyConfig {
+    /// Serve several tenants from one deployment.
+    pub enabled: bool,
+    pub isolation: Isolation,
+    /// Tenant of requests that name none, rejected when empty.
+    pub default_tenant: String,
+    /// The tenants served, keyed by the id sent in `X-Tenant-Id`.
+    pub tenants: HashMap<String, TenantConfig>,
+}
+
+impl Default for TenancyConfig {
+    fn default() -> Self {
+        TenancyConfig {
+            enabled: false,
+            isolation: Isolation::Field,
+            default_tenant: String::new(),
+            tenants: HashMap::new(),
+        }
+    }
+}
+
+impl TenancyConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if self.tenants.is_empty() {
+            return Err("tenancy.tenants needs at least one tenant".into());
+        }
+        // ids end up in database names and bucket keys
+        let valid = |id: &str| {
+            !id.is_empty()
+                && id.len() <= 32
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        };
+        if let Some(id) = self.tenants.keys().find(|id| !valid(id)) {
+            return Err(format!(
+                "tenant id {:?} must be 1 to 32 letters, digits, - or _",
+                id
+            ));
+        }
+        if !self.default_tenant.is_empty() && !self.tenants.contains_key(&self.default_tenant) {
+            return Err("tenancy.default_tenant must be one of tenancy.tenants".into());
+        }
+        self.tenants
+            .values()
+            .try_for_each(|tenant| tenant.rate_limit.validate())
+    }
+}
+
+/// Why no tenant could be resolved for a request.
+#[derive(Debug, Clone)]
+pub enum TenantError {
+    /// Neither the credentials nor `X-Tenant-Id` name a tenant.
+    Missing,
+    /// The tenant is not one of `tenancy.tenants`.
+    Unknown(String),
+    /// `X-Tenant-Id` names another tenant than the credentials.
+    Mismatch,
+    /// Credentials bound to no tenant, without the `admin` scope, name
+    /// another tenant than `tenancy.default_tenant`.
+    Unbound,
+}
+
+impl fmt::Display for TenantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TenantError::Missing => write!(f, "The X-Tenant-Id header is required."),
+            TenantError::Unknown(id) => write!(f, "Unknown tenant {}.", id),
+            TenantError::Mismatch => write!(f, "The credentials belong to another tenant."),
+            TenantError::Unbound => write!(
+                f,
+                "The credentials are not bound to a tenant and cannot choose one."
+            ),
+        }
+    }
+}
+
+/// The tenant a request is served for, with the database holding its
+/// customers.
+#[derive(Debug, Clone)]
+pub struct Tenant {
+    /// `None` when tenancy is disabled.
+    id: Option<String>,
+    db: Database,
+    /// The database of `mongodb.database`.
+    shared: Database,
+    isolation: Isolation,
+    limits: RouteLimits,
+}
+
+impl Tenant {
+    /// The only tenant of a deployment without tenancy.
+    pub fn single(db: Database) -> Tenant {
+        Tenant {
+            id: None,
+            shared: db.clone(),
+            db,
+            isolation: Isolation::Field,
+            limits: RouteLimits::default(),
+        }
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// The database holding the customers of the tenant, with their outbox
+    /// events and webhooks.
+    pub fn db(&self) -> &Database {
+        &self.db
+    }
+
+    /// Whether the customers live outside the shared database, the one
+    /// watched by the change feed.
+    pub fn has_own_database(&self) -> bool {
+        self.db.name() != self.shared.name()
+    }
+
+    /// Rate limits overriding the defaults for the tenant.
+    pub fn limits(&self) -> &RouteLimits {
+        &self.limits
+    }
+
+    /// `filter` narrowed to the documents of the tenant, and `tenantId` of
+    /// the documents it creates.
+    pub fn filter(&self, mut filter: Document) -> Document {
+        if let (Some(id), Isolation::Field) = (&self.id, self.isolation) {
+            filter.insert("tenantId", id.clone());
+        }
+        filter
+    }
+}
+
+/// `filter` narrowed to the API keys or OAuth2 clients bound to `tenant`,
+/// every one of them when `None`, for admins bound to a tenant.
+pub fn credentials_filter(tenant: Option<&str>, mut filter: Document) -> Document {
+    if let Some(tenant) = tenant {
+        filter.insert("tenant", tenant);
+    }
+    filter
+}
+
+/// The tenants of `tenancy.tenants`, each with a `Database` handle created
+/// once. Clones share the same handles.
+#[derive(Clone)]
+pub struct Tenants {
+    config: TenancyConfig,
+    db: Database,
+    databases: Arc<Mutex<HashMap<String, Database>>>,
+}
+
+impl Tenants {
+    pub fn new(config: TenancyConfig, db: Database) -> Tenants {
+        Tenants {
+            config,
+            db,
+            databases: Arc::default(),
+        }
+    }
+
+    /// The tenant of the credentials, else the one named in `X-Tenant-Id`,
+    /// else `tenancy.default_tenant`. Credentials bound to a tenant cannot
+    /// name another one, and only admins bound to none may name any, the
+    /// others being served for the default tenant.
+    pub fn resolve(
+        &self,
+        principal: Option<&Principal>,
+        header: Option<&str>,
+    ) -> Result<Tenant, TenantError> {
+        if !self.config.enabled {
+            return Ok(Tenant::single(self.db.clone()));
+        }
+        let default = Some(self.config.default_tenant.as_str()).filter(|id| !id.is_empty());
+        let bound = principal.and_then(Principal::tenant);
+        // requests without credentials are turned away by the routes
+        let chooses = principal.is_none_or(|principal| principal.has_scope(Scope::Admin));
+        let id = match (bound, header) {
+            (Some(bound), Some(header)) if bound != header => return Err(TenantError::Mismatch),
+            (Some(id), _) => id,
+            (None, Some(id)) if chooses || default == Some(id) => id,
+            (None, None) => match default {
+                Some(id) => id,
+                None if chooses => return Err(TenantError::Missing),
+                None => return Err(TenantError::Unbound),
+            },
+            (None, Some(_)) => return Err(TenantError::Unbound),
+        };
+        self.tenant(id)
+    }
+
+    /// Every tenant served, for the work done outside of requests.
+    pub fn all(&self) -> Vec<Tenant> {
+        if !self.config.enabled {
+            return vec![Tenant::single(self.db.clone())];
+        }
+        self.config
+            .tenants
+            .keys()
+            .filter_map(|id| self.tenant(id).ok())
+            .collect()
+    }
+
+    fn tenant(&self, id: &str) -> Result<Tenant, TenantError> {
+        let Some(config) = self.config.tenants.get(id) else {
+            return Err(TenantError::Unknown(id.to_owned()));
+        };
+
+        let db = match self.config.isolation {
+            Isolation::Field => self.db.clone(),
+            Isolation::Database => self.database(id, config),
+        };
+        Ok(Tenant {
+            id: Some(id.to_owned()),
+            db,
+            shared: self.db.clone(),
+            isolation: self.config.isolation,
+            limits: config.rate_limit.clone(),
+        })
+    }
+
+    /// Whether `id` names one of `tenancy.tenants`, any id doing without
+    /// tenancy.
+    pub fn exists(&self, id: &str) -> bool {
+        !self.config.enabled || self.config.tenants.contains_key(id)
+    }
+
+    fn database(&self, id: &str, config: &TenantConfig) -> Database {
+        self.databases
+            .lock()
+            .unwrap()
+            .entry(id.to_owned())
+            .or_insert_with(|| {
+                let name = config
+                    .database
+                    .clone()
+                    .unwrap_or_else(|| format!("{}_{}", self.db.name(), id));
+                // handles of one client share its connection pool
+                self.db
+                    .collection::<Document>("customer")
+                    .client()
+                    .database(&name)
+            })
+            .clone()
+    }
+}
+
+/// Manage the `Tenants`, so it has to be attached after `db::init`, and
+/// answer 400 with `MyError`, telling why no tenant was resolved.
+pub fn init() -> AdHoc {
+    AdHoc::on_ignite("Tenants", |rocket| async {
+        let Some(db) = rocket.state::<Database>().cloned() else {
+            return rocket;
+        };
+        let config = rocket
+            .state::<AppConfig>()
+            .map(|config| config.tenancy.clone())
+            .unwrap_or_default();
+        rocket
+            .manage(Tenants::new(config, db))
+            .register("/", catchers![bad_request])
+    })
+}
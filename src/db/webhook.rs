@@ -0,0 +1,244 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    options::{FindOneAndUpdateOptions, IndexOptions, ReturnDocument, UpdateOptions},
+    Database, IndexModel,
+};
+use rocket::serde::json::Json;
+
+use crate::db::{ensure_index, tenant::Tenant};
+use crate::models::{
+    event::CustomerEventKind,
+    webhook::{Webhook, WebhookDeliveryDocument, WebhookDocument, WebhookInput},
+};
+
+/// Databases where the unique index on the event and webhook of deliveries
+/// exists.
+static DELIVERY_INDEX: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Webhooks live next to the customers of their tenant, and are scoped by
+// the `Tenant` like them.
+
+pub async fn find_webhooks(tenant: &Tenant) -> mongodb::error::Result<Vec<Webhook>> {
+    let collection = tenant.db().collection::<WebhookDocument>("webhook");
+
+    let mut cursor = collection.find(tenant.filter(doc! {}), None).await?;
+
+    let mut webhooks: Vec<Webhook> = vec![];
+    while let Some(result) = cursor.try_next().await? {
+        webhooks.push(Webhook::from(result));
+    }
+
+    Ok(webhooks)
+}
+
+pub async fn find_webhook_by_id(
+    tenant: &Tenant,
+    oid: ObjectId,
+) -> mongodb::error::Result<Option<WebhookDocument>> {
+    let collection = tenant.db().collection::<WebhookDocument>("webhook");
+
+    collection
+        .find_one(tenant.filter(doc! {"_id": oid}), None)
+        .await
+}
+
+/// Active webhooks of `tenant_id` interested in `kind` events.
+pub async fn find_subscribed_webhooks(
+    db: &Database,
+    kind: CustomerEventKind,
+    tenant_id: Option<&str>,
+) -> mongodb::error::Result<Vec<WebhookDocument>> {
+    let collection = db.collection::<WebhookDocument>("webhook");
+
+    // `null` also matches the webhooks of deployments without tenancy
+    let filter = doc! {
+        "active": true,
+        "tenantId": tenant_id,
+        "$or": [{"events": {"$size": 0}}, {"events": kind.as_str()}],
+    };
+    collection.find(filter, None).await?.try_collect().await
+}
+
+pub async fn insert_webhook(
+    tenant: &Tenant,
+    input: Json<WebhookInput>,
+) -> mongodb::error::Result<String> {
+    let collection = tenant.db().collection::<Document>("webhook");
+
+    let created_at = Utc::now();
+    let events: Vec<&str> = input.events.iter().map(|kind| kind.as_str()).collect();
+
+    let insert_one_result = collection
+        .insert_one(
+            doc! {
+                "url": input.url.clone(),
+                "events": events,
+                "secret": input.secret.clone(),
+                "active": input.active,
+                "tenantId": tenant.id(),
+                "createdAt": created_at,
+            },
+            None,
+        )
+        .await?;
+
+    Ok(insert_one_result.inserted_id.to_string())
+}
+
+pub async fn update_webhook_by_id(
+    tenant: &Tenant,
+    oid: ObjectId,
+    input: Json<WebhookInput>,
+) -> mongodb::error::Result<Option<Webhook>> {
+    let collection = tenant.db().collection::<WebhookDocument>("webhook");
+    let find_one_and_update_options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+
+    let events: Vec<&str> = input.events.iter().map(|kind| kind.as_str()).collect();
+
+    let webhook_doc = collection
+        .find_one_and_update(
+            tenant.filter(doc! {"_id": oid}),
+            doc! {"$set": {
+                "url": input.url.clone(),
+                "events": events,
+                "secret": input.secret.clone(),
+                "active": input.active,
+            }},
+            find_one_and_update_options,
+        )
+        .await?;
+
+    Ok(webhook_doc.map(Webhook::from))
+}
+
+pub async fn delete_webhook_by_id(
+    tenant: &Tenant,
+    oid: ObjectId,
+) -> mongodb::error::Result<Option<Webhook>> {
+    let collection = tenant.db().collection::<WebhookDocument>("webhook");
+
+    let webhook_doc = collection
+        .find_one_and_delete(tenant.filter(doc! {"_id": oid}), None)
+        .await?;
+
+    Ok(webhook_doc.map(Webhook::from))
+}
+
+/// Queue the delivery of the outbox event `event_id` to `webhook_id`, once
+/// even when the event is fanned out again after a crash.
+pub async fn insert_delivery(
+    tenant: &Tenant,
+    event_id: ObjectId,
+    webhook_id: ObjectId,
+    kind: CustomerEventKind,
+    payload: &str,
+) -> mongodb::error::Result<()> {
+    let index = IndexModel::builder()
+        .keys(doc! {"eventId": 1, "webhookId": 1})
+        .options(IndexOptions::builder().unique(true).build())
+        .build();
+    ensure_index(&DELIVERY_INDEX, tenant.db(), "webhook_delivery", index).await?;
+    let collection = tenant.db().collection::<Document>("webhook_delivery");
+
+    collection
+        .update_one(
+            tenant.filter(doc! {"eventId": event_id, "webhookId": webhook_id}),
+            doc! {"$setOnInsert": {
+                "kind": kind.as_str(),
+                "payload": payload,
+                "attempts": 0,
+                "lastError": null,
+                "nextAttemptAt": Utc::now(),
+            }},
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Claim a delivery that is due by pushing its next attempt `lease` ahead,
+/// so that other instances skip it while it is being sent.
+pub async fn claim_due_delivery(
+    tenant: &Tenant,
+    lease: Duration,
+) -> mongodb::error::Result<Option<WebhookDeliveryDocument>> {
+    let collection = tenant
+        .db()
+        .collection::<WebhookDeliveryDocument>("webhook_delivery");
+    let now = Utc::now();
+    let options = FindOneAndUpdateOptions::builder()
+        .sort(doc! {"nextAttemptAt": 1})
+        .build();
+
+    collection
+        .find_one_and_update(
+            tenant.filter(doc! {"nextAttemptAt": {"$lte": now}}),
+            doc! {"$set": {"nextAttemptAt": now + lease}},
+            options,
+        )
+        .await
+}
+
+pub async fn delete_delivery(db: &Database, oid: ObjectId) -> mongodb::error::Result<()> {
+    let collection = db.collection::<Document>("webhook_delivery");
+
+    collection.delete_one(doc! {"_id": oid}, None).await?;
+
+    Ok(())
+}
+
+pub async fn reschedule_delivery(
+    db: &Database,
+    oid: ObjectId,
+    error: &str,
+    next_attempt_at: DateTime<Utc>,
+) -> mongodb::error::Result<()> {
+    let collection = db.collection::<Document>("webhook_delivery");
+
+    collection
+        .update_one(
+            doc! {"_id": oid},
+            doc! {
+                "$inc": {"attempts": 1},
+                "$set": {"lastError": error, "nextAttemptAt": next_attempt_at},
+            },
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Move a delivery that will not be retried anymore to `webhook_dead_letter`.
+pub async fn dead_letter_delivery(
+    db: &Database,
+    delivery: &WebhookDeliveryDocument,
+    error: &str,
+) -> mongodb::error::Result<()> {
+    let collection = db.collection::<Document>("webhook_dead_letter");
+
+    collection
+        .insert_one(
+            doc! {
+                "_id": delivery.id,
+                "eventId": delivery.event_id,
+                "webhookId": delivery.webhook_id,
+                "kind": delivery.kind.as_str(),
+                "payload": delivery.payload.clone(),
+                "attempts": delivery.attempts + 1,
+                "lastError": error,
+                "failedAt": Utc::now(),
+            },
+            None,
+        )
+        .await?;
+
+    delete_delivery(db, delivery.id).await
+}
@@ -0,0 +1,112 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    okapi::{
+        self,
+        openapi3::{MediaType, RefOr, Responses},
+    },
+    response::OpenApiResponderInner,
+    OpenApiError,
+};
+
+/// Error of the `/oauth` endpoints, in the format of RFC 6749 section 5.2
+/// rather than `MyError`, as OAuth2 clients expect.
+#[derive(Debug, serde::Serialize, schemars::JsonSchema)]
+pub struct OAuthError {
+    /// `invalid_request`, `invalid_client`, `unsupported_grant_type`,
+    /// `invalid_scope` or `temporarily_unavailable`
+    pub error: String,
+    pub error_description: Option<String>,
+    #[serde(skip)]
+    status: u16,
+}
+
+impl OAuthError {
+    fn build(status: u16, error: &str, description: Option<&str>) -> OAuthError {
+        OAuthError {
+            error: error.to_owned(),
+            error_description: description.map(str::to_owned),
+            status,
+        }
+    }
+
+    pub fn invalid_request(description: &str) -> OAuthError {
+        OAuthError::build(400, "invalid_request", Some(description))
+    }
+
+    /// Unknown client, wrong secret or revoked client, told apart in logs only.
+    pub fn invalid_client() -> OAuthError {
+        OAuthError::build(401, "invalid_client", Some("Client authentication failed."))
+    }
+
+    pub fn unsupported_grant_type() -> OAuthError {
+        OAuthError::build(
+            400,
+            "unsupported_grant_type",
+            Some("Only the client_credentials grant is supported."),
+        )
+    }
+
+    pub fn invalid_scope(description: &str) -> OAuthError {
+        OAuthError::build(400, "invalid_scope", Some(description))
+    }
+
+    pub fn temporarily_unavailable() -> OAuthError {
+        OAuthError::build(
+            503,
+            "temporarily_unavailable",
+            Some("Clients cannot be looked up, retry later."),
+        )
+    }
+}
+
+impl<'r> rocket::response::Responder<'r, 'static> for OAuthError {
+    fn respond_to(self, _req: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        let body = serde_json::to_string(&self).unwrap();
+        let mut response = rocket::Response::build();
+        response
+            .sized_body(body.len(), std::io::Cursor::new(body))
+            .header(ContentType::JSON)
+            .header(Header::new("Cache-Control", "no-store"))
+            .status(Status::new(self.status));
+        if self.status == 401 {
+            response.header(Header::new("WWW-Authenticate", "Basic realm=\"oauth\""));
+        }
+        response.ok()
+    }
+}
+
+impl OpenApiResponderInner for OAuthError {
+    fn responses(gen: &mut OpenApiGenerator) -> Result<Responses, OpenApiError> {
+        let schema = gen.json_schema::<OAuthError>();
+        let response = |description: &str| {
+            RefOr::Object(okapi::openapi3::Response {
+                description: description.to_owned(),
+                content: okapi::map! {
+                    "application/json".to_owned() => MediaType {
+                        schema: Some(schema.clone()),
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            })
+        };
+        Ok(Responses {
+            responses: okapi::map! {
+                "400".to_owned() => response("\
+                # 400 Bad Request\n\
+                The request is malformed, uses another grant or asks for scopes the client lacks. \
+                "),
+                "401".to_owned() => response("\
+                # 401 Unauthorized\n\
+                The client could not be authenticated. \
+                "),
+                "503".to_owned() => response("\
+                # 503 Service Unavailable\n\
+                The database is not reachable yet, retry later. \
+                "),
+            },
+            ..Default::default()
+        })
+    }
+}
@@ -2,12 +2,15 @@ use rocket_okapi::{
     gen::OpenApiGenerator,
     okapi::{
         self,
-        openapi3::{MediaType, Responses},
+        openapi3::{Header, Object, ParameterValue, RefOr, Responses},
     },
     response::OpenApiResponderInner,
     OpenApiError,
 };
 
+use crate::fairings::request_log::RequestId;
+use crate::formats::negotiated::{media_types, Format};
+
 /// error type
 #[derive(Debug, serde::Serialize, schemars::JsonSchema)]
 pub struct ErrorContent {
@@ -17,6 +20,9 @@ pub struct ErrorContent {
     reason: String,
     // Description for an error if any
     description: Option<String>,
+    // Id of the failed request, as sent back in `X-Request-Id`
+    #[serde(rename = "requestId", skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
 /// Error messages returned to user
@@ -31,6 +37,10 @@ impl MyError {
         let reason = match code {
             400 => "Bad Request".to_string(),
             401 => "Unauthorized".to_string(),
+            403 => "Forbidden".to_string(),
+            404 => "Not Found".to_string(),
+            429 => "Too Many Requests".to_string(),
+            503 => "Service Unavailable".to_string(),
             _ => "Error".to_string(),
         };
         MyError {
@@ -38,6 +48,7 @@ impl MyError {
                 code,
                 reason,
                 description,
+                request_id: None,
             },
         }
     }
@@ -52,12 +63,7 @@ pub fn bad_request_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Resp
         The request given is wrongly formatted or data was missing. \
         "
         .to_owned(),
-        content: okapi::map! {
-            "application/json".to_owned() => MediaType {
-                schema: Some(schema),
-                ..Default::default()
-            }
-        },
+        content: media_types(schema),
         ..Default::default()
     }
 }
@@ -70,23 +76,96 @@ pub fn unauthorized_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Res
         The authentication given was incorrect or insufficient. \
         "
         .to_owned(),
-        content: okapi::map! {
-            "application/json".to_owned() => MediaType {
-                schema: Some(schema),
-                ..Default::default()
-            }
+        content: media_types(schema),
+        ..Default::default()
+    }
+}
+
+pub fn forbidden_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Response {
+    let schema = gen.json_schema::<MyError>();
+    okapi::openapi3::Response {
+        description: "\
+        # 403 Forbidden\n\
+        The credentials are valid but lack a scope required by this route. \
+        "
+        .to_owned(),
+        content: media_types(schema),
+        ..Default::default()
+    }
+}
+
+pub fn not_found_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Response {
+    let schema = gen.json_schema::<MyError>();
+    okapi::openapi3::Response {
+        description: "\
+        # 404 Not Found\n\
+        No document with this id is visible to the credentials given. \
+        "
+        .to_owned(),
+        content: media_types(schema),
+        ..Default::default()
+    }
+}
+
+pub fn service_unavailable_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Response {
+    let schema = gen.json_schema::<MyError>();
+    okapi::openapi3::Response {
+        description: "\
+        # 503 Service Unavailable\n\
+        The database is not reachable yet, retry later. \
+        "
+        .to_owned(),
+        content: media_types(schema),
+        ..Default::default()
+    }
+}
+
+pub fn too_many_requests_response(gen: &mut OpenApiGenerator) -> okapi::openapi3::Response {
+    let schema = gen.json_schema::<MyError>();
+    let seconds = gen.json_schema::<u64>();
+    let header = |description: &str| {
+        RefOr::Object(Header {
+            description: Some(description.to_owned()),
+            required: false,
+            deprecated: false,
+            allow_empty_value: false,
+            value: ParameterValue::Schema {
+                style: None,
+                explode: None,
+                allow_reserved: false,
+                schema: seconds.clone(),
+                example: None,
+                examples: None,
+            },
+            extensions: Object::default(),
+        })
+    };
+    okapi::openapi3::Response {
+        description: "\
+        # 429 Too Many Requests\n\
+        The rate limit of the caller was exceeded, retry after `Retry-After` seconds. \
+        "
+        .to_owned(),
+        content: media_types(schema),
+        headers: okapi::map! {
+            "Retry-After".to_owned() => header("Seconds to wait before retrying"),
+            "RateLimit-Limit".to_owned() => header("Requests allowed in a burst"),
+            "RateLimit-Remaining".to_owned() => header("Requests left in the current burst"),
+            "RateLimit-Reset".to_owned() => header("Seconds until the limit is fully restored"),
         },
         ..Default::default()
     }
 }
 
 impl<'r> rocket::response::Responder<'r, 'static> for MyError {
-    fn respond_to(self, _: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
-        // Convert object to json
-        let body = serde_json::to_string(&self).unwrap();
+    fn respond_to(mut self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        self.error.request_id = RequestId::of(req);
+        // Convert object to the format asked for in `Accept`, json by default
+        let format = Format::accepted(req);
+        let body = format.serialize(&self).unwrap();
         rocket::Response::build()
             .sized_body(body.len(), std::io::Cursor::new(body))
-            .header(rocket::http::ContentType::JSON)
+            .header(format.content_type())
             .status(rocket::http::Status::new(self.error.code))
             .ok()
     }
@@ -94,10 +173,10 @@ impl<'r> rocket::response::Responder<'r, 'static> for MyError {
 
 impl OpenApiResponderInner for MyError {
     fn responses(gen: &mut OpenApiGenerator) -> Result<Responses, OpenApiError> {
-        use rocket_okapi::okapi::openapi3::RefOr;
         Ok(Responses {
             responses: okapi::map! {
                 "400".to_owned() => RefOr::Object(bad_request_response(gen)),
+                "404".to_owned() => RefOr::Object(not_found_response(gen)),
                 // Note: 401 is already declared for ApiKey. so this is not essential.
                 // "401".to_owned() => RefOr::Object(unauthorized_response(gen)),
             },
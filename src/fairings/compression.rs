@@ -0,0 +1,183 @@
+use std::io::Cursor;
+
+use async_compression::tokio::bufread::{BrotliEncoder, GzipEncoder, ZstdEncoder};
+use async_compression::Level;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+use rocket::tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+use rocket::{Build, Request, Response, Rocket};
+use serde::Deserialize;
+
+use crate::config::AppConfig;
+
+/// `[default.compression]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    /// Bodies smaller than this many bytes are sent as is.
+    pub min_size: usize,
+    /// Encodings offered, in order of preference: `br`, `zstd` and/or `gzip`.
+    pub encodings: Vec<String>,
+    /// Content types worth compressing, matched by prefix.
+    /// Already compressed types (images, archives...) should not be listed.
+    pub types: Vec<String>,
+    pub gzip_level: i32,
+    pub brotli_level: i32,
+    pub zstd_level: i32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            enabled: true,
+            min_size: 1024,
+            encodings: vec!["br".into(), "zstd".into(), "gzip".into()],
+            types: vec![
+                "text/".into(),
+                "application/json".into(),
+                "application/javascript".into(),
+                "application/xml".into(),
+                "application/msgpack".into(),
+                "application/cbor".into(),
+                "image/svg+xml".into(),
+            ],
+            gzip_level: 6,
+            brotli_level: 4,
+            zstd_level: 3,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// The preferred encoding among those the client accepts with a non-zero q-value.
+    fn negotiate(&self, accept_encoding: &str) -> Option<&str> {
+        let accepted: Vec<(&str, f32)> = accept_encoding
+            .split(',')
+            .filter_map(|value| {
+                let mut parts = value.split(';');
+                let coding = parts.next()?.trim();
+                let q = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                Some((coding, q))
+            })
+            .collect();
+
+        self.encodings.iter().map(String::as_str).find(|encoding| {
+            let q = accepted
+                .iter()
+                .find(|(coding, _)| coding.eq_ignore_ascii_case(encoding))
+                .or_else(|| accepted.iter().find(|(coding, _)| *coding == "*"))
+                .map_or(0.0, |(_, q)| *q);
+            q > 0.0
+        })
+    }
+
+    fn compressible(&self, content_type: &str) -> bool {
+        // event streams must reach the client as soon as each event is written
+        !content_type.starts_with("text/event-stream")
+            && self
+                .types
+                .iter()
+                .any(|t| content_type.starts_with(t.as_str()))
+    }
+
+    fn encoder<'r, R>(&self, encoding: &str, body: R) -> Box<dyn AsyncRead + Send + Unpin + 'r>
+    where
+        R: AsyncRead + Send + Unpin + 'r,
+    {
+        let body = BufReader::new(body);
+        match encoding {
+            "br" => Box::new(BrotliEncoder::with_quality(
+                body,
+                Level::Precise(self.brotli_level),
+            )),
+            "zstd" => Box::new(ZstdEncoder::with_quality(
+                body,
+                Level::Precise(self.zstd_level),
+            )),
+            _ => Box::new(GzipEncoder::with_quality(
+                body,
+                Level::Precise(self.gzip_level),
+            )),
+        }
+    }
+}
+
+/// Compress response bodies with gzip, brotli or zstd according to `Accept-Encoding`.
+pub struct Compression;
+
+#[rocket::async_trait]
+impl Fairing for Compression {
+    fn info(&self) -> Info {
+        Info {
+            name: "Compress responses",
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        let config = rocket
+            .state::<AppConfig>()
+            .map(|config| config.compression.clone())
+            .unwrap_or_default();
+        Ok(rocket.manage(config))
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(config) = request.rocket().state::<CompressionConfig>() else {
+            return;
+        };
+        if !config.enabled
+            || response.status() == Status::NoContent
+            || response.headers().contains("Content-Encoding")
+            || response.body().is_none()
+        {
+            return;
+        }
+        match response.content_type() {
+            Some(content_type) if config.compressible(&content_type.to_string()) => {}
+            _ => return,
+        }
+
+        // caches must keep one copy per encoding, whether compressed or not
+        response.adjoin_header(Header::new("Vary", "Accept-Encoding"));
+
+        let Some(encoding) = request
+            .headers()
+            .get_one("Accept-Encoding")
+            .and_then(|accept_encoding| config.negotiate(accept_encoding))
+        else {
+            return;
+        };
+        let encoding = encoding.to_owned();
+
+        match response.body().preset_size() {
+            Some(size) if size < config.min_size => {}
+            Some(_) => {
+                // compress in memory to keep a Content-Length
+                let Ok(body) = response.body_mut().to_bytes().await else {
+                    return;
+                };
+                let mut compressed = vec![];
+                let encoded = config
+                    .encoder(&encoding, &body[..])
+                    .read_to_end(&mut compressed)
+                    .await;
+                if encoded.is_err() {
+                    response.set_sized_body(body.len(), Cursor::new(body));
+                    return;
+                }
+                response.set_sized_body(compressed.len(), Cursor::new(compressed));
+                response.set_header(Header::new("Content-Encoding", encoding));
+            }
+            None => {
+                let body = response.body_mut().take();
+                response.set_streamed_body(config.encoder(&encoding, body));
+                response.set_header(Header::new("Content-Encoding", encoding));
+            }
+        }
+    }
+}
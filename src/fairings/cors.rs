@@ -1,6 +1,115 @@
 use rocket::fairing::{Fairing, Info, Kind};
-use rocket::http::Header;
-use rocket::{Request, Response};
+use rocket::http::{Header, Method, Status};
+use rocket::{Build, Request, Response, Rocket};
+use serde::Deserialize;
+
+use crate::config::AppConfig;
+
+/// `[default.cors]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Origins allowed to call the API, e.g. `https://app.example.com`,
+    /// `https://*.example.com` for any subdomain, or `*` for any origin
+    /// without credentials.
+    pub allowed_origins: Vec<String>,
+    /// Request headers allowed in preflights, `*` to allow whatever is asked for.
+    pub allowed_headers: Vec<String>,
+    /// Response headers readable by the browser.
+    pub exposed_headers: Vec<String>,
+    pub allow_credentials: bool,
+    /// Seconds a preflight response may be cached by the browser.
+    pub max_age: u32,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: vec!["*".into()],
+            allowed_headers: vec!["*".into()],
+            exposed_headers: vec!["X-Request-Id".into()],
+            allow_credentials: false,
+            max_age: 3600,
+        }
+    }
+}
+
+impl CorsConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.allow_credentials && self.allowed_origins.iter().any(|origin| origin == "*") {
+            return Err("cors.allowed_origins cannot be `*` with cors.allow_credentials".into());
+        }
+        Ok(())
+    }
+
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|allowed| {
+            if allowed == origin {
+                return true;
+            }
+            // any site could read credentialed responses
+            if allowed == "*" {
+                return !self.allow_credentials;
+            }
+            // `https://*.example.com` matches `https://api.example.com`
+            let Some((scheme, domain)) = allowed.split_once("*.") else {
+                return false;
+            };
+            origin
+                .strip_prefix(scheme)
+                .and_then(|host| host.strip_suffix(domain))
+                .and_then(|subdomain| subdomain.strip_suffix('.'))
+                .is_some_and(|subdomain| !subdomain.is_empty() && !subdomain.contains(['/', ':']))
+        })
+    }
+
+    fn allowed_origin(&self, origin: &str) -> String {
+        if self.allowed_origins == ["*"] && !self.allow_credentials {
+            "*".to_owned()
+        } else {
+            origin.to_owned()
+        }
+    }
+}
+
+/// Whether a mounted route path such as `/customer/<id>` matches `path`.
+fn route_matches(route_path: &str, path: &str) -> bool {
+    let mut route_segments = route_path.split('/').filter(|s| !s.is_empty());
+    let mut segments = path.split('/').filter(|s| !s.is_empty());
+    loop {
+        match (route_segments.next(), segments.next()) {
+            (Some(route_segment), _) if route_segment.ends_with("..>") => return true,
+            (Some(route_segment), Some(segment)) => {
+                let dynamic = route_segment.starts_with('<') && route_segment.ends_with('>');
+                if !dynamic && route_segment != segment {
+                    return false;
+                }
+            }
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+/// Methods of the mounted routes matching `path`, for `Access-Control-Allow-Methods`.
+fn allowed_methods(request: &Request<'_>, path: &str) -> Vec<Method> {
+    let mut methods: Vec<Method> = request
+        .rocket()
+        .routes()
+        .filter(|route| route.method != Method::Options && route_matches(route.uri.path(), path))
+        .map(|route| route.method)
+        .collect();
+    methods.sort_by_key(|method| method.as_str());
+    methods.dedup();
+    methods
+}
+
+/// Answer CORS preflight requests for every path.
+/// The `Cors` fairing adds the headers, or leaves them out to deny the request.
+#[options("/<_..>")]
+pub fn preflight() -> Status {
+    Status::NoContent
+}
 
 pub struct Cors;
 
@@ -9,17 +118,81 @@ impl Fairing for Cors {
     fn info(&self) -> Info {
         Info {
             name: "Add CORS headers to responses",
-            kind: Kind::Response,
+            kind: Kind::Ignite | Kind::Response,
         }
     }
 
-    async fn on_response<'r>(&self, _request: &'r Request<'_>, response: &mut Response<'r>) {
-        response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        let config = rocket
+            .state::<AppConfig>()
+            .map(|config| config.cors.clone())
+            .unwrap_or_default();
+        Ok(rocket.manage(config).mount("/", routes![preflight]))
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(config) = request.rocket().state::<CorsConfig>() else {
+            return;
+        };
+        // the answer depends on the origin unless every origin gets `*`
+        response.adjoin_header(Header::new("Vary", "Origin"));
+
+        let Some(origin) = request.headers().get_one("Origin") else {
+            return;
+        };
+        if !config.allows_origin(origin) {
+            return;
+        }
+
+        let preflight_method = request
+            .headers()
+            .get_one("Access-Control-Request-Method")
+            .filter(|_| request.method() == Method::Options);
+        if let Some(requested_method) = preflight_method {
+            let methods = allowed_methods(request, request.uri().path().as_str());
+            if !methods
+                .iter()
+                .any(|method| method.as_str().eq_ignore_ascii_case(requested_method))
+            {
+                return;
+            }
+            let methods: Vec<&str> = methods.iter().map(|method| method.as_str()).collect();
+            response.set_header(Header::new(
+                "Access-Control-Allow-Methods",
+                methods.join(", "),
+            ));
+
+            let allowed_headers = if config.allowed_headers == ["*"] {
+                request
+                    .headers()
+                    .get_one("Access-Control-Request-Headers")
+                    .unwrap_or_default()
+                    .to_owned()
+            } else {
+                config.allowed_headers.join(", ")
+            };
+            if !allowed_headers.is_empty() {
+                response.set_header(Header::new("Access-Control-Allow-Headers", allowed_headers));
+            }
+            response.set_header(Header::new(
+                "Access-Control-Max-Age",
+                config.max_age.to_string(),
+            ));
+            response.adjoin_header(Header::new("Vary", "Access-Control-Request-Method"));
+            response.adjoin_header(Header::new("Vary", "Access-Control-Request-Headers"));
+        } else if !config.exposed_headers.is_empty() {
+            response.set_header(Header::new(
+                "Access-Control-Expose-Headers",
+                config.exposed_headers.join(", "),
+            ));
+        }
+
         response.set_header(Header::new(
-            "Access-Control-Allow-Methods",
-            "POST, GET, PATCH, OPTIONS",
+            "Access-Control-Allow-Origin",
+            config.allowed_origin(origin),
         ));
-        response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
-        response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        if config.allow_credentials {
+            response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+        }
     }
 }
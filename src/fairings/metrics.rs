@@ -0,0 +1,104 @@
+use std::time::Instant;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::ContentType;
+use rocket::{Build, Data, Request, Response, Rocket, State};
+use serde::Deserialize;
+
+use crate::config::AppConfig;
+use crate::errors::response::MyError;
+use crate::metrics::{self, HTTP_REQUESTS, HTTP_REQUESTS_IN_FLIGHT, HTTP_REQUEST_DURATION};
+use crate::request_guards::{basic::AuthError, principal::Principal};
+
+/// `[default.metrics]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    /// Require the `x-api-key` header to scrape `/metrics`.
+    pub require_api_key: bool,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            enabled: true,
+            require_api_key: false,
+        }
+    }
+}
+
+/// Prometheus scrape endpoint, in the text exposition format.
+#[get("/metrics")]
+pub fn scrape(
+    config: &State<MetricsConfig>,
+    principal: Result<Principal, AuthError>,
+) -> Result<(ContentType, String), MyError> {
+    if config.require_api_key && principal.is_err() {
+        return Err(MyError::build(
+            401,
+            Some("Invalid API key or bearer token.".to_string()),
+        ));
+    }
+    let content_type = ContentType::new("text", "plain").with_params(("version", "0.0.4"));
+    Ok((content_type, metrics::render()))
+}
+
+/// When the request was received, kept in request-local state.
+struct RequestStart(Instant);
+
+/// Record Prometheus metrics for every request and serve them on `/metrics`.
+pub struct Metrics;
+
+#[rocket::async_trait]
+impl Fairing for Metrics {
+    fn info(&self) -> Info {
+        Info {
+            name: "Prometheus request metrics",
+            kind: Kind::Ignite | Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        let config = rocket
+            .state::<AppConfig>()
+            .map(|config| config.metrics.clone())
+            .unwrap_or_default();
+        if !config.enabled {
+            return Ok(rocket);
+        }
+        Ok(rocket.manage(config).mount("/", routes![scrape]))
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        // probes would drown the request metrics
+        if request.rocket().state::<MetricsConfig>().is_none()
+            || request.uri().path().starts_with("/health/")
+        {
+            return;
+        }
+        request.local_cache(|| Some(RequestStart(Instant::now())));
+        HTTP_REQUESTS_IN_FLIGHT.inc();
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(start) = request.local_cache(|| None::<RequestStart>) else {
+            return;
+        };
+        HTTP_REQUESTS_IN_FLIGHT.dec();
+
+        let method = request.method().as_str();
+        // labelled by route rather than path so that ids do not add series
+        let route = request
+            .route()
+            .map_or("unmatched", |route| route.uri.as_str());
+        let status = format!("{}xx", response.status().code / 100);
+
+        HTTP_REQUESTS
+            .with_label_values(&[method, route, &status])
+            .inc();
+        HTTP_REQUEST_DURATION
+            .with_label_values(&[method, route])
+            .observe(start.0.elapsed().as_secs_f64());
+    }
+}
@@ -1,2 +1,7 @@
+pub mod compression;
 pub mod cors;
-pub mod counter;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_log;
+pub mod security_headers;
+pub mod telemetry;
@@ -0,0 +1,65 @@
+use mongodb::Database;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Build, Request, Response, Rocket};
+
+use crate::config::AppConfig;
+use crate::errors::response::MyError;
+use crate::rate_limit::{RateLimiter, StoreKind};
+use crate::request_guards::rate_limit::RateLimit;
+
+#[catch(429)]
+pub fn too_many_requests() -> MyError {
+    MyError::build(429, Some("Rate limit exceeded, retry later.".to_string()))
+}
+
+/// Set up the `RateLimit` guard's buckets and add `RateLimit-*` headers,
+/// plus `Retry-After` once a client is limited.
+pub struct RateLimiting;
+
+#[rocket::async_trait]
+impl Fairing for RateLimiting {
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate limiting",
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        let config = rocket
+            .state::<AppConfig>()
+            .map(|config| config.rate_limit.clone())
+            .unwrap_or_default();
+        if !config.enabled {
+            return Ok(rocket);
+        }
+
+        let limiter = match (config.store, rocket.state::<Database>()) {
+            (StoreKind::Memory, _) => RateLimiter::memory(config),
+            (StoreKind::Mongo, Some(db)) => RateLimiter::mongo(config, db.clone()),
+            (StoreKind::Mongo, None) => {
+                tracing::error!("the mongo rate limit store needs a database");
+                return Err(rocket);
+            }
+        };
+        Ok(rocket
+            .manage(limiter)
+            .register("/", catchers![too_many_requests]))
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(decision) = RateLimit::decision(request) else {
+            return;
+        };
+        response.set_header(Header::new("RateLimit-Limit", decision.limit.to_string()));
+        response.set_header(Header::new(
+            "RateLimit-Remaining",
+            decision.remaining.to_string(),
+        ));
+        response.set_header(Header::new("RateLimit-Reset", decision.reset.to_string()));
+        if !decision.allowed {
+            response.set_header(Header::new("Retry-After", decision.retry_after.to_string()));
+        }
+    }
+}
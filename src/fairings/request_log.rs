@@ -0,0 +1,98 @@
+use std::time::Instant;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Build, Data, Request, Response, Rocket};
+use tracing_subscriber::EnvFilter;
+
+use crate::request_guards::principal::Principal;
+
+/// Id of the request, taken from `X-Request-Id` or generated,
+/// kept in request-local state.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub const HEADER: &'static str = "X-Request-Id";
+
+    /// Ids sent by clients are kept if they are short and printable.
+    fn accepts(id: &str) -> bool {
+        !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic())
+    }
+
+    /// The id of `request`, once the `RequestLog` fairing has seen it.
+    pub fn of(request: &Request<'_>) -> Option<String> {
+        request
+            .local_cache(|| None::<RequestId>)
+            .as_ref()
+            .map(|id| id.0.clone())
+    }
+}
+
+/// When the request was received.
+struct RequestStart(Instant);
+
+/// Assign an id to every request, echo it in `X-Request-Id`
+/// and write one JSON log line per request.
+pub struct RequestLog;
+
+#[rocket::async_trait]
+impl Fairing for RequestLog {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ids and JSON request logs",
+            kind: Kind::Ignite | Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        // `RUST_LOG` filters the logs, `info` by default; set only once per process
+        let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+        let _ = tracing_subscriber::fmt()
+            .json()
+            .with_env_filter(filter)
+            .with_current_span(false)
+            .try_init();
+        Ok(rocket)
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let id = request
+            .headers()
+            .get_one(RequestId::HEADER)
+            .filter(|id| RequestId::accepts(id))
+            .map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_owned);
+        request.local_cache(|| Some(RequestId(id)));
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(request_id) = RequestId::of(request) else {
+            return;
+        };
+        response.set_header(Header::new(RequestId::HEADER, request_id.clone()));
+
+        let latency = request
+            .local_cache(|| RequestStart(Instant::now()))
+            .0
+            .elapsed();
+        let route = request.route().map(|route| route.uri.as_str());
+        // set by the `Principal` guard when the request carried valid credentials
+        let principal = request
+            .local_cache(|| None::<Principal>)
+            .as_ref()
+            .map(Principal::identity);
+
+        tracing::info!(
+            target: "request",
+            request_id = %request_id,
+            method = %request.method(),
+            path = %request.uri().path(),
+            route,
+            status = response.status().code,
+            latency_ms = latency.as_secs_f64() * 1000.0,
+            principal,
+            "request completed"
+        );
+    }
+}
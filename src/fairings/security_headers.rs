@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Build, Request, Response, Rocket};
+use serde::Deserialize;
+
+use crate::config::AppConfig;
+
+/// `[default.security_headers]` section of `Rocket.toml`. An empty value
+/// leaves its header out.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SecurityHeadersConfig {
+    pub enabled: bool,
+    /// `Strict-Transport-Security`, also sent over plain HTTP for the TLS
+    /// proxy in front, browsers ignoring it there.
+    pub hsts: String,
+    /// `X-Content-Type-Options`
+    pub content_type_options: String,
+    /// `Referrer-Policy`
+    pub referrer_policy: String,
+    /// `X-Frame-Options`
+    pub frame_options: String,
+    /// `Content-Security-Policy` of the API responses.
+    pub content_security_policy: String,
+    /// `Content-Security-Policy` of the pages under a path, the longest path
+    /// matching the request winning, for the Swagger UI and GraphiQL.
+    pub path_policies: HashMap<String, String>,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        SecurityHeadersConfig {
+            enabled: true,
+            hsts: "max-age=31536000; includeSubDomains".into(),
+            content_type_options: "nosniff".into(),
+            referrer_policy: "no-referrer".into(),
+            frame_options: "DENY".into(),
+            content_security_policy: "default-src 'none'; frame-ancestors 'none'".into(),
+            path_policies: HashMap::from([
+                (
+                    "/api-docs".into(),
+                    "default-src 'self'; style-src 'self' 'unsafe-inline'; \
+                     img-src 'self' data:; frame-ancestors 'none'"
+                        .into(),
+                ),
+                (
+                    "/graphiql".into(),
+                    "default-src 'self'; script-src 'self' 'unsafe-inline' unpkg.com; \
+                     style-src 'self' 'unsafe-inline' unpkg.com; img-src 'self' data:; \
+                     font-src 'self' data: unpkg.com; connect-src 'self' ws: wss:; \
+                     frame-ancestors 'none'"
+                        .into(),
+                ),
+            ]),
+        }
+    }
+}
+
+impl SecurityHeadersConfig {
+    /// The policy of the longest of `path_policies` that `path` is under,
+    /// the default policy otherwise.
+    fn content_security_policy(&self, path: &str) -> &str {
+        self.path_policies
+            .iter()
+            .filter(|(prefix, _)| {
+                let prefix = prefix.trim_end_matches('/');
+                path.strip_prefix(prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(&self.content_security_policy, |(_, policy)| policy)
+    }
+}
+
+/// Set the security headers on every response, replacing those of Rocket's
+/// `Shield`.
+pub struct SecurityHeaders;
+
+#[rocket::async_trait]
+impl Fairing for SecurityHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "Add security headers to responses",
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        let config = rocket
+            .state::<AppConfig>()
+            .map(|config| config.security_headers.clone())
+            .unwrap_or_default();
+        Ok(rocket.manage(config))
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(config) = request.rocket().state::<SecurityHeadersConfig>() else {
+            return;
+        };
+        if !config.enabled {
+            return;
+        }
+
+        let policy = config.content_security_policy(request.uri().path().as_str());
+        let headers = [
+            ("Strict-Transport-Security", &config.hsts),
+            ("X-Content-Type-Options", &config.content_type_options),
+            ("Referrer-Policy", &config.referrer_policy),
+            ("X-Frame-Options", &config.frame_options),
+        ];
+        for (name, value) in headers
+            .into_iter()
+            .map(|(name, value)| (name, value.as_str()))
+            .chain([("Content-Security-Policy", policy)])
+        {
+            if value.is_empty() {
+                response.remove_header(name);
+            } else {
+                response.set_header(Header::new(name, value.to_owned()));
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::trace::{SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::HeaderMap;
+use rocket::{Build, Data, Orbit, Request, Response, Rocket};
+
+use crate::config::AppConfig;
+use crate::fairings::request_log::RequestId;
+use crate::request_guards::trace_context::TraceContext;
+use crate::telemetry::TRACER;
+
+/// Reads W3C `traceparent` and `tracestate` from request headers.
+struct HeaderExtractor<'a, 'r> {
+    headers: &'a HeaderMap<'r>,
+    names: Vec<String>,
+}
+
+impl<'a, 'r> HeaderExtractor<'a, 'r> {
+    fn new(headers: &'a HeaderMap<'r>) -> Self {
+        let names = headers
+            .iter()
+            .map(|header| header.name().to_string())
+            .collect();
+        HeaderExtractor { headers, names }
+    }
+}
+
+impl Extractor for HeaderExtractor<'_, '_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.headers.get_one(key)
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.names.iter().map(String::as_str).collect()
+    }
+}
+
+/// Export a span per request to an OTLP collector, continuing the trace
+/// of the caller when the request carries a `traceparent` header.
+pub struct Telemetry;
+
+#[rocket::async_trait]
+impl Fairing for Telemetry {
+    fn info(&self) -> Info {
+        Info {
+            name: "OpenTelemetry request spans",
+            kind: Kind::Ignite | Kind::Request | Kind::Response | Kind::Shutdown,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        let config = rocket
+            .state::<AppConfig>()
+            .map(|config| config.telemetry.clone())
+            .unwrap_or_default();
+        if !config.enabled {
+            return Ok(rocket);
+        }
+
+        match config.provider() {
+            Ok(provider) => {
+                global::set_tracer_provider(provider.clone());
+                Ok(rocket.manage(provider))
+            }
+            Err(error) => {
+                tracing::error!(%error, "cannot export traces");
+                Err(rocket)
+            }
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        if request.rocket().state::<TracerProvider>().is_none() {
+            return;
+        }
+        let parent =
+            TraceContextPropagator::new().extract(&HeaderExtractor::new(request.headers()));
+        let tracer = global::tracer(TRACER);
+        // renamed after routing, once the matched route is known
+        let span = tracer
+            .span_builder(request.method().as_str().to_owned())
+            .with_kind(SpanKind::Server)
+            .with_attributes([
+                KeyValue::new("http.request.method", request.method().as_str()),
+                KeyValue::new("url.path", request.uri().path().to_string()),
+            ])
+            .start_with_context(&tracer, &parent);
+        let cx = parent.with_span(span);
+        request.local_cache(|| Some(TraceContext(cx)));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(TraceContext(cx)) = request.local_cache(|| None::<TraceContext>) else {
+            return;
+        };
+        let span = cx.span();
+        if let Some(route) = request.route() {
+            span.update_name(format!("{} {}", request.method(), route.uri.as_str()));
+            span.set_attribute(KeyValue::new("http.route", route.uri.as_str().to_owned()));
+        }
+        if let Some(request_id) = RequestId::of(request) {
+            span.set_attribute(KeyValue::new(
+                "http.request.header.x-request-id",
+                request_id,
+            ));
+        }
+        let status = response.status().code;
+        span.set_attribute(KeyValue::new(
+            "http.response.status_code",
+            i64::from(status),
+        ));
+        if status >= 500 {
+            span.set_status(Status::error(response.status().reason_lossy()));
+        }
+        span.end();
+    }
+
+    async fn on_shutdown(&self, rocket: &Rocket<Orbit>) {
+        let Some(provider) = rocket.state::<TracerProvider>().cloned() else {
+            return;
+        };
+        // flushing blocks until the collector answered
+        let _ = rocket::tokio::task::spawn_blocking(move || provider.shutdown()).await;
+    }
+}
@@ -0,0 +1,187 @@
+use rocket::data::{Data, FromData, Limits, Outcome};
+use rocket::http::{ContentType, MediaType as RocketMediaType, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use rocket_okapi::okapi::openapi3::{MediaType, RequestBody, Responses};
+use rocket_okapi::okapi::schemars::schema::SchemaObject;
+use rocket_okapi::okapi::Map;
+use rocket_okapi::{
+    gen::OpenApiGenerator, request::OpenApiFromData, response::OpenApiResponderInner,
+    util::add_schema_response, OpenApiError,
+};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::auth::signature::body_hash;
+use crate::request_guards::signature::SignedRequest;
+
+/// Serialization formats offered besides JSON to cut encoding costs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    MsgPack,
+    Cbor,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Json, Format::MsgPack, Format::Cbor];
+
+    pub fn content_type(&self) -> ContentType {
+        match self {
+            Format::Json => ContentType::JSON,
+            Format::MsgPack => ContentType::MsgPack,
+            Format::Cbor => ContentType::new("application", "cbor"),
+        }
+    }
+
+    fn from_media_type(media_type: &RocketMediaType) -> Option<Format> {
+        Format::ALL
+            .into_iter()
+            .find(|format| format.content_type().media_type() == media_type)
+    }
+
+    /// The format the client prefers according to `Accept`, JSON by default.
+    pub fn accepted(req: &Request<'_>) -> Format {
+        let Some(accept) = req.accept() else {
+            return Format::Json;
+        };
+        let mut media_types: Vec<_> = accept.iter().collect();
+        // stable sort keeps the client's order among equal weights
+        media_types.sort_by(|a, b| b.weight_or(1.0).total_cmp(&a.weight_or(1.0)));
+        media_types
+            .into_iter()
+            .find_map(|media_type| Format::from_media_type(media_type.media_type()))
+            .unwrap_or(Format::Json)
+    }
+
+    /// The format of the request body according to `Content-Type`, JSON by default.
+    pub fn of_body(req: &Request<'_>) -> Format {
+        req.content_type()
+            .and_then(|content_type| Format::from_media_type(content_type.media_type()))
+            .unwrap_or(Format::Json)
+    }
+
+    pub fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Format::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            // named encoding so fields keep their names, like in JSON
+            Format::MsgPack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            Format::Cbor => {
+                let mut bytes = vec![];
+                ciborium::into_writer(value, &mut bytes).map_err(|e| e.to_string())?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    pub fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Format::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            Format::MsgPack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            Format::Cbor => ciborium::from_reader(bytes).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn limit(&self, req: &Request<'_>) -> rocket::data::ByteUnit {
+        match self {
+            Format::Json => req.limits().get("json").unwrap_or(Limits::JSON),
+            Format::MsgPack => req.limits().get("msgpack").unwrap_or(Limits::MESSAGE_PACK),
+            Format::Cbor => req.limits().get("cbor").unwrap_or(Limits::MESSAGE_PACK),
+        }
+    }
+}
+
+/// Every supported media type, each with `schema`.
+pub fn media_types(schema: SchemaObject) -> Map<String, MediaType> {
+    Format::ALL
+        .into_iter()
+        .map(|format| {
+            let media = MediaType {
+                schema: Some(schema.clone()),
+                ..Default::default()
+            };
+            (format.content_type().to_string(), media)
+        })
+        .collect()
+}
+
+/// Like `Json<T>`, but responds in the format asked for by the `Accept` header
+/// and reads request bodies according to their `Content-Type`:
+/// `application/json`, `application/msgpack` or `application/cbor`.
+#[derive(Debug)]
+pub struct Negotiated<T>(pub T);
+
+impl<T> Negotiated<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<'r, T: Serialize> Responder<'r, 'static> for Negotiated<T> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let format = Format::accepted(req);
+        let body = format.serialize(&self.0).map_err(|error| {
+            tracing::error!(?format, %error, "failed to serialize response");
+            Status::InternalServerError
+        })?;
+
+        rocket::Response::build()
+            .sized_body(body.len(), std::io::Cursor::new(body))
+            .header(format.content_type())
+            .ok()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned> FromData<'r> for Negotiated<T> {
+    type Error = String;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> Outcome<'r, Self> {
+        let format = Format::of_body(req);
+        let bytes = match data.open(format.limit(req)).into_bytes().await {
+            Ok(bytes) if bytes.is_complete() => bytes.into_inner(),
+            Ok(_) => {
+                return Outcome::Error((Status::PayloadTooLarge, "data limit exceeded".into()))
+            }
+            Err(error) => return Outcome::Error((Status::BadRequest, error.to_string())),
+        };
+        if SignedRequest::body_hash_of(req).is_some_and(|signed| signed != body_hash(&bytes)) {
+            return Outcome::Error((
+                Status::Unauthorized,
+                "the body does not match its signature".into(),
+            ));
+        }
+
+        match format.deserialize(&bytes) {
+            Ok(value) => Outcome::Success(Negotiated(value)),
+            Err(error) => Outcome::Error((Status::UnprocessableEntity, error)),
+        }
+    }
+}
+
+impl<T: Serialize + JsonSchema + Send> OpenApiResponderInner for Negotiated<T> {
+    fn responses(gen: &mut OpenApiGenerator) -> Result<Responses, OpenApiError> {
+        let mut responses = Responses::default();
+        let schema = gen.json_schema::<T>();
+        for format in Format::ALL {
+            add_schema_response(
+                &mut responses,
+                200,
+                format.content_type().to_string(),
+                schema.clone(),
+            )?;
+        }
+        Ok(responses)
+    }
+}
+
+impl<'r, T: DeserializeOwned + JsonSchema + Send> OpenApiFromData<'r> for Negotiated<T> {
+    fn request_body(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<RequestBody> {
+        let schema = gen.json_schema::<T>();
+        Ok(RequestBody {
+            content: media_types(schema),
+            required: true,
+            ..Default::default()
+        })
+    }
+}
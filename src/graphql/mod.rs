@@ -0,0 +1,146 @@
+use async_graphql::{Context, EmptySubscription, Error, Guard, Object, Result, Schema, ID};
+use mongodb::bson::oid::ObjectId;
+use rocket::fairing::AdHoc;
+use rocket::serde::json::Json;
+
+use crate::{
+    config::{page_offset, AppConfig, PaginationConfig},
+    db::{
+        change_feed::ChangeFeed,
+        customer,
+        tenant::{Tenant, TenantError},
+    },
+    models::{
+        api_key::Scope,
+        customer::{Customer, CustomerInput, Ownership},
+    },
+    request_guards::principal::Principal,
+};
+
+pub type CustomerSchema = Schema<QueryRoot, MutationRoot, EmptySubscription>;
+
+/// Same rule as the `Authorized` request guard on the REST routes: a valid
+/// `x-api-key` header or bearer token granted the scope has to be sent with
+/// the request.
+struct RequireScope(Scope);
+
+impl Guard for RequireScope {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        match ctx.data_opt::<Principal>() {
+            Some(principal) if principal.has_scope(self.0) => Ok(()),
+            Some(_) => Err(Error::new("Forbidden")),
+            None => Err(Error::new("Unauthorized")),
+        }
+    }
+}
+
+/// The tenant of the request, resolved by the `Tenant` request guard.
+fn tenant<'a>(ctx: &Context<'a>) -> Result<&'a Tenant> {
+    match (ctx.data_opt::<Tenant>(), ctx.data_opt::<TenantError>()) {
+        (Some(tenant), _) => Ok(tenant),
+        (None, Some(error)) => Err(Error::new(error.to_string())),
+        (None, None) => Err(Error::new(TenantError::Missing.to_string())),
+    }
+}
+
+/// The customers the caller may access, once `RequireScope` let it through.
+fn ownership(ctx: &Context<'_>) -> Result<Ownership> {
+    Ok(ctx.data::<Principal>()?.ownership())
+}
+
+fn parse_id(id: &ID) -> Result<ObjectId> {
+    ObjectId::parse_str(id.as_str()).map_err(|_| Error::new("Invalid _id format."))
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// get customer document by _id
+    #[graphql(guard = "RequireScope(Scope::CustomerRead)")]
+    async fn customer(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Customer>> {
+        let tenant = tenant(ctx)?;
+        let oid = parse_id(&id)?;
+
+        Ok(customer::find_customer_by_id(tenant, &ownership(ctx)?, oid).await?)
+    }
+
+    /// get customer documents
+    #[graphql(guard = "RequireScope(Scope::CustomerRead)")]
+    async fn customers(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        #[graphql(default = 1)] page: i64,
+    ) -> Result<Vec<Customer>> {
+        let tenant = tenant(ctx)?;
+        let limit = ctx.data::<PaginationConfig>()?.limit(limit);
+        let skip = page_offset(page, limit).map_err(Error::new)?;
+
+        Ok(customer::find_customer(tenant, &ownership(ctx)?, limit, skip).await?)
+    }
+}
+
+pub struct MutationRoot;
+
+#[Object]
+impl MutationRoot {
+    /// create a customer document
+    #[graphql(guard = "RequireScope(Scope::CustomerWrite)")]
+    async fn create_customer(&self, ctx: &Context<'_>, input: CustomerInput) -> Result<Customer> {
+        let tenant = tenant(ctx)?;
+        let feed = ctx.data::<ChangeFeed>()?;
+
+        Ok(customer::insert_customer(tenant, feed, &ownership(ctx)?, Json(input)).await?)
+    }
+
+    /// update a customer document by _id
+    #[graphql(guard = "RequireScope(Scope::CustomerWrite)")]
+    async fn update_customer(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        input: CustomerInput,
+    ) -> Result<Option<Customer>> {
+        let tenant = tenant(ctx)?;
+        let feed = ctx.data::<ChangeFeed>()?;
+        let oid = parse_id(&id)?;
+
+        Ok(
+            customer::update_customer_by_id(tenant, feed, &ownership(ctx)?, oid, Json(input))
+                .await?,
+        )
+    }
+
+    /// delete a customer document by _id
+    #[graphql(guard = "RequireScope(Scope::CustomerDelete)")]
+    async fn delete_customer(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Customer>> {
+        let tenant = tenant(ctx)?;
+        let feed = ctx.data::<ChangeFeed>()?;
+        let oid = parse_id(&id)?;
+
+        Ok(customer::delete_customer_by_id(tenant, feed, &ownership(ctx)?, oid).await?)
+    }
+}
+
+/// Build the schema from the managed `ChangeFeed`, so it has to be attached
+/// after `db::change_feed::init`. The `Tenant` holding the customers is
+/// given with each request.
+pub fn init() -> AdHoc {
+    AdHoc::on_ignite("GraphQL schema", |rocket| async {
+        let Some(feed) = rocket.state::<ChangeFeed>() else {
+            return rocket;
+        };
+        let pagination = rocket
+            .state::<AppConfig>()
+            .map(|config| config.pagination.clone())
+            .unwrap_or_default();
+        let schema = Schema::build(QueryRoot, MutationRoot, EmptySubscription)
+            .data(feed.clone())
+            .data(pagination)
+            .limit_depth(10)
+            .limit_complexity(200)
+            .finish();
+        rocket.manage(schema)
+    })
+}
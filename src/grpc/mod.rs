@@ -0,0 +1,423 @@
+// `tonic::Status` is the error type of every gRPC handler, large or not.
+#![allow(clippy::result_large_err)]
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+use futures::Stream;
+use mongodb::bson::oid::ObjectId;
+use rocket::fairing::AdHoc;
+use rocket::serde::json::Json;
+use rocket::tokio::{net::TcpListener, task::JoinHandle};
+use serde::Deserialize;
+use tonic::{
+    metadata::MetadataMap,
+    transport::{server::TcpIncoming, Server},
+    Code, Request, Response, Status,
+};
+
+use crate::{
+    auth::{jwt::JwtVerifier, ApiKeyStore},
+    config::{page_offset, AppConfig, PaginationConfig},
+    db::{
+        change_feed::ChangeFeed,
+        customer,
+        tenant::{Tenant, TenantError, Tenants},
+    },
+    models::{
+        api_key::Scope,
+        customer::{Customer as CustomerJson, CustomerInput},
+    },
+    rate_limit::RateLimiter,
+    request_guards::{basic::AuthError, bearer::BearerToken, principal::Principal},
+};
+
+pub mod proto {
+    tonic::include_proto!("customer.v1");
+}
+
+use proto::customer_service_server::{CustomerService, CustomerServiceServer};
+use proto::{
+    CreateCustomerRequest, Customer, DeleteCustomerRequest, GetCustomerRequest,
+    ListCustomersRequest, UpdateCustomerRequest,
+};
+
+/// `[default.grpc]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct GrpcConfig {
+    pub enabled: bool,
+    pub address: IpAddr,
+    pub port: u16,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        GrpcConfig {
+            enabled: true,
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 50051,
+        }
+    }
+}
+
+impl From<CustomerJson> for Customer {
+    fn from(customer: CustomerJson) -> Self {
+        Customer {
+            id: customer.id,
+            name: customer.name,
+            created_at: customer.created_at,
+            team: customer.team.unwrap_or_default(),
+        }
+    }
+}
+
+fn parse_id(id: &str) -> Result<ObjectId, Status> {
+    ObjectId::parse_str(id).map_err(|_| Status::invalid_argument("Invalid _id format."))
+}
+
+fn tenant_header<T>(request: &Request<T>) -> Option<&str> {
+    request
+        .metadata()
+        .get("x-tenant-id")
+        .and_then(|value| value.to_str().ok())
+}
+
+fn not_found(id: &str) -> Status {
+    Status::not_found(format!("Customer not found with _id {}", id))
+}
+
+pub struct CustomerGrpc {
+    tenants: Tenants,
+    feed: ChangeFeed,
+    keys: ApiKeyStore,
+    /// Set when `auth.jwt` is enabled.
+    jwt: Option<JwtVerifier>,
+    /// Set when `rate_limit` is enabled, sharing the buckets of REST.
+    limiter: Option<RateLimiter>,
+    pagination: PaginationConfig,
+}
+
+impl CustomerGrpc {
+    pub fn new(
+        tenants: Tenants,
+        feed: ChangeFeed,
+        keys: ApiKeyStore,
+        jwt: Option<JwtVerifier>,
+        limiter: Option<RateLimiter>,
+        config: &AppConfig,
+    ) -> CustomerGrpc {
+        CustomerGrpc {
+            tenants,
+            feed,
+            keys,
+            jwt,
+            limiter,
+            pagination: config.pagination.clone(),
+        }
+    }
+
+    pub fn into_server(self) -> CustomerServiceServer<CustomerGrpc> {
+        CustomerServiceServer::new(self)
+    }
+
+    /// Check the `authorization` bearer token or the `x-api-key` metadata
+    /// with the same rules as the `Authorized` guard, once a token was taken
+    /// from the bucket of the caller like the `RateLimit` guard does.
+    async fn authorize<T>(
+        &self,
+        request: &Request<T>,
+        method: &str,
+        scope: Scope,
+    ) -> Result<Principal, Status> {
+        let metadata = request.metadata();
+        let bearer = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(BearerToken::parse);
+        let principal = match (bearer, &self.jwt) {
+            (Some(token), Some(jwt)) => jwt.verify(token).await.map(Principal::Bearer),
+            (Some(_), None) => Err(AuthError::Invalid),
+            (None, _) => match metadata.get("x-api-key").map(|key| key.to_str()) {
+                Some(Ok(key)) => self.keys.verify(key).await.map(Principal::ApiKey),
+                Some(Err(_)) => Err(AuthError::Invalid),
+                None => Err(AuthError::Missing),
+            },
+        };
+        self.limit(request, method, principal.as_ref().ok()).await?;
+        match principal {
+            Ok(principal) if principal.has_scope(scope) => Ok(principal),
+            Ok(_) => Err(Status::permission_denied(format!(
+                "The credentials lack the {} scope.",
+                scope.as_str()
+            ))),
+            Err(AuthError::Missing) => {
+                Err(Status::unauthenticated("Missing API key or bearer token."))
+            }
+            Err(AuthError::Unavailable) => Err(Status::unavailable("Cannot verify credentials.")),
+            Err(_) => Err(Status::unauthenticated("Invalid API key or bearer token.")),
+        }
+    }
+
+    /// Take a token from the bucket of `principal`, or of the client IP
+    /// without valid credentials. Buckets are shared with REST, and routes
+    /// of `rate_limit.routes` are named like `POST
+    /// /customer.v1.CustomerService/ListCustomers`.
+    async fn limit<T>(
+        &self,
+        request: &Request<T>,
+        method: &str,
+        principal: Option<&Principal>,
+    ) -> Result<(), Status> {
+        let Some(limiter) = &self.limiter else {
+            return Ok(());
+        };
+        let route = format!("POST /customer.v1.CustomerService/{}", method);
+        let tenant = self.tenants.resolve(principal, tenant_header(request)).ok();
+        let ip = request.remote_addr().map(|address| address.ip());
+        let Some(decision) = limiter.check(&route, principal, ip, tenant.as_ref()).await else {
+            return Ok(());
+        };
+        if decision.allowed {
+            return Ok(());
+        }
+        let mut metadata = MetadataMap::new();
+        metadata.insert("retry-after", decision.retry_after.into());
+        Err(Status::with_metadata(
+            Code::ResourceExhausted,
+            "Rate limit exceeded, retry later.",
+            metadata,
+        ))
+    }
+
+    /// The tenant of `principal` or of the `x-tenant-id` metadata, with the
+    /// same rules as the `Tenant` guard.
+    fn tenant<T>(&self, request: &Request<T>, principal: &Principal) -> Result<Tenant, Status> {
+        match self
+            .tenants
+            .resolve(Some(principal), tenant_header(request))
+        {
+            Ok(tenant) => Ok(tenant),
+            Err(error @ (TenantError::Mismatch | TenantError::Unbound)) => {
+                Err(Status::permission_denied(error.to_string()))
+            }
+            Err(error) => Err(Status::invalid_argument(error.to_string())),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl CustomerService for CustomerGrpc {
+    async fn get_customer(
+        &self,
+        request: Request<GetCustomerRequest>,
+    ) -> Result<Response<Customer>, Status> {
+        let principal = self
+            .authorize(&request, "GetCustomer", Scope::CustomerRead)
+            .await?;
+        let tenant = self.tenant(&request, &principal)?;
+        let id = request.into_inner().id;
+        let oid = parse_id(&id)?;
+
+        match customer::find_customer_by_id(&tenant, &principal.ownership(), oid).await {
+            Ok(Some(customer_doc)) => Ok(Response::new(customer_doc.into())),
+            Ok(None) => Err(not_found(&id)),
+            Err(error) => Err(Status::internal(error.to_string())),
+        }
+    }
+
+    type ListCustomersStream = Pin<Box<dyn Stream<Item = Result<Customer, Status>> + Send>>;
+
+    async fn list_customers(
+        &self,
+        request: Request<ListCustomersRequest>,
+    ) -> Result<Response<Self::ListCustomersStream>, Status> {
+        let principal = self
+            .authorize(&request, "ListCustomers", Scope::CustomerRead)
+            .await?;
+        let tenant = self.tenant(&request, &principal)?;
+        let request = request.into_inner();
+        // Setting default values
+        let limit = self.pagination.limit(Some(request.limit));
+        let page = if request.page > 0 { request.page } else { 1 };
+        let skip = page_offset(page, limit).map_err(Status::invalid_argument)?;
+
+        match customer::find_customer(&tenant, &principal.ownership(), limit, skip).await {
+            Ok(customer_docs) => {
+                let stream = futures::stream::iter(
+                    customer_docs
+                        .into_iter()
+                        .map(|customer_doc| Ok(customer_doc.into())),
+                );
+                Ok(Response::new(Box::pin(stream)))
+            }
+            Err(error) => Err(Status::internal(error.to_string())),
+        }
+    }
+
+    async fn create_customer(
+        &self,
+        request: Request<CreateCustomerRequest>,
+    ) -> Result<Response<Customer>, Status> {
+        let principal = self
+            .authorize(&request, "CreateCustomer", Scope::CustomerWrite)
+            .await?;
+        let tenant = self.tenant(&request, &principal)?;
+        let request = request.into_inner();
+        let input = CustomerInput {
+            name: request.name,
+            team: Some(request.team).filter(|team| !team.is_empty()),
+        };
+
+        match customer::insert_customer(&tenant, &self.feed, &principal.ownership(), Json(input))
+            .await
+        {
+            Ok(customer_doc) => Ok(Response::new(customer_doc.into())),
+            Err(error) => Err(Status::invalid_argument(error.to_string())),
+        }
+    }
+
+    async fn update_customer(
+        &self,
+        request: Request<UpdateCustomerRequest>,
+    ) -> Result<Response<Customer>, Status> {
+        let principal = self
+            .authorize(&request, "UpdateCustomer", Scope::CustomerWrite)
+            .await?;
+        let tenant = self.tenant(&request, &principal)?;
+        let request = request.into_inner();
+        let oid = parse_id(&request.id)?;
+        let input = CustomerInput {
+            name: request.name,
+            team: None,
+        };
+
+        match customer::update_customer_by_id(
+            &tenant,
+            &self.feed,
+            &principal.ownership(),
+            oid,
+            Json(input),
+        )
+        .await
+        {
+            Ok(Some(customer_doc)) => Ok(Response::new(customer_doc.into())),
+            Ok(None) => Err(not_found(&request.id)),
+            Err(error) => Err(Status::internal(error.to_string())),
+        }
+    }
+
+    async fn delete_customer(
+        &self,
+        request: Request<DeleteCustomerRequest>,
+    ) -> Result<Response<Customer>, Status> {
+        let principal = self
+            .authorize(&request, "DeleteCustomer", Scope::CustomerDelete)
+            .await?;
+        let tenant = self.tenant(&request, &principal)?;
+        let id = request.into_inner().id;
+        let oid = parse_id(&id)?;
+
+        match customer::delete_customer_by_id(&tenant, &self.feed, &principal.ownership(), oid)
+            .await
+        {
+            Ok(Some(customer_doc)) => Ok(Response::new(customer_doc.into())),
+            Ok(None) => Err(not_found(&id)),
+            Err(error) => Err(Status::internal(error.to_string())),
+        }
+    }
+}
+
+/// Serve `CustomerService` on its own port next to Rocket,
+/// stopping together with it.
+/// The task serving gRPC next to Rocket, for readiness to report whether it
+/// serves and for `main` to wait for it to release its port.
+#[derive(Default)]
+pub struct GrpcServer {
+    /// `None` until started or when disabled, else why it stopped serving.
+    status: Arc<Mutex<Option<Result<(), String>>>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl GrpcServer {
+    /// `None` when gRPC is disabled, else whether the server is serving.
+    pub fn status(&self) -> Option<Result<(), String>> {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Wait for the server to stop after shutdown, its port being free then.
+    pub async fn stopped(&self) {
+        let task = self.task.lock().unwrap().take();
+        if let Some(task) = task {
+            let _ = task.await;
+        }
+    }
+}
+
+/// Serve gRPC once Rocket has lifted off, until it shuts down; a failed bind
+/// is reported by `/health/ready`.
+pub fn init() -> AdHoc {
+    AdHoc::on_ignite("gRPC CustomerService", |rocket| async {
+        rocket
+            .manage(GrpcServer::default())
+            .attach(AdHoc::on_liftoff("Serving gRPC", |rocket| {
+                Box::pin(async move {
+                    let (Some(app_config), Some(tenants), Some(feed), Some(keys), Some(server)) = (
+                        rocket.state::<AppConfig>(),
+                        rocket.state::<Tenants>(),
+                        rocket.state::<ChangeFeed>(),
+                        rocket.state::<ApiKeyStore>(),
+                        rocket.state::<GrpcServer>(),
+                    ) else {
+                        return;
+                    };
+                    let config = app_config.grpc.clone();
+                    if !config.enabled {
+                        return;
+                    }
+
+                    let jwt = rocket.state::<JwtVerifier>().cloned();
+                    let limiter = rocket.state::<RateLimiter>().cloned();
+                    let service = CustomerGrpc::new(
+                        tenants.clone(),
+                        feed.clone(),
+                        keys.clone(),
+                        jwt,
+                        limiter,
+                        app_config,
+                    )
+                    .into_server();
+                    let address = SocketAddr::new(config.address, config.port);
+                    let incoming = match TcpListener::bind(address).await {
+                        Ok(listener) => TcpIncoming::from_listener(listener, true, None)
+                            .map_err(|error| error.to_string()),
+                        Err(error) => Err(error.to_string()),
+                    };
+                    let incoming = match incoming {
+                        Ok(incoming) => incoming,
+                        Err(error) => {
+                            tracing::error!(%address, %error, "gRPC server cannot listen");
+                            *server.status.lock().unwrap() = Some(Err(error));
+                            return;
+                        }
+                    };
+                    *server.status.lock().unwrap() = Some(Ok(()));
+                    let (status, shutdown) = (server.status.clone(), rocket.shutdown());
+
+                    let task = rocket::tokio::spawn(async move {
+                        tracing::info!(%address, "gRPC CustomerService listening");
+                        if let Err(error) = Server::builder()
+                            .add_service(service)
+                            .serve_with_incoming_shutdown(incoming, shutdown)
+                            .await
+                        {
+                            tracing::error!(%error, "gRPC server failed");
+                            *status.lock().unwrap() = Some(Err(error.to_string()));
+                        }
+                    });
+                    *server.task.lock().unwrap() = Some(task);
+                })
+            }))
+    })
+}
@@ -0,0 +1,117 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use mongodb::Database;
+use rocket::fairing::AdHoc;
+use rocket::tokio::time::timeout;
+use serde::Deserialize;
+
+use crate::config::AppConfig;
+use crate::db::{self, change_feed::ChangeFeed};
+use crate::grpc::GrpcServer;
+use crate::models::health::{ComponentHealth, HealthReport};
+
+/// `[default.health]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Milliseconds to wait for MongoDB to answer `ping`.
+    pub timeout_ms: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig { timeout_ms: 2000 }
+    }
+}
+
+/// Whether the instance should receive traffic, off once shutdown has begun.
+pub struct Readiness(AtomicBool);
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn shut_down(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+async fn check_mongodb(db: Option<&Database>, limit: Duration) -> ComponentHealth {
+    let Some(db) = db else {
+        return ComponentHealth::down("not connected".to_string());
+    };
+    let start = Instant::now();
+    let mut health = match timeout(limit, db::ping(db)).await {
+        Ok(Ok(())) => ComponentHealth::up(None),
+        Ok(Err(error)) => ComponentHealth::down(error.to_string()),
+        Err(_) => ComponentHealth::down(format!("no answer within {:?}", limit)),
+    };
+    health.latency_ms = Some(start.elapsed().as_millis() as u64);
+    health
+}
+
+/// Check every dependency needed to serve requests.
+pub async fn readiness(
+    readiness: &Readiness,
+    config: &HealthConfig,
+    db: Option<&Database>,
+    feed: Option<&ChangeFeed>,
+    grpc: Option<&GrpcServer>,
+) -> HealthReport {
+    let mut components = BTreeMap::new();
+    components.insert(
+        "server".to_string(),
+        if readiness.is_ready() {
+            ComponentHealth::up(None)
+        } else {
+            ComponentHealth::down("shutting down".to_string())
+        },
+    );
+    components.insert(
+        "mongodb".to_string(),
+        check_mongodb(db, Duration::from_millis(config.timeout_ms)).await,
+    );
+    if let Some(feed) = feed {
+        let source = if feed.is_change_stream() {
+            "change stream"
+        } else {
+            "in-process"
+        };
+        components.insert(
+            "changeFeed".to_string(),
+            ComponentHealth::up(Some(source.to_string())),
+        );
+    }
+    match grpc.and_then(GrpcServer::status) {
+        Some(Ok(())) => {
+            components.insert("grpc".to_string(), ComponentHealth::up(None));
+        }
+        Some(Err(error)) => {
+            components.insert("grpc".to_string(), ComponentHealth::down(error));
+        }
+        None => {}
+    }
+    HealthReport::new(components)
+}
+
+pub fn init() -> AdHoc {
+    AdHoc::on_ignite("Health checks", |rocket| async {
+        let config = rocket
+            .state::<AppConfig>()
+            .map(|config| config.health.clone())
+            .unwrap_or_default();
+        rocket
+            .manage(config)
+            .manage(Readiness(AtomicBool::new(true)))
+            .attach(AdHoc::on_shutdown("Readiness off", |rocket| {
+                Box::pin(async move {
+                    if let Some(readiness) = rocket.state::<Readiness>() {
+                        readiness.shut_down();
+                    }
+                })
+            }))
+    })
+}
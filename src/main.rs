@@ -3,34 +3,88 @@ extern crate rocket;
 
 use dotenv::dotenv;
 use rocket_okapi::{
-    openapi_get_routes,
+    get_openapi_route, openapi_get_routes_spec,
+    settings::OpenApiSettings,
     swagger_ui::{make_swagger_ui, SwaggerUIConfig},
 };
 
+mod auth;
+mod config;
 mod db;
 mod errors;
 mod fairings;
+mod formats;
+mod graphql;
+mod grpc;
+mod health;
+mod metrics;
 mod models;
+mod rate_limit;
 mod request_guards;
 mod routes;
+mod telemetry;
+mod tls;
+mod webhooks;
 
-#[launch]
-fn rocket() -> _ {
+fn rocket() -> rocket::Rocket<rocket::Build> {
     dotenv().ok();
+    let settings = OpenApiSettings::new();
+    let (routes, mut spec) = openapi_get_routes_spec![
+        settings:
+        routes::index,
+        routes::health::live,
+        routes::health::ready,
+        routes::customer::get_customers,
+        routes::customer::get_customer_events,
+        routes::customer::customer_updates,
+        routes::customer::get_customer_by_id,
+        routes::customer::post_customer,
+        routes::customer::patch_customer_by_id,
+        routes::customer::delete_customer_by_id,
+        routes::webhook::get_webhooks,
+        routes::webhook::get_webhook_by_id,
+        routes::webhook::post_webhook,
+        routes::webhook::patch_webhook_by_id,
+        routes::webhook::delete_webhook_by_id,
+        routes::admin::post_api_key,
+        routes::admin::get_api_keys,
+        routes::admin::rotate_api_key,
+        routes::admin::revoke_api_key,
+        routes::admin::post_oauth_client,
+        routes::admin::get_oauth_clients,
+        routes::admin::revoke_oauth_client,
+        routes::oauth::token,
+        routes::oauth::introspect,
+        routes::oauth::revoke
+    ];
+    request_guards::bearer::document(&mut spec);
+    request_guards::signature::document(&mut spec);
+
     rocket::build()
+        .attach(fairings::request_log::RequestLog)
+        .attach(config::init())
+        .attach(fairings::telemetry::Telemetry)
         .attach(db::init())
+        .attach(db::tenant::init())
+        .attach(auth::init())
+        .attach(health::init())
+        .attach(fairings::rate_limit::RateLimiting)
+        .attach(db::change_feed::init())
+        .attach(webhooks::init())
+        .attach(graphql::init())
+        .attach(grpc::init())
+        .attach(tls::init())
         .attach(fairings::cors::Cors)
+        .attach(fairings::security_headers::SecurityHeaders)
+        .attach(fairings::compression::Compression)
+        .attach(fairings::metrics::Metrics)
+        .mount("/", routes)
+        .mount("/", vec![get_openapi_route(spec, &settings)])
         .mount(
             "/",
-            openapi_get_routes![
-                routes::index,
-                routes::customer::get_customers,
-                routes::customer::get_customer_by_id,
-                routes::customer::post_customer,
-                routes::customer::patch_customer_by_id,
-                routes::customer::delete_customer_by_id
-            ],
+            routes![routes::graphql::get_graphql, routes::graphql::post_graphql],
         )
+        .mount("/graphiql", routes![routes::graphql::graphiql])
         .mount(
             "/api-docs",
             make_swagger_ui(&SwaggerUIConfig {
@@ -40,6 +94,21 @@ fn rocket() -> _ {
         )
 }
 
+#[rocket::main]
+async fn main() -> Result<(), Box<rocket::Error>> {
+    loop {
+        let rocket = rocket().launch().await?;
+        // stopped by `tls::init` to serve new certificates
+        if !tls::take_reload() {
+            return Ok(());
+        }
+        // the next launch binds the same gRPC port
+        if let Some(grpc) = rocket.state::<grpc::GrpcServer>() {
+            grpc.stopped().await;
+        }
+    }
+}
+
 // Unit testings
 #[cfg(test)]
 mod tests;
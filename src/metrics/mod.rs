@@ -0,0 +1,57 @@
+use std::sync::LazyLock;
+
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, Encoder, HistogramTimer,
+    HistogramVec, IntCounterVec, IntGauge, TextEncoder,
+};
+
+pub static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "http_requests_total",
+        "HTTP requests by method, route and status class",
+        &["method", "route", "status"]
+    )
+    .unwrap()
+});
+
+pub static HTTP_REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "http_request_duration_seconds",
+        "HTTP request latency by method and route",
+        &["method", "route"]
+    )
+    .unwrap()
+});
+
+pub static HTTP_REQUESTS_IN_FLIGHT: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "http_requests_in_flight",
+        "HTTP requests currently being handled"
+    )
+    .unwrap()
+});
+
+pub static MONGODB_OPERATION_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "mongodb_operation_duration_seconds",
+        "MongoDB operation latency by db function",
+        &["operation"]
+    )
+    .unwrap()
+});
+
+/// Time a MongoDB operation until the returned timer is dropped.
+pub fn time_db(operation: &str) -> HistogramTimer {
+    MONGODB_OPERATION_DURATION
+        .with_label_values(&[operation])
+        .start_timer()
+}
+
+/// Every registered metric in the Prometheus text format.
+pub fn render() -> String {
+    let mut buffer = vec![];
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
@@ -0,0 +1,246 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::{self, oid::ObjectId};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Permission granted to an API key, named `resource:action`.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    #[serde(rename = "customer:read")]
+    CustomerRead,
+    #[serde(rename = "customer:write")]
+    CustomerWrite,
+    #[serde(rename = "customer:delete")]
+    CustomerDelete,
+    /// manage API keys, OAuth2 clients and webhooks
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::CustomerRead,
+        Scope::CustomerWrite,
+        Scope::CustomerDelete,
+        Scope::Admin,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::CustomerRead => "customer:read",
+            Scope::CustomerWrite => "customer:write",
+            Scope::CustomerDelete => "customer:delete",
+            Scope::Admin => "admin",
+        }
+    }
+
+    /// The scope named `value`, as in `customer:read`.
+    pub fn parse(value: &str) -> Option<Scope> {
+        Scope::ALL.into_iter().find(|scope| scope.as_str() == value)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Scope::CustomerRead => "read customers and their events",
+            Scope::CustomerWrite => "create and update customers",
+            Scope::CustomerDelete => "delete customers",
+            Scope::Admin => "manage API keys, OAuth2 clients and webhooks",
+        }
+    }
+}
+
+/// Role of a principal, granting a fixed set of scopes on top of its own.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// read customers
+    Viewer,
+    /// read, write and delete customers
+    Editor,
+    /// every scope, and every customer whatever its team
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Viewer, Role::Editor, Role::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// The role named `value`, as in `viewer`.
+    pub fn parse(value: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|role| role.as_str() == value)
+    }
+
+    pub fn scopes(&self) -> &'static [Scope] {
+        match self {
+            Role::Viewer => &[Scope::CustomerRead],
+            Role::Editor => &[
+                Scope::CustomerRead,
+                Scope::CustomerWrite,
+                Scope::CustomerDelete,
+            ],
+            Role::Admin => &Scope::ALL,
+        }
+    }
+
+    /// `scopes` together with the scopes granted by `roles`.
+    pub fn grant(scopes: &[Scope], roles: &[Role]) -> Vec<Scope> {
+        let mut granted = scopes.to_vec();
+        for scope in roles.iter().flat_map(Role::scopes) {
+            if !granted.contains(scope) {
+                granted.push(*scope);
+            }
+        }
+        granted
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ApiKeyDocument {
+    /// Document Id
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    /// what the key is used for
+    pub name: String,
+    /// who the key was issued to
+    pub owner: String,
+    /// public part of the key, used to look it up
+    pub prefix: String,
+    /// HMAC-SHA256 of the secret part, keyed with the pepper
+    pub hash: String,
+    pub scopes: Vec<Scope>,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    /// team owning the customers the key may access, unless an admin
+    #[serde(default)]
+    pub team: Option<String>,
+    /// tenant the key is bound to; when absent, any for admins, the default one otherwise
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// expiresAt, never when absent
+    #[serde(rename = "expiresAt", default)]
+    pub expires_at: Option<bson::DateTime>,
+    /// lastUsedAt, updated at most once a minute
+    #[serde(rename = "lastUsedAt", default)]
+    pub last_used_at: Option<bson::DateTime>,
+    /// revokedAt
+    #[serde(rename = "revokedAt", default)]
+    pub revoked_at: Option<bson::DateTime>,
+    /// createdAt
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    pub created_at: DateTime<Utc>,
+}
+
+impl ApiKeyDocument {
+    /// Whether the key may still be used at `now`.
+    pub fn is_active(&self, now: bson::DateTime) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct ApiKeyInfo {
+    /// Document Id
+    #[serde(rename = "_id")]
+    pub id: String,
+    /// what the key is used for
+    pub name: String,
+    /// who the key was issued to
+    pub owner: String,
+    /// public part of the key, `rrs_<prefix>_...`
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub roles: Vec<Role>,
+    /// team owning the customers the key may access, unless an admin
+    pub team: Option<String>,
+    /// tenant the key is bound to; when absent, any for admins, the default one otherwise
+    pub tenant: Option<String>,
+    /// expiresAt, never when absent
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<String>,
+    /// lastUsedAt
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: Option<String>,
+    /// revokedAt
+    #[serde(rename = "revokedAt")]
+    pub revoked_at: Option<String>,
+    /// createdAt
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+impl From<ApiKeyDocument> for ApiKeyInfo {
+    fn from(api_key_doc: ApiKeyDocument) -> Self {
+        // the hash is never sent back
+        let rfc3339 = |date: bson::DateTime| date.to_chrono().to_rfc3339();
+        ApiKeyInfo {
+            id: api_key_doc.id.to_string(),
+            name: api_key_doc.name,
+            owner: api_key_doc.owner,
+            prefix: api_key_doc.prefix,
+            scopes: api_key_doc.scopes,
+            roles: api_key_doc.roles,
+            team: api_key_doc.team,
+            tenant: api_key_doc.tenant,
+            expires_at: api_key_doc.expires_at.map(rfc3339),
+            last_used_at: api_key_doc.last_used_at.map(rfc3339),
+            revoked_at: api_key_doc.revoked_at.map(rfc3339),
+            created_at: api_key_doc.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// A key just created, the only response carrying its secret.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct IssuedApiKey {
+    /// the key to send in `x-api-key`, not retrievable later
+    pub key: String,
+    /// the secret to sign requests with instead, not retrievable later
+    #[serde(rename = "signingSecret")]
+    pub signing_secret: String,
+    #[serde(flatten)]
+    pub info: ApiKeyInfo,
+}
+
+/// A nonce of a signed request, kept until its timestamp leaves the window.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RequestNonceDocument {
+    /// `<key prefix>:<nonce>`
+    #[serde(rename = "_id")]
+    pub id: String,
+    /// expiresAt, when the document is removed
+    #[serde(rename = "expiresAt")]
+    pub expires_at: bson::DateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct ApiKeyInput {
+    /// what the key is used for
+    pub name: String,
+    /// who the key is issued to
+    pub owner: String,
+    #[serde(default)]
+    pub scopes: Vec<Scope>,
+    /// roles granting scopes on top of `scopes`
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    /// team owning the customers the key may access, unless an admin
+    #[serde(default)]
+    pub team: Option<String>,
+    /// tenant the key is bound to; when absent, any for admins, the default one otherwise.
+    /// Admins bound to a tenant can only issue keys of their tenant.
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// RFC 3339 expiry date, never when absent
+    #[serde(rename = "expiresAt", default)]
+    pub expires_at: Option<String>,
+}
@@ -1,5 +1,6 @@
+use async_graphql::{InputObject, SimpleObject};
 use chrono::{DateTime, Utc};
-use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{oid::ObjectId, Document};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -16,9 +17,15 @@ pub struct CustomerDocument {
         rename = "createdAt"
     )]
     pub created_at: DateTime<Utc>,
+    /// owning team, none for customers created by admins without one
+    #[serde(default)]
+    pub team: Option<String>,
+    /// tenant of the customer with `field` isolation
+    #[serde(rename = "tenantId", default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, SimpleObject, Clone)]
 pub struct Customer {
     /// Document Id
     #[serde(rename = "_id")]
@@ -28,10 +35,61 @@ pub struct Customer {
     /// createdAt
     #[serde(rename = "createdAt")]
     pub created_at: String,
+    /// owning team
+    pub team: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, InputObject, Clone)]
 pub struct CustomerInput {
     /// customer name
     pub name: String,
+    /// owning team, only set by admins on creation; customers created by
+    /// others belong to their team
+    #[serde(default)]
+    pub team: Option<String>,
+}
+
+/// The customers a principal may see and modify: every customer for admins,
+/// else those of its team, or those without a team when it has none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ownership {
+    All,
+    Team(Option<String>),
+}
+
+impl Ownership {
+    /// `filter` narrowed to the customers in scope.
+    pub fn filter(&self, mut filter: Document) -> Document {
+        if let Ownership::Team(team) = self {
+            filter.insert("team", team.clone());
+        }
+        filter
+    }
+
+    pub fn includes(&self, customer: &Customer) -> bool {
+        match self {
+            Ownership::All => true,
+            Ownership::Team(team) => customer.team == *team,
+        }
+    }
+
+    /// The team of a customer created by the principal.
+    pub fn team_for(&self, input: &CustomerInput) -> Option<String> {
+        match self {
+            Ownership::All => input.team.clone(),
+            Ownership::Team(team) => team.clone(),
+        }
+    }
+}
+
+impl From<CustomerDocument> for Customer {
+    fn from(customer_doc: CustomerDocument) -> Self {
+        // transform ObjectId to String
+        Customer {
+            id: customer_doc.id.to_string(),
+            name: customer_doc.name,
+            created_at: customer_doc.created_at.to_string(),
+            team: customer_doc.team,
+        }
+    }
 }
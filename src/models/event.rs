@@ -0,0 +1,65 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::models::customer::{Customer, Ownership};
+
+/// Kind of change applied to a customer document
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomerEventKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl CustomerEventKind {
+    pub const ALL: [CustomerEventKind; 3] = [
+        CustomerEventKind::Created,
+        CustomerEventKind::Updated,
+        CustomerEventKind::Deleted,
+    ];
+
+    /// Name used as the SSE `event` field.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CustomerEventKind::Created => "created",
+            CustomerEventKind::Updated => "updated",
+            CustomerEventKind::Deleted => "deleted",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct CustomerEvent {
+    /// `<epoch>-<sequence number>` of the event, usable as `Last-Event-ID`,
+    /// the epoch changing whenever the server restarts
+    pub id: String,
+    /// Sequence number of the event in the feed that published it
+    #[serde(skip)]
+    pub seq: u64,
+    /// created, updated or deleted
+    pub kind: CustomerEventKind,
+    /// _id of the customer document
+    #[serde(rename = "customerId")]
+    pub customer_id: String,
+    /// customer document after the change, if still available
+    pub customer: Option<Customer>,
+    /// tenant of the customer, when tenancy is enabled
+    #[serde(rename = "tenantId", default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+}
+
+impl CustomerEvent {
+    /// Whether the event concerns a customer of `tenant` in scope. Events
+    /// without the document, such as deletions reported by a change stream,
+    /// are only visible to admins of deployments without tenancy.
+    pub fn is_visible_to(&self, tenant: Option<&str>, ownership: &Ownership) -> bool {
+        if self.tenant_id.as_deref() != tenant {
+            return false;
+        }
+        match &self.customer {
+            Some(customer) => ownership.includes(customer),
+            None => *ownership == Ownership::All,
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use rocket_okapi::{
+    gen::OpenApiGenerator, okapi::openapi3::Responses, response::OpenApiResponderInner,
+    util::add_schema_response, JsonSchema, OpenApiError,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    /// How long the check took, in milliseconds.
+    #[serde(rename = "latencyMs", skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<String>,
+}
+
+impl ComponentHealth {
+    pub fn up(details: Option<String>) -> ComponentHealth {
+        ComponentHealth {
+            status: HealthStatus::Up,
+            latency_ms: None,
+            details,
+        }
+    }
+
+    pub fn down(details: String) -> ComponentHealth {
+        ComponentHealth {
+            status: HealthStatus::Down,
+            latency_ms: None,
+            details: Some(details),
+        }
+    }
+}
+
+/// Health of the service, answered with 200 when up and 503 when down.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct HealthReport {
+    pub status: HealthStatus,
+    pub components: BTreeMap<String, ComponentHealth>,
+}
+
+impl HealthReport {
+    /// Up when every component is up.
+    pub fn new(components: BTreeMap<String, ComponentHealth>) -> HealthReport {
+        let status = if components
+            .values()
+            .all(|component| component.status == HealthStatus::Up)
+        {
+            HealthStatus::Up
+        } else {
+            HealthStatus::Down
+        };
+        HealthReport { status, components }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for HealthReport {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let status = match self.status {
+            HealthStatus::Up => Status::Ok,
+            HealthStatus::Down => Status::ServiceUnavailable,
+        };
+        (status, Json(self)).respond_to(req)
+    }
+}
+
+impl OpenApiResponderInner for HealthReport {
+    fn responses(gen: &mut OpenApiGenerator) -> Result<Responses, OpenApiError> {
+        let mut responses = Responses::default();
+        let schema = gen.json_schema::<HealthReport>();
+        add_schema_response(&mut responses, 200, "application/json", schema.clone())?;
+        add_schema_response(&mut responses, 503, "application/json", schema)?;
+        Ok(responses)
+    }
+}
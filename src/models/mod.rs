@@ -1,2 +1,8 @@
+pub mod api_key;
 pub mod customer;
+pub mod event;
+pub mod health;
+pub mod oauth;
 pub mod response;
+pub mod subscription;
+pub mod webhook;
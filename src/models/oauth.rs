@@ -0,0 +1,216 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::{self, oid::ObjectId};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::models::api_key::Scope;
+
+/// A client allowed to obtain tokens with the client-credentials grant.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OAuthClientDocument {
+    /// Document Id
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    /// public identifier, `rrc_<12 hex digits>`
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    /// what the client is used for
+    pub name: String,
+    /// who the client was registered for
+    pub owner: String,
+    /// HMAC-SHA256 of the secret, keyed with the pepper
+    #[serde(rename = "secretHash")]
+    pub secret_hash: String,
+    /// scopes tokens of the client may be granted
+    pub scopes: Vec<Scope>,
+    /// team owning the customers its tokens may access, unless granted `admin`
+    #[serde(default)]
+    pub team: Option<String>,
+    /// tenant its tokens are bound to; when absent, any for admins, the default one otherwise
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// revokedAt
+    #[serde(rename = "revokedAt", default)]
+    pub revoked_at: Option<bson::DateTime>,
+    /// createdAt
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct OAuthClientInfo {
+    /// Document Id
+    #[serde(rename = "_id")]
+    pub id: String,
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    /// what the client is used for
+    pub name: String,
+    /// who the client was registered for
+    pub owner: String,
+    pub scopes: Vec<Scope>,
+    /// team owning the customers its tokens may access, unless granted `admin`
+    pub team: Option<String>,
+    /// tenant its tokens are bound to; when absent, any for admins, the default one otherwise
+    pub tenant: Option<String>,
+    /// revokedAt
+    #[serde(rename = "revokedAt")]
+    pub revoked_at: Option<String>,
+    /// createdAt
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+impl From<OAuthClientDocument> for OAuthClientInfo {
+    fn from(client_doc: OAuthClientDocument) -> Self {
+        // the hash is never sent back
+        OAuthClientInfo {
+            id: client_doc.id.to_string(),
+            client_id: client_doc.client_id,
+            name: client_doc.name,
+            owner: client_doc.owner,
+            scopes: client_doc.scopes,
+            team: client_doc.team,
+            tenant: client_doc.tenant,
+            revoked_at: client_doc
+                .revoked_at
+                .map(|date| date.to_chrono().to_rfc3339()),
+            created_at: client_doc.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// A client just registered, the only response carrying its secret.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct RegisteredOAuthClient {
+    /// the secret to authenticate with, not retrievable later
+    #[serde(rename = "clientSecret")]
+    pub client_secret: String,
+    #[serde(flatten)]
+    pub info: OAuthClientInfo,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct OAuthClientInput {
+    /// what the client is used for
+    pub name: String,
+    /// who the client is registered for
+    pub owner: String,
+    pub scopes: Vec<Scope>,
+    /// team owning the customers its tokens may access, unless granted `admin`
+    #[serde(default)]
+    pub team: Option<String>,
+    /// tenant its tokens are bound to; when absent, any for admins, the default one otherwise.
+    /// Admins bound to a tenant can only register clients of their tenant.
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+/// A revoked access token, kept until it would have expired anyway.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RevokedTokenDocument {
+    /// `jti` claim of the token
+    #[serde(rename = "_id")]
+    pub id: String,
+    /// expiresAt, when the document is removed
+    #[serde(rename = "expiresAt")]
+    pub expires_at: bson::DateTime,
+}
+
+/// Claims of the access tokens issued by `/oauth/token`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AccessClaims {
+    pub iss: String,
+    pub aud: String,
+    /// the client id
+    pub sub: String,
+    pub client_id: String,
+    /// granted scopes, space separated
+    pub scope: String,
+    /// team of the client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<String>,
+    /// tenant of the client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String,
+}
+
+/// `application/x-www-form-urlencoded` body of a token request (RFC 6749).
+#[derive(Debug, FromForm, JsonSchema)]
+pub struct TokenRequest {
+    /// only `client_credentials` is supported
+    pub grant_type: String,
+    /// when not sent with HTTP Basic authentication
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    /// space separated subset of the scopes of the client, all of them when absent
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct TokenResponse {
+    pub access_token: String,
+    /// always `Bearer`
+    pub token_type: String,
+    /// seconds until the token expires
+    pub expires_in: u64,
+    /// granted scopes, space separated
+    pub scope: String,
+}
+
+/// Body of introspection (RFC 7662) and revocation (RFC 7009) requests;
+/// `token_type_hint` is ignored, only access tokens are issued.
+#[derive(Debug, FromForm, JsonSchema)]
+pub struct TokenInput {
+    pub token: String,
+    /// when not sent with HTTP Basic authentication
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// Introspection response (RFC 7662): only `active` for tokens that are not.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Default)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+}
+
+impl From<AccessClaims> for IntrospectionResponse {
+    fn from(claims: AccessClaims) -> Self {
+        IntrospectionResponse {
+            active: true,
+            scope: Some(claims.scope),
+            client_id: Some(claims.client_id),
+            token_type: Some("Bearer".to_owned()),
+            exp: Some(claims.exp),
+            iat: Some(claims.iat),
+            sub: Some(claims.sub),
+            aud: Some(claims.aud),
+            iss: Some(claims.iss),
+            jti: Some(claims.jti),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::event::{CustomerEvent, CustomerEventKind};
+
+/// Message sent by a client over the customer WebSocket
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum ClientMessage {
+    /// authenticate with an API key, when not given in the `x-api-key` header
+    Auth {
+        #[serde(rename = "apiKey")]
+        api_key: String,
+    },
+    /// start receiving events matching the filter
+    Subscribe(SubscriptionFilter),
+    /// stop receiving events matching the filter
+    Unsubscribe(SubscriptionFilter),
+}
+
+/// Customer ids and/or event kinds to (un)subscribe to.
+/// No ids means every customer, no kinds means every kind.
+#[derive(Debug, Deserialize, Default)]
+pub struct SubscriptionFilter {
+    #[serde(default)]
+    pub ids: Vec<String>,
+    #[serde(default)]
+    pub kinds: Vec<CustomerEventKind>,
+}
+
+impl SubscriptionFilter {
+    fn kinds(&self) -> HashSet<CustomerEventKind> {
+        if self.kinds.is_empty() {
+            CustomerEventKind::ALL.into()
+        } else {
+            self.kinds.iter().copied().collect()
+        }
+    }
+}
+
+/// Message sent by the server over the customer WebSocket
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ServerMessage {
+    Ack {
+        action: String,
+        subscription: Subscription,
+    },
+    Event {
+        event: CustomerEvent,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Current subscription of a WebSocket connection
+#[derive(Debug, Serialize, Default, Clone, PartialEq, Eq)]
+pub struct Subscription {
+    /// kinds of events received for every customer, none when empty
+    pub all: HashSet<CustomerEventKind>,
+    /// kinds of events received for these customer ids in place of `all`,
+    /// none when empty
+    pub ids: HashMap<String, HashSet<CustomerEventKind>>,
+}
+
+impl Subscription {
+    pub fn subscribe(&mut self, filter: SubscriptionFilter) {
+        let kinds = filter.kinds();
+        if filter.ids.is_empty() {
+            self.all.extend(&kinds);
+            for id_kinds in self.ids.values_mut() {
+                id_kinds.extend(&kinds);
+            }
+        }
+        for id in filter.ids {
+            let all = &self.all;
+            self.ids
+                .entry(id)
+                .or_insert_with(|| all.clone())
+                .extend(&kinds);
+        }
+        self.ids.retain(|_, id_kinds| *id_kinds != self.all);
+    }
+
+    pub fn unsubscribe(&mut self, filter: SubscriptionFilter) {
+        let kinds = filter.kinds();
+        if filter.ids.is_empty() {
+            self.all.retain(|kind| !kinds.contains(kind));
+            for id_kinds in self.ids.values_mut() {
+                id_kinds.retain(|kind| !kinds.contains(kind));
+            }
+        }
+        for id in filter.ids {
+            let all = &self.all;
+            self.ids
+                .entry(id)
+                .or_insert_with(|| all.clone())
+                .retain(|kind| !kinds.contains(kind));
+        }
+        self.ids.retain(|_, id_kinds| *id_kinds != self.all);
+    }
+
+    pub fn matches(&self, event: &CustomerEvent) -> bool {
+        self.ids
+            .get(&event.customer_id)
+            .unwrap_or(&self.all)
+            .contains(&event.kind)
+    }
+}
@@ -0,0 +1,159 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{customer::Customer, event::CustomerEventKind};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookDocument {
+    /// Document Id
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    /// URL receiving the events
+    pub url: String,
+    /// kinds of customer events to send, all when empty
+    pub events: Vec<CustomerEventKind>,
+    /// shared secret used to sign the payloads
+    pub secret: String,
+    /// whether events are sent to this webhook
+    pub active: bool,
+    /// tenant whose customer events are sent, when tenancy is enabled
+    #[serde(rename = "tenantId", default)]
+    pub tenant_id: Option<String>,
+    /// createdAt
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct Webhook {
+    /// Document Id
+    #[serde(rename = "_id")]
+    pub id: String,
+    /// URL receiving the events
+    pub url: String,
+    /// kinds of customer events to send, all when empty
+    pub events: Vec<CustomerEventKind>,
+    /// whether events are sent to this webhook
+    pub active: bool,
+    /// tenant whose customer events are sent, when tenancy is enabled
+    #[serde(rename = "tenantId", default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+    /// createdAt
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+}
+
+impl From<WebhookDocument> for Webhook {
+    fn from(webhook_doc: WebhookDocument) -> Self {
+        // the secret is never sent back
+        Webhook {
+            id: webhook_doc.id.to_string(),
+            url: webhook_doc.url,
+            events: webhook_doc.events,
+            active: webhook_doc.active,
+            tenant_id: webhook_doc.tenant_id,
+            created_at: webhook_doc.created_at.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct WebhookInput {
+    /// HTTP(S) URL receiving the events, not on a private address unless the
+    /// deployment allows it
+    pub url: String,
+    /// kinds of customer events to send, all when empty
+    #[serde(default)]
+    pub events: Vec<CustomerEventKind>,
+    /// shared secret used to sign the payloads
+    pub secret: String,
+    /// whether events are sent to this webhook
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+/// Customer event waiting in the outbox to be sent to webhooks
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OutboxEventDocument {
+    /// Document Id
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub kind: CustomerEventKind,
+    #[serde(rename = "customerId")]
+    pub customer_id: String,
+    pub customer: Option<Customer>,
+    #[serde(rename = "tenantId", default)]
+    pub tenant_id: Option<String>,
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "createdAt"
+    )]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Body POSTed to webhook URLs
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone)]
+pub struct WebhookPayload {
+    /// Id of the event, identical across retries
+    pub id: String,
+    /// created, updated or deleted
+    pub kind: CustomerEventKind,
+    /// _id of the customer document
+    #[serde(rename = "customerId")]
+    pub customer_id: String,
+    /// customer document after the change, if still available
+    pub customer: Option<Customer>,
+    /// tenant of the customer, when tenancy is enabled
+    #[serde(rename = "tenantId", default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+    /// when the change happened
+    #[serde(rename = "occurredAt")]
+    pub occurred_at: String,
+}
+
+impl From<OutboxEventDocument> for WebhookPayload {
+    fn from(event: OutboxEventDocument) -> Self {
+        WebhookPayload {
+            id: event.id.to_string(),
+            kind: event.kind,
+            customer_id: event.customer_id,
+            customer: event.customer,
+            tenant_id: event.tenant_id,
+            occurred_at: event.created_at.to_rfc3339(),
+        }
+    }
+}
+
+/// Pending delivery of one event to one webhook
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookDeliveryDocument {
+    /// Document Id
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    /// outbox event delivered, absent from deliveries queued before it was recorded
+    #[serde(rename = "eventId", default)]
+    pub event_id: Option<ObjectId>,
+    #[serde(rename = "webhookId")]
+    pub webhook_id: ObjectId,
+    pub kind: CustomerEventKind,
+    /// serialized `WebhookPayload`, kept as is so every attempt sends the same body
+    pub payload: String,
+    /// number of failed attempts so far
+    pub attempts: u32,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[serde(
+        with = "bson::serde_helpers::chrono_datetime_as_bson_datetime",
+        rename = "nextAttemptAt"
+    )]
+    pub next_attempt_at: DateTime<Utc>,
+}
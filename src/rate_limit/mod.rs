@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use mongodb::Database;
+use serde::Deserialize;
+
+use crate::db::{rate_limit, tenant::Tenant};
+use crate::request_guards::principal::Principal;
+
+/// Idle buckets are dropped once the in-memory store holds this many.
+const MAX_BUCKETS: usize = 10_000;
+
+/// A token bucket: `capacity` requests at once, refilled at `per_second`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct Limit {
+    pub capacity: u32,
+    pub per_second: f64,
+}
+
+/// Callers are limited by client IP, or by key or token subject once they
+/// send a valid API key or bearer token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tier {
+    Anonymous,
+    ApiKey,
+}
+
+/// Limits overriding the defaults for a route or a tenant, which then gets
+/// its own buckets.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RouteLimits {
+    pub anonymous: Option<Limit>,
+    pub api_key: Option<Limit>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    /// Buckets local to this instance.
+    Memory,
+    /// Buckets in the `rate_limit` collection, shared by every instance.
+    Mongo,
+}
+
+/// `[default.rate_limit]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub store: StoreKind,
+    pub anonymous: Limit,
+    pub api_key: Limit,
+    /// Keyed by method and route path, e.g. `GET /customer`.
+    pub routes: HashMap<String, RouteLimits>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            enabled: true,
+            store: StoreKind::Memory,
+            anonymous: Limit {
+                capacity: 60,
+                per_second: 1.0,
+            },
+            api_key: Limit {
+                capacity: 600,
+                per_second: 10.0,
+            },
+            routes: HashMap::new(),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// The limit of `tier` on `route`, else the one of `tenant`, else the
+    /// default, and whether the route has buckets of its own.
+    pub fn limit(&self, route: &str, tier: Tier, tenant: Option<&RouteLimits>) -> (Limit, bool) {
+        if let Some(limit) = self.routes.get(route).and_then(|limits| limits.get(tier)) {
+            return (limit, true);
+        }
+        match (tenant.and_then(|limits| limits.get(tier)), tier) {
+            (Some(limit), _) => (limit, false),
+            (None, Tier::Anonymous) => (self.anonymous, false),
+            (None, Tier::ApiKey) => (self.api_key, false),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let route_limits = self.routes.values().try_for_each(RouteLimits::validate);
+        [self.anonymous, self.api_key]
+            .iter()
+            .try_for_each(Limit::validate)
+            .and(route_limits)
+    }
+}
+
+impl Limit {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.capacity == 0 || self.per_second <= 0.0 {
+            return Err("limits need a capacity and a positive per_second".into());
+        }
+        Ok(())
+    }
+}
+
+impl RouteLimits {
+    pub fn get(&self, tier: Tier) -> Option<Limit> {
+        match tier {
+            Tier::Anonymous => self.anonymous,
+            Tier::ApiKey => self.api_key,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        [self.anonymous, self.api_key]
+            .iter()
+            .flatten()
+            .try_for_each(Limit::validate)
+    }
+}
+
+/// Outcome of taking a token from a bucket.
+#[derive(Debug, Clone, Copy)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset: u64,
+    /// Seconds until a request would be allowed, 0 when it was.
+    pub retry_after: u64,
+}
+
+impl Decision {
+    /// Decision for a bucket left with `tokens` after the request.
+    pub fn new(limit: Limit, tokens: f64, allowed: bool) -> Decision {
+        let retry_after = if allowed {
+            0
+        } else {
+            ((1.0 - tokens) / limit.per_second).ceil() as u64
+        };
+        Decision {
+            allowed,
+            limit: limit.capacity,
+            remaining: tokens.floor() as u32,
+            reset: ((f64::from(limit.capacity) - tokens) / limit.per_second).ceil() as u64,
+            retry_after,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: Limit, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(f64::from(limit.capacity));
+        self.updated = now;
+    }
+}
+
+enum Store {
+    Memory(Mutex<HashMap<String, Bucket>>),
+    Mongo(Database),
+}
+
+/// Token buckets of every client, kept by the configured store, for REST
+/// and gRPC alike. Clones share the same buckets.
+#[derive(Clone)]
+pub struct RateLimiter {
+    pub config: RateLimitConfig,
+    store: Arc<Store>,
+}
+
+impl RateLimiter {
+    pub fn memory(config: RateLimitConfig) -> RateLimiter {
+        RateLimiter {
+            config,
+            store: Arc::new(Store::Memory(Mutex::new(HashMap::new()))),
+        }
+    }
+
+    pub fn mongo(config: RateLimitConfig, db: Database) -> RateLimiter {
+        RateLimiter {
+            config,
+            store: Arc::new(Store::Mongo(db)),
+        }
+    }
+
+    /// Take a token from the bucket of `principal`, or of the client `ip`
+    /// without valid credentials, on `route` of `tenant`. `None` when the
+    /// store is unavailable, which must not take the API down.
+    pub async fn check(
+        &self,
+        route: &str,
+        principal: Option<&Principal>,
+        ip: Option<IpAddr>,
+        tenant: Option<&Tenant>,
+    ) -> Option<Decision> {
+        let (tier, mut client) = match principal {
+            Some(principal) => (Tier::ApiKey, principal.identity()),
+            None => (
+                Tier::Anonymous,
+                ip.map_or_else(|| "unknown".to_owned(), |ip| ip.to_string()),
+            ),
+        };
+        if let Some(id) = tenant.and_then(Tenant::id) {
+            client = format!("{}/{}", id, client);
+        }
+
+        let (limit, per_route) = self.config.limit(route, tier, tenant.map(Tenant::limits));
+        let key = if per_route {
+            format!("{:?}:{}:{}", tier, client, route)
+        } else {
+            format!("{:?}:{}", tier, client)
+        };
+        match self.take(&key, limit).await {
+            Ok(decision) => Some(decision),
+            Err(error) => {
+                tracing::warn!(%error, "rate limit store unavailable");
+                None
+            }
+        }
+    }
+
+    /// Take a token from the bucket `key`.
+    pub async fn take(&self, key: &str, limit: Limit) -> mongodb::error::Result<Decision> {
+        match self.store.as_ref() {
+            Store::Memory(buckets) => Ok(take_from_memory(buckets, key, limit)),
+            Store::Mongo(db) => rate_limit::take_token(db, key, limit).await,
+        }
+    }
+}
+
+fn take_from_memory(buckets: &Mutex<HashMap<String, Bucket>>, key: &str, limit: Limit) -> Decision {
+    let now = Instant::now();
+    let mut buckets = buckets.lock().unwrap();
+    if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(key) {
+        // full buckets hold no state worth keeping
+        buckets.retain(|_, bucket| {
+            bucket.refill(limit, now);
+            bucket.tokens < f64::from(limit.capacity)
+        });
+    }
+
+    let bucket = buckets.entry(key.to_owned()).or_insert(Bucket {
+        tokens: f64::from(limit.capacity),
+        updated: now,
+    });
+    bucket.refill(limit, now);
+    let allowed = bucket.tokens >= 1.0;
+    if allowed {
+        bucket.tokens -= 1.0;
+    }
+    Decision::new(limit, bucket.tokens, allowed)
+}
@@ -0,0 +1,101 @@
+use std::marker::PhantomData;
+
+use rocket::http::Status;
+use rocket::outcome::try_outcome;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::okapi::openapi3::{RefOr, Responses};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+use crate::db::tenant::Tenant;
+use crate::errors::response::{forbidden_response, MyError};
+use crate::models::api_key::Scope;
+use crate::request_guards::basic::{security_input, AuthError};
+use crate::request_guards::principal::Principal;
+
+/// Scope a route requires, as the type parameter of `Authorized`.
+pub trait RequiredScope {
+    const SCOPE: Scope;
+}
+
+pub struct CustomerRead;
+pub struct CustomerWrite;
+pub struct CustomerDelete;
+pub struct Admin;
+
+impl RequiredScope for CustomerRead {
+    const SCOPE: Scope = Scope::CustomerRead;
+}
+
+impl RequiredScope for CustomerWrite {
+    const SCOPE: Scope = Scope::CustomerWrite;
+}
+
+impl RequiredScope for CustomerDelete {
+    const SCOPE: Scope = Scope::CustomerDelete;
+}
+
+impl RequiredScope for Admin {
+    const SCOPE: Scope = Scope::Admin;
+}
+
+/// A `Principal` granted the scope `S`: fails with 401 without valid
+/// credentials and with 403 when they lack the scope.
+pub struct Authorized<S: RequiredScope> {
+    pub principal: Principal,
+    scope: PhantomData<fn() -> S>,
+}
+
+#[rocket::async_trait]
+impl<'r, S: RequiredScope> FromRequest<'r> for Authorized<S> {
+    type Error = AuthError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let principal = try_outcome!(req.guard::<Principal>().await);
+        if principal.has_scope(S::SCOPE) {
+            Outcome::Success(Authorized {
+                principal,
+                scope: PhantomData,
+            })
+        } else {
+            Outcome::Error((Status::Forbidden, AuthError::Forbidden))
+        }
+    }
+}
+
+#[catch(401)]
+pub fn unauthorized() -> MyError {
+    MyError::build(
+        401,
+        Some("Missing or invalid API key or bearer token.".to_string()),
+    )
+}
+
+#[catch(403)]
+pub fn forbidden(req: &Request<'_>) -> MyError {
+    let description = match Tenant::error_of(req) {
+        Some(error) => error.to_string(),
+        None => "The credentials lack the required scope.".to_string(),
+    };
+    MyError::build(403, Some(description))
+}
+
+impl<'a, S: RequiredScope> OpenApiFromRequest<'a> for Authorized<S> {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(security_input(vec![S::SCOPE.as_str().to_owned()]))
+    }
+
+    fn get_responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut responses = Principal::get_responses(gen)?;
+        responses
+            .responses
+            .insert("403".to_owned(), RefOr::Object(forbidden_response(gen)));
+        Ok(responses)
+    }
+}
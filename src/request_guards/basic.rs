@@ -1,3 +1,4 @@
+use mongodb::bson::oid::ObjectId;
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome, Request};
 use rocket_okapi::okapi;
@@ -8,64 +9,133 @@ use rocket_okapi::{
     gen::OpenApiGenerator,
     request::{OpenApiFromRequest, RequestHeaderInput},
 };
-use std::env;
+use sha2::{Digest, Sha256};
 
+use crate::auth::ApiKeyStore;
 use crate::errors::response::unauthorized_response;
+use crate::models::api_key::{ApiKeyDocument, Role, Scope};
 
+/// The key a request was made with, resolved by `ApiKeyStore`.
 #[allow(dead_code)]
-pub struct ApiKey(String);
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    /// `None` for the bootstrap key of `auth.api_key`.
+    pub id: Option<ObjectId>,
+    pub name: String,
+    pub owner: String,
+    /// Its own scopes and those of its roles.
+    pub scopes: Vec<Scope>,
+    pub roles: Vec<Role>,
+    pub team: Option<String>,
+    /// The tenant the key is bound to, if any.
+    pub tenant: Option<String>,
+    identity: String,
+}
 
-#[derive(Debug)]
-pub enum ApiKeyError {
+/// Why a request carries no usable API key or bearer token.
+#[derive(Debug, Clone)]
+pub enum AuthError {
     Missing,
     Invalid,
+    /// The key or the signing keys could not be looked up.
+    Unavailable,
+    /// The credentials lack a scope required by the route.
+    Forbidden,
+}
+
+impl ApiKey {
+    /// The key configured as `auth.api_key`, an admin.
+    pub fn bootstrap(key: &str) -> ApiKey {
+        let digest = Sha256::digest(key.as_bytes());
+        ApiKey {
+            id: None,
+            name: "bootstrap".to_owned(),
+            owner: "configuration".to_owned(),
+            scopes: Scope::ALL.to_vec(),
+            roles: vec![Role::Admin],
+            team: None,
+            tenant: None,
+            identity: format!("sha256:{}", &hex::encode(digest)[..12]),
+        }
+    }
+
+    pub fn stored(document: &ApiKeyDocument) -> ApiKey {
+        ApiKey {
+            id: Some(document.id),
+            name: document.name.clone(),
+            owner: document.owner.clone(),
+            scopes: Role::grant(&document.scopes, &document.roles),
+            roles: document.roles.clone(),
+            team: document.team.clone(),
+            tenant: document.tenant.clone(),
+            identity: format!("key:{}", document.prefix),
+        }
+    }
+
+    /// Identifies the key in logs without revealing it.
+    pub fn identity(&self) -> String {
+        self.identity.clone()
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
 }
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for ApiKey {
-    type Error = ApiKeyError;
+    type Error = AuthError;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        fn is_valid(key: &str) -> bool {
-            let api_key = env::var("API_KEY").expect("env.API_KEY is not found.");
-
-            key == api_key
-        }
-
-        match req.headers().get_one("x-api-key") {
-            None => Outcome::Error((Status::Unauthorized, ApiKeyError::Missing)),
-            Some(key) if is_valid(key) => Outcome::Success(ApiKey(key.to_owned())),
-            Some(_) => Outcome::Error((Status::Unauthorized, ApiKeyError::Invalid)),
+        // resolved once per request, however many guards ask for it
+        let result = req
+            .local_cache_async(async {
+                let Some(store) = req.rocket().state::<ApiKeyStore>() else {
+                    return Err(AuthError::Invalid);
+                };
+                match req.headers().get_one("x-api-key") {
+                    None => Err(AuthError::Missing),
+                    Some(key) => store.verify(key).await,
+                }
+            })
+            .await;
+        match result {
+            Ok(api_key) => Outcome::Success(api_key.clone()),
+            Err(AuthError::Unavailable) => {
+                Outcome::Error((Status::ServiceUnavailable, AuthError::Unavailable))
+            }
+            Err(error) => Outcome::Error((Status::Unauthorized, error.clone())),
         }
     }
 }
 
+/// The `ApiKey` security scheme, requiring `scopes` on the route.
+pub fn security_input(scopes: Vec<String>) -> RequestHeaderInput {
+    // Setup global requirement for Security scheme
+    let security_scheme = SecurityScheme {
+        description: Some("Requires an API key to access".to_owned()),
+
+        data: SecuritySchemeData::ApiKey {
+            name: "x-api-key".to_owned(),
+            location: "header".to_owned(),
+        },
+        extensions: Object::default(),
+    };
+    // Add the requirement for this route/endpoint
+    // This can change between routes.
+    let mut security_req = SecurityRequirement::new();
+    // Each security requirement needs to be met before access is allowed.
+    security_req.insert("ApiKey".to_owned(), scopes);
+    RequestHeaderInput::Security("ApiKey".to_owned(), security_scheme, security_req)
+}
+
 impl<'a> OpenApiFromRequest<'a> for ApiKey {
     fn from_request_input(
         _gen: &mut OpenApiGenerator,
         _name: String,
         _required: bool,
     ) -> rocket_okapi::Result<RequestHeaderInput> {
-        // Setup global requirement for Security scheme
-        let security_scheme = SecurityScheme {
-            description: Some("Requires an API key to access".to_owned()),
-
-            data: SecuritySchemeData::ApiKey {
-                name: "x-api-key".to_owned(),
-                location: "header".to_owned(),
-            },
-            extensions: Object::default(),
-        };
-        // Add the requirement for this route/endpoint
-        // This can change between routes.
-        let mut security_req = SecurityRequirement::new();
-        // Each security requirement needs to be met before access is allowed.
-        security_req.insert("ApiKey".to_owned(), Vec::new());
-        Ok(RequestHeaderInput::Security(
-            "ApiKey".to_owned(),
-            security_scheme,
-            security_req,
-        ))
+        Ok(security_input(Vec::new()))
     }
 
     fn get_responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
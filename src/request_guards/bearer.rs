@@ -0,0 +1,200 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::okapi;
+use rocket_okapi::okapi::openapi3::{
+    OAuthFlows, Object, OpenApi, RefOr, Responses, SecurityRequirement, SecurityScheme,
+    SecuritySchemeData,
+};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+use crate::auth::jwt::JwtVerifier;
+use crate::errors::response::unauthorized_response;
+use crate::models::api_key::{Role, Scope};
+use crate::request_guards::basic::AuthError;
+
+/// A token sent as `Authorization: Bearer`, verified by `JwtVerifier`.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct BearerToken {
+    /// The `sub` claim.
+    pub subject: String,
+    pub issuer: String,
+    /// Its own scopes and those of its roles.
+    pub scopes: Vec<Scope>,
+    pub roles: Vec<Role>,
+    pub team: Option<String>,
+    /// The tenant the token is bound to, if any.
+    pub tenant: Option<String>,
+    identity: String,
+}
+
+impl BearerToken {
+    pub fn new(
+        subject: &str,
+        issuer: &str,
+        scopes: Vec<Scope>,
+        roles: Vec<Role>,
+        team: Option<String>,
+        tenant: Option<String>,
+    ) -> BearerToken {
+        BearerToken {
+            subject: subject.to_owned(),
+            issuer: issuer.to_owned(),
+            scopes: Role::grant(&scopes, &roles),
+            roles,
+            team,
+            tenant,
+            identity: format!("jwt:{}", subject),
+        }
+    }
+
+    /// The token of an `Authorization` header value, if it is a bearer token.
+    pub fn parse(authorization: &str) -> Option<&str> {
+        let (scheme, token) = authorization.split_once(' ')?;
+        scheme
+            .eq_ignore_ascii_case("bearer")
+            .then(|| token.trim())
+            .filter(|token| !token.is_empty())
+    }
+
+    /// Identifies the token in logs without revealing it.
+    pub fn identity(&self) -> String {
+        self.identity.clone()
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BearerToken {
+    type Error = AuthError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        // resolved once per request, however many guards ask for it
+        let result = req
+            .local_cache_async(async {
+                let Some(token) = req
+                    .headers()
+                    .get_one("authorization")
+                    .and_then(BearerToken::parse)
+                else {
+                    return Err(AuthError::Missing);
+                };
+                match req.rocket().state::<JwtVerifier>() {
+                    Some(verifier) => verifier.verify(token).await,
+                    // bearer tokens are disabled
+                    None => Err(AuthError::Invalid),
+                }
+            })
+            .await;
+        match result {
+            Ok(token) => Outcome::Success(token.clone()),
+            Err(AuthError::Unavailable) => {
+                Outcome::Error((Status::ServiceUnavailable, AuthError::Unavailable))
+            }
+            Err(error) => Outcome::Error((Status::Unauthorized, error.clone())),
+        }
+    }
+}
+
+fn security_scheme() -> SecurityScheme {
+    SecurityScheme {
+        description: Some(
+            "Requires a JWT signed by a key of the configured JWKS, when auth.jwt is enabled"
+                .to_owned(),
+        ),
+        data: SecuritySchemeData::Http {
+            scheme: "bearer".to_owned(),
+            bearer_format: Some("JWT".to_owned()),
+        },
+        extensions: Object::default(),
+    }
+}
+
+/// The client-credentials flow of `/oauth/token`, for Swagger UI to obtain
+/// tokens with.
+fn oauth2_security_scheme() -> SecurityScheme {
+    SecurityScheme {
+        description: Some("Access tokens of registered OAuth2 clients".to_owned()),
+        data: SecuritySchemeData::OAuth2 {
+            flows: OAuthFlows::ClientCredentials {
+                token_url: "/oauth/token".to_owned(),
+                refresh_url: None,
+                scopes: Scope::ALL
+                    .iter()
+                    .map(|scope| (scope.as_str().to_owned(), scope.description().to_owned()))
+                    .collect(),
+                extensions: Object::default(),
+            },
+        },
+        extensions: Object::default(),
+    }
+}
+
+/// Register the `Bearer` and `OAuth2` schemes and offer them wherever the
+/// `ApiKey` scheme is required, with the same scopes. okapi gives a guard a
+/// single scheme, so the alternatives are added to the generated document.
+pub fn document(spec: &mut OpenApi) {
+    for item in spec.paths.values_mut() {
+        let operations = [
+            &mut item.get,
+            &mut item.put,
+            &mut item.post,
+            &mut item.delete,
+            &mut item.patch,
+        ];
+        for operation in operations.into_iter().flatten() {
+            let Some(security) = operation.security.as_mut() else {
+                continue;
+            };
+            let bearer: Vec<SecurityRequirement> = security
+                .iter()
+                .filter_map(|requirement| requirement.get("ApiKey"))
+                .flat_map(|scopes| {
+                    ["Bearer", "OAuth2"].map(|scheme| {
+                        let mut requirement = SecurityRequirement::new();
+                        requirement.insert(scheme.to_owned(), scopes.clone());
+                        requirement
+                    })
+                })
+                .collect();
+            security.extend(bearer);
+        }
+    }
+    let schemes = &mut spec
+        .components
+        .get_or_insert_with(Default::default)
+        .security_schemes;
+    schemes.insert("Bearer".to_owned(), RefOr::Object(security_scheme()));
+    schemes.insert("OAuth2".to_owned(), RefOr::Object(oauth2_security_scheme()));
+}
+
+impl<'a> OpenApiFromRequest<'a> for BearerToken {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        let mut security_req = SecurityRequirement::new();
+        security_req.insert("Bearer".to_owned(), Vec::new());
+        Ok(RequestHeaderInput::Security(
+            "Bearer".to_owned(),
+            security_scheme(),
+            security_req,
+        ))
+    }
+
+    fn get_responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        Ok(Responses {
+            responses: okapi::map! {
+                "401".to_owned() => RefOr::Object(unauthorized_response(gen)),
+            },
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,74 @@
+use rocket::http::Status;
+use rocket::mtls::Certificate;
+use rocket::request::{FromRequest, Outcome, Request};
+
+use crate::auth::mtls::CertificateClient;
+use crate::config::AppConfig;
+use crate::models::api_key::{Role, Scope};
+use crate::request_guards::basic::AuthError;
+
+/// A client certificate verified during the TLS handshake, mapped to a
+/// client of `auth.mtls.clients` by the common name of its subject.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct ClientCertificate {
+    pub common_name: String,
+    /// Its own scopes and those of its roles.
+    pub scopes: Vec<Scope>,
+    pub roles: Vec<Role>,
+    pub team: Option<String>,
+    /// The tenant the client is bound to, if any.
+    pub tenant: Option<String>,
+    identity: String,
+}
+
+impl ClientCertificate {
+    pub fn new(common_name: &str, client: &CertificateClient) -> ClientCertificate {
+        ClientCertificate {
+            common_name: common_name.to_owned(),
+            scopes: Role::grant(&client.scopes, &client.roles),
+            roles: client.roles.clone(),
+            team: client.team.clone(),
+            tenant: client.tenant.clone(),
+            identity: format!("cert:{}", common_name),
+        }
+    }
+
+    /// Identifies the client in logs.
+    pub fn identity(&self) -> String {
+        self.identity.clone()
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientCertificate {
+    type Error = AuthError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(config) = req.rocket().state::<AppConfig>() else {
+            return Outcome::Error((Status::Unauthorized, AuthError::Missing));
+        };
+        // verified against `tls.mutual.ca_certs` by Rocket
+        let Outcome::Success(certificate) = req.guard::<Certificate<'_>>().await else {
+            return Outcome::Error((Status::Unauthorized, AuthError::Missing));
+        };
+        let principal = certificate
+            .subject()
+            .common_name()
+            .and_then(|common_name| config.auth.mtls.principal(common_name));
+        match principal {
+            Some(principal) => Outcome::Success(principal),
+            None => {
+                tracing::info!(
+                    subject = %certificate.subject(),
+                    "client certificate not mapped to a client"
+                );
+                Outcome::Error((Status::Unauthorized, AuthError::Invalid))
+            }
+        }
+    }
+}
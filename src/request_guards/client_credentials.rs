@@ -0,0 +1,77 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rocket::http::{RawStr, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::okapi::openapi3::{
+    Object, SecurityRequirement, SecurityScheme, SecuritySchemeData,
+};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+/// Client id and secret sent with HTTP Basic authentication to the `/oauth`
+/// endpoints, which also accept them as form fields.
+pub struct ClientCredentials {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl ClientCredentials {
+    /// Both parts are form-urlencoded before being joined (RFC 6749 2.3.1).
+    fn parse(authorization: &str) -> Option<ClientCredentials> {
+        let (scheme, encoded) = authorization.split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return None;
+        }
+        let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+        let (client_id, client_secret) = decoded.split_once(':')?;
+        Some(ClientCredentials {
+            client_id: RawStr::new(client_id).url_decode().ok()?.into_owned(),
+            client_secret: RawStr::new(client_secret).url_decode().ok()?.into_owned(),
+        })
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientCredentials {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req
+            .headers()
+            .get_one("authorization")
+            .and_then(ClientCredentials::parse)
+        {
+            Some(credentials) => Outcome::Success(credentials),
+            None => Outcome::Error((Status::Unauthorized, ())),
+        }
+    }
+}
+
+impl<'a> OpenApiFromRequest<'a> for ClientCredentials {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        let security_scheme = SecurityScheme {
+            description: Some(
+                "Client id and secret of a registered OAuth2 client, \
+                 or the client_id and client_secret fields"
+                    .to_owned(),
+            ),
+            data: SecuritySchemeData::Http {
+                scheme: "basic".to_owned(),
+                bearer_format: None,
+            },
+            extensions: Object::default(),
+        };
+        let mut security_req = SecurityRequirement::new();
+        security_req.insert("ClientSecretBasic".to_owned(), Vec::new());
+        Ok(RequestHeaderInput::Security(
+            "ClientSecretBasic".to_owned(),
+            security_scheme,
+            security_req,
+        ))
+    }
+}
@@ -0,0 +1,53 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::okapi;
+use rocket_okapi::okapi::openapi3::Responses;
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+use crate::db::DbStatus;
+use crate::errors::response::{service_unavailable_response, MyError};
+
+/// Fails with 503 while MongoDB has not answered after a degraded start.
+pub struct DbAvailable;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DbAvailable {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.rocket().state::<DbStatus>() {
+            Some(status) if !status.is_available() => {
+                Outcome::Error((Status::ServiceUnavailable, ()))
+            }
+            _ => Outcome::Success(DbAvailable),
+        }
+    }
+}
+
+#[catch(503)]
+pub fn service_unavailable() -> MyError {
+    MyError::build(503, Some("The database is not reachable yet.".to_string()))
+}
+
+impl<'a> OpenApiFromRequest<'a> for DbAvailable {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+
+    fn get_responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        use rocket_okapi::okapi::openapi3::RefOr;
+        Ok(Responses {
+            responses: okapi::map! {
+                "503".to_owned() => RefOr::Object(service_unavailable_response(gen)),
+            },
+            ..Default::default()
+        })
+    }
+}
@@ -1 +1,12 @@
+pub mod authorized;
 pub mod basic;
+pub mod bearer;
+pub mod client_certificate;
+pub mod client_credentials;
+pub mod db_available;
+pub mod last_event_id;
+pub mod principal;
+pub mod rate_limit;
+pub mod signature;
+pub mod tenant;
+pub mod trace_context;
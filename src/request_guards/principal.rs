@@ -0,0 +1,112 @@
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::okapi::openapi3::Responses;
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+use crate::auth::signature::SignatureHeader;
+use crate::models::api_key::Scope;
+use crate::models::customer::Ownership;
+use crate::request_guards::basic::{security_input, ApiKey, AuthError};
+use crate::request_guards::bearer::BearerToken;
+use crate::request_guards::client_certificate::ClientCertificate;
+use crate::request_guards::signature::SignedRequest;
+
+/// Who a request is made by: a bearer token when an `Authorization: Bearer`
+/// header is sent, the key a request is signed with when an
+/// `Authorization: HMAC-SHA256` header is, the `x-api-key` header when it is
+/// sent, the client certificate otherwise.
+#[derive(Debug, Clone)]
+pub enum Principal {
+    ApiKey(ApiKey),
+    Bearer(BearerToken),
+    Certificate(ClientCertificate),
+}
+
+impl Principal {
+    /// Identifies the caller in logs without revealing its credentials.
+    pub fn identity(&self) -> String {
+        match self {
+            Principal::ApiKey(key) => key.identity(),
+            Principal::Bearer(token) => token.identity(),
+            Principal::Certificate(certificate) => certificate.identity(),
+        }
+    }
+
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        match self {
+            Principal::ApiKey(key) => key.has_scope(scope),
+            Principal::Bearer(token) => token.has_scope(scope),
+            Principal::Certificate(certificate) => certificate.has_scope(scope),
+        }
+    }
+
+    pub fn team(&self) -> Option<&str> {
+        match self {
+            Principal::ApiKey(key) => key.team.as_deref(),
+            Principal::Bearer(token) => token.team.as_deref(),
+            Principal::Certificate(certificate) => certificate.team.as_deref(),
+        }
+    }
+
+    pub fn tenant(&self) -> Option<&str> {
+        match self {
+            Principal::ApiKey(key) => key.tenant.as_deref(),
+            Principal::Bearer(token) => token.tenant.as_deref(),
+            Principal::Certificate(certificate) => certificate.tenant.as_deref(),
+        }
+    }
+
+    /// The customers the caller may access: all of them with the `admin`
+    /// scope, those of its team otherwise.
+    pub fn ownership(&self) -> Ownership {
+        if self.has_scope(Scope::Admin) {
+            Ownership::All
+        } else {
+            Ownership::Team(self.team().map(str::to_owned))
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Principal {
+    type Error = AuthError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let authorization = req.headers().get_one("authorization");
+        let outcome = if authorization.and_then(BearerToken::parse).is_some() {
+            req.guard::<BearerToken>().await.map(Principal::Bearer)
+        } else if authorization.and_then(SignatureHeader::parse).is_some() {
+            req.guard::<SignedRequest>()
+                .await
+                .map(|signed| Principal::ApiKey(signed.key))
+        } else if req.headers().contains("x-api-key") {
+            req.guard::<ApiKey>().await.map(Principal::ApiKey)
+        } else if let Outcome::Success(certificate) = req.guard::<ClientCertificate>().await {
+            Outcome::Success(Principal::Certificate(certificate))
+        } else {
+            req.guard::<ApiKey>().await.map(Principal::ApiKey)
+        };
+        if let Outcome::Success(principal) = &outcome {
+            // remembered for the request log
+            req.local_cache(|| Some(principal.clone()));
+        }
+        outcome
+    }
+}
+
+impl<'a> OpenApiFromRequest<'a> for Principal {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        // `bearer::document` and `signature::document` add the alternatives
+        Ok(security_input(Vec::new()))
+    }
+
+    fn get_responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        ApiKey::get_responses(gen)
+    }
+}
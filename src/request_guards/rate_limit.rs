@@ -0,0 +1,82 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::okapi;
+use rocket_okapi::okapi::openapi3::Responses;
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+use crate::db::tenant::Tenants;
+use crate::errors::response::too_many_requests_response;
+use crate::rate_limit::{Decision, RateLimiter};
+use crate::request_guards::{principal::Principal, tenant::TenantHeader};
+
+/// Takes a token from the caller's bucket, failing with 429 once it is empty.
+/// The decision is kept in request-local state for the `RateLimit-*` headers.
+pub struct RateLimit;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RateLimit {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(limiter) = req.rocket().state::<RateLimiter>() else {
+            return Outcome::Success(RateLimit);
+        };
+        let route = req
+            .route()
+            .map(|route| format!("{} {}", route.method, route.uri.path()))
+            .unwrap_or_default();
+        let principal = match req.guard::<Option<Principal>>().await {
+            Outcome::Success(principal) => principal,
+            _ => None,
+        };
+        // resolved without the guard, the route reports its own tenant errors
+        let TenantHeader(header) = req.guard::<TenantHeader>().await.unwrap();
+        let tenant = req
+            .rocket()
+            .state::<Tenants>()
+            .and_then(|tenants| tenants.resolve(principal.as_ref(), header.as_deref()).ok());
+        let Some(decision) = limiter
+            .check(&route, principal.as_ref(), req.client_ip(), tenant.as_ref())
+            .await
+        else {
+            return Outcome::Success(RateLimit);
+        };
+
+        req.local_cache(|| Some(decision));
+        if decision.allowed {
+            Outcome::Success(RateLimit)
+        } else {
+            Outcome::Error((Status::TooManyRequests, ()))
+        }
+    }
+}
+
+impl RateLimit {
+    /// The decision taken for `request`, if it went through the guard.
+    pub fn decision(request: &Request<'_>) -> Option<Decision> {
+        *request.local_cache(|| None::<Decision>)
+    }
+}
+
+impl<'a> OpenApiFromRequest<'a> for RateLimit {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+
+    fn get_responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        use rocket_okapi::okapi::openapi3::RefOr;
+        Ok(Responses {
+            responses: okapi::map! {
+                "429".to_owned() => RefOr::Object(too_many_requests_response(gen)),
+            },
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,154 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::okapi;
+use rocket_okapi::okapi::openapi3::{
+    Object, OpenApi, RefOr, Responses, SecurityRequirement, SecurityScheme, SecuritySchemeData,
+};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+use crate::auth::signature::{body_hash, SignatureHeader, SignatureVerifier};
+use crate::errors::response::unauthorized_response;
+use crate::models::api_key::Scope;
+use crate::request_guards::basic::{ApiKey, AuthError};
+
+/// A request signed with the secret of an API key, verified by
+/// `SignatureVerifier`.
+#[derive(Debug, Clone)]
+pub struct SignedRequest {
+    /// The key, without the `admin` scope.
+    pub key: ApiKey,
+    /// The hash of the body the client signed.
+    body_hash: String,
+}
+
+impl SignedRequest {
+    /// The body hash `request` was signed with, if it was signed. Checked by
+    /// the data guards once they have read the body.
+    pub fn body_hash_of(request: &Request<'_>) -> Option<String> {
+        request
+            .local_cache(|| Err::<SignedRequest, _>(AuthError::Missing))
+            .as_ref()
+            .ok()
+            .map(|signed| signed.body_hash.clone())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SignedRequest {
+    type Error = AuthError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        // resolved once per request, however many guards ask for it
+        let result = req
+            .local_cache_async(async {
+                let Some(header) = req
+                    .headers()
+                    .get_one("authorization")
+                    .and_then(SignatureHeader::parse)
+                else {
+                    return Err(AuthError::Missing);
+                };
+                let Some(verifier) = req.rocket().state::<SignatureVerifier>() else {
+                    return Err(AuthError::Invalid);
+                };
+                // requests without a body need not send its hash
+                let body_hash = req
+                    .headers()
+                    .get_one("X-Content-SHA256")
+                    .map_or_else(|| body_hash(b""), str::to_ascii_lowercase);
+                let path = req.uri().to_string();
+                let key = verifier
+                    .verify(req.method().as_str(), &path, &header, &body_hash)
+                    .await?;
+                Ok(SignedRequest { key, body_hash })
+            })
+            .await;
+        match result {
+            Ok(signed) => Outcome::Success(signed.clone()),
+            Err(AuthError::Unavailable) => {
+                Outcome::Error((Status::ServiceUnavailable, AuthError::Unavailable))
+            }
+            Err(error) => Outcome::Error((Status::Unauthorized, error.clone())),
+        }
+    }
+}
+
+fn security_scheme() -> SecurityScheme {
+    SecurityScheme {
+        description: Some(
+            "Requires `Authorization: HMAC-SHA256 KeyId=<key prefix>, Timestamp=<unix seconds>, \
+             Nonce=<unique value>, Signature=<hex>`, the signature being the HMAC-SHA256, keyed \
+             with the signing secret of the API key, of `HMAC-SHA256`, the method, the path with \
+             its query, the timestamp, the nonce and the hex SHA-256 of the body, one per line. \
+             Requests with a body send that hash in `X-Content-SHA256`. Never grants `admin`."
+                .to_owned(),
+        ),
+        data: SecuritySchemeData::ApiKey {
+            name: "Authorization".to_owned(),
+            location: "header".to_owned(),
+        },
+        extensions: Object::default(),
+    }
+}
+
+/// Register the `Signature` scheme and offer it wherever the `ApiKey` scheme
+/// is required without `admin`, with the same scopes.
+pub fn document(spec: &mut OpenApi) {
+    for item in spec.paths.values_mut() {
+        let operations = [
+            &mut item.get,
+            &mut item.put,
+            &mut item.post,
+            &mut item.delete,
+            &mut item.patch,
+        ];
+        for operation in operations.into_iter().flatten() {
+            let Some(security) = operation.security.as_mut() else {
+                continue;
+            };
+            let signed: Vec<SecurityRequirement> = security
+                .iter()
+                .filter_map(|requirement| requirement.get("ApiKey"))
+                .filter(|scopes| !scopes.iter().any(|scope| scope == Scope::Admin.as_str()))
+                .map(|scopes| {
+                    let mut requirement = SecurityRequirement::new();
+                    requirement.insert("Signature".to_owned(), scopes.clone());
+                    requirement
+                })
+                .collect();
+            security.extend(signed);
+        }
+    }
+    spec.components
+        .get_or_insert_with(Default::default)
+        .security_schemes
+        .insert("Signature".to_owned(), RefOr::Object(security_scheme()));
+}
+
+impl<'a> OpenApiFromRequest<'a> for SignedRequest {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        let mut security_req = SecurityRequirement::new();
+        security_req.insert("Signature".to_owned(), Vec::new());
+        Ok(RequestHeaderInput::Security(
+            "Signature".to_owned(),
+            security_scheme(),
+            security_req,
+        ))
+    }
+
+    fn get_responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        Ok(Responses {
+            responses: okapi::map! {
+                "401".to_owned() => RefOr::Object(unauthorized_response(gen)),
+            },
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,126 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::okapi;
+use rocket_okapi::okapi::openapi3::{Object, Parameter, ParameterValue, RefOr, Responses};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+use crate::db::tenant::{Tenant, TenantError, Tenants};
+use crate::errors::response::{bad_request_response, forbidden_response, MyError};
+use crate::request_guards::principal::Principal;
+
+/// Value of the `X-Tenant-Id` header, for routes resolving the tenant
+/// themselves once the caller is authenticated.
+pub struct TenantHeader(pub Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TenantHeader {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let id = req
+            .headers()
+            .get_one("X-Tenant-Id")
+            .map(|id| id.trim().to_owned())
+            .filter(|id| !id.is_empty());
+        Outcome::Success(TenantHeader(id))
+    }
+}
+
+impl<'a> OpenApiFromRequest<'a> for TenantHeader {
+    fn from_request_input(
+        gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::Parameter(Parameter {
+            name: "X-Tenant-Id".to_owned(),
+            location: "header".to_owned(),
+            description: Some(
+                "Tenant to serve the request for, when tenancy is enabled and admin \
+                 credentials are not bound to one"
+                    .to_owned(),
+            ),
+            required: false,
+            deprecated: false,
+            allow_empty_value: false,
+            value: ParameterValue::Schema {
+                style: None,
+                explode: None,
+                allow_reserved: false,
+                schema: gen.json_schema::<String>(),
+                example: None,
+                examples: None,
+            },
+            extensions: Object::default(),
+        }))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Tenant {
+    type Error = TenantError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        // managed together with the database
+        let Some(tenants) = req.rocket().state::<Tenants>() else {
+            return Outcome::Error((Status::ServiceUnavailable, TenantError::Missing));
+        };
+        let principal = match req.guard::<Option<Principal>>().await {
+            Outcome::Success(principal) => principal,
+            _ => None,
+        };
+        let TenantHeader(header) = req.guard::<TenantHeader>().await.unwrap();
+        let tenant = tenants.resolve(principal.as_ref(), header.as_deref());
+        match tenant {
+            Ok(tenant) => Outcome::Success(tenant),
+            Err(error) => {
+                // told to the client by the catchers
+                req.local_cache(|| Some(error.clone()));
+                let status = match error {
+                    TenantError::Mismatch | TenantError::Unbound => Status::Forbidden,
+                    _ => Status::BadRequest,
+                };
+                Outcome::Error((status, error))
+            }
+        }
+    }
+}
+
+impl Tenant {
+    /// Why the tenant of `request` could not be resolved, if it could not.
+    pub fn error_of(request: &Request<'_>) -> Option<TenantError> {
+        request.local_cache(|| None::<TenantError>).clone()
+    }
+}
+
+#[catch(400)]
+pub fn bad_request(req: &Request<'_>) -> MyError {
+    let description = match Tenant::error_of(req) {
+        Some(error) => error.to_string(),
+        None => "The request is malformed.".to_string(),
+    };
+    MyError::build(400, Some(description))
+}
+
+impl<'a> OpenApiFromRequest<'a> for Tenant {
+    fn from_request_input(
+        gen: &mut OpenApiGenerator,
+        name: String,
+        required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        TenantHeader::from_request_input(gen, name, required)
+    }
+
+    fn get_responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        Ok(Responses {
+            responses: okapi::map! {
+                "400".to_owned() => RefOr::Object(bad_request_response(gen)),
+                "403".to_owned() => RefOr::Object(forbidden_response(gen)),
+            },
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,35 @@
+use opentelemetry::Context;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::{
+    gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+/// Trace context of the request span started by the `Telemetry` fairing.
+/// Database calls run with it so that their spans belong to the request.
+#[derive(Clone)]
+pub struct TraceContext(pub Context);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TraceContext {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let cx = req
+            .local_cache(|| None::<TraceContext>)
+            .clone()
+            .unwrap_or_else(|| TraceContext(Context::new()));
+        Outcome::Success(cx)
+    }
+}
+
+impl<'a> OpenApiFromRequest<'a> for TraceContext {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        // `traceparent` is an infrastructure header, not part of the API
+        Ok(RequestHeaderInput::None)
+    }
+}
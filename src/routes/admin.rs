@@ -0,0 +1,359 @@
+use chrono::{DateTime as ChronoDateTime, Duration};
+use mongodb::{
+    bson::{oid::ObjectId, DateTime},
+    Database,
+};
+use rocket::{serde::json::Json, State};
+use rocket_okapi::openapi;
+
+use crate::{
+    auth::{oauth::TokenIssuer, ApiKeyStore},
+    config::AppConfig,
+    db::{
+        api_key, oauth,
+        tenant::{TenantError, Tenants},
+    },
+    errors::response::MyError,
+    models::{
+        api_key::{ApiKeyInfo, ApiKeyInput, IssuedApiKey},
+        oauth::{OAuthClientInfo, OAuthClientInput, RegisteredOAuthClient},
+    },
+    request_guards::{
+        authorized::{Admin, Authorized},
+        db_available::DbAvailable,
+        principal::Principal,
+        rate_limit::RateLimit,
+    },
+};
+
+/// The tenant of credentials issued by `admin`: the one it is bound to, which
+/// it cannot leave, else the requested one of `tenancy.tenants`, if any.
+fn issued_tenant(
+    tenants: &Tenants,
+    admin: &Principal,
+    requested: Option<&str>,
+) -> Result<Option<String>, MyError> {
+    match (admin.tenant(), requested) {
+        (Some(bound), Some(requested)) if bound == requested => Ok(Some(bound.to_owned())),
+        (Some(bound), _) => Err(MyError::build(
+            403,
+            Some(format!(
+                "The credentials are bound to tenant {} and can only issue credentials \
+                 of that tenant.",
+                bound
+            )),
+        )),
+        (None, Some(requested)) if !tenants.exists(requested) => Err(MyError::build(
+            400,
+            Some(TenantError::Unknown(requested.to_owned()).to_string()),
+        )),
+        (None, requested) => Ok(requested.map(str::to_owned)),
+    }
+}
+
+/// issue an API key
+///
+/// The key is only returned in this response and cannot be retrieved later.
+#[openapi(tag = "Admin")]
+#[post("/admin/api-keys", data = "<input>")]
+// every argument is a request guard or parameter
+#[allow(clippy::too_many_arguments)]
+pub async fn post_api_key(
+    db: &State<Database>,
+    keys: &State<ApiKeyStore>,
+    tenants: &State<Tenants>,
+    _limit: RateLimit,
+    admin: Authorized<Admin>,
+    _available: DbAvailable,
+    input: Json<ApiKeyInput>,
+) -> Result<Json<IssuedApiKey>, MyError> {
+    if input.name.is_empty()
+        || input.owner.is_empty()
+        || (input.scopes.is_empty() && input.roles.is_empty())
+    {
+        return Err(MyError::build(
+            400,
+            Some("name, owner and scopes or roles are required.".to_string()),
+        ));
+    }
+    let expires_at = match input
+        .expires_at
+        .as_deref()
+        .map(ChronoDateTime::parse_from_rfc3339)
+    {
+        None => None,
+        Some(Ok(expires_at)) => Some(DateTime::from_chrono(expires_at)),
+        Some(Err(_)) => {
+            return Err(MyError::build(
+                400,
+                Some("Invalid expiresAt, expected an RFC 3339 date.".to_string()),
+            ))
+        }
+    };
+
+    let tenant = issued_tenant(tenants, &admin.principal, input.tenant.as_deref())?;
+
+    let generated = keys.generate();
+    match api_key::insert_api_key(
+        db,
+        &input.name,
+        &input.owner,
+        &input.scopes,
+        &input.roles,
+        input.team.as_deref(),
+        tenant.as_deref(),
+        expires_at,
+        &generated,
+    )
+    .await
+    {
+        Ok(api_key_doc) => {
+            tracing::info!(
+                admin = %admin.principal.identity(),
+                prefix = %api_key_doc.prefix,
+                "API key issued"
+            );
+            Ok(Json(IssuedApiKey {
+                key: generated.key,
+                signing_secret: generated.signing_secret,
+                info: ApiKeyInfo::from(api_key_doc),
+            }))
+        }
+        Err(error) => Err(MyError::build(400, Some(error.to_string()))),
+    }
+}
+
+/// get API keys, without their secrets
+///
+/// Admins bound to a tenant only see the keys of their tenant.
+#[openapi(tag = "Admin")]
+#[get("/admin/api-keys")]
+pub async fn get_api_keys(
+    db: &State<Database>,
+    _limit: RateLimit,
+    admin: Authorized<Admin>,
+    _available: DbAvailable,
+) -> Result<Json<Vec<ApiKeyInfo>>, MyError> {
+    match api_key::find_api_keys(db, admin.principal.tenant()).await {
+        Ok(api_key_docs) => Ok(Json(
+            api_key_docs.into_iter().map(ApiKeyInfo::from).collect(),
+        )),
+        Err(error) => Err(MyError::build(400, Some(error.to_string()))),
+    }
+}
+
+/// rotate an API key by _id
+///
+/// Issues a new key with the same name, owner, scopes, roles, team, tenant and
+/// expiry. The old key
+/// keeps working for `overlap` seconds (`auth.rotation_overlap_secs` by default)
+/// so that clients can switch without downtime. Admins bound to a tenant can
+/// only rotate the keys of their tenant.
+#[openapi(tag = "Admin")]
+#[post("/admin/api-keys/<id>/rotate?<overlap>")]
+// every argument is a request guard or parameter
+#[allow(clippy::too_many_arguments)]
+pub async fn rotate_api_key(
+    db: &State<Database>,
+    keys: &State<ApiKeyStore>,
+    config: &State<AppConfig>,
+    _limit: RateLimit,
+    admin: Authorized<Admin>,
+    _available: DbAvailable,
+    id: &str,
+    overlap: Option<u32>,
+) -> Result<Json<IssuedApiKey>, MyError> {
+    let Ok(oid) = ObjectId::parse_str(id) else {
+        return Err(MyError::build(400, Some("Invalid _id format.".to_string())));
+    };
+    let old = match api_key::find_api_key_by_id(db, admin.principal.tenant(), oid).await {
+        Ok(Some(old)) if old.is_active(DateTime::now()) => old,
+        Ok(_) => {
+            return Err(MyError::build(
+                400,
+                Some(format!("Active API key not found with _id {}", &id)),
+            ))
+        }
+        Err(error) => return Err(MyError::build(400, Some(error.to_string()))),
+    };
+
+    let generated = keys.generate();
+    let new = match api_key::insert_api_key(
+        db,
+        &old.name,
+        &old.owner,
+        &old.scopes,
+        &old.roles,
+        old.team.as_deref(),
+        old.tenant.as_deref(),
+        old.expires_at,
+        &generated,
+    )
+    .await
+    {
+        Ok(new) => new,
+        Err(error) => return Err(MyError::build(400, Some(error.to_string()))),
+    };
+
+    let overlap = overlap.map_or(config.auth.rotation_overlap_secs, u64::from);
+    let expires_at = chrono::Utc::now() + Duration::seconds(overlap as i64);
+    if let Err(error) = api_key::expire_api_key(db, oid, DateTime::from_chrono(expires_at)).await {
+        return Err(MyError::build(400, Some(error.to_string())));
+    }
+    keys.evict(oid);
+
+    tracing::info!(
+        admin = %admin.principal.identity(),
+        old = %old.prefix,
+        new = %new.prefix,
+        overlap,
+        "API key rotated"
+    );
+    Ok(Json(IssuedApiKey {
+        key: generated.key,
+        signing_secret: generated.signing_secret,
+        info: ApiKeyInfo::from(new),
+    }))
+}
+
+/// revoke an API key by _id
+///
+/// The key stops working at once. Admins bound to a tenant can only revoke
+/// the keys of their tenant.
+#[openapi(tag = "Admin")]
+#[delete("/admin/api-keys/<id>")]
+pub async fn revoke_api_key(
+    db: &State<Database>,
+    keys: &State<ApiKeyStore>,
+    _limit: RateLimit,
+    admin: Authorized<Admin>,
+    _available: DbAvailable,
+    id: &str,
+) -> Result<Json<ApiKeyInfo>, MyError> {
+    let Ok(oid) = ObjectId::parse_str(id) else {
+        return Err(MyError::build(400, Some("Invalid _id format.".to_string())));
+    };
+
+    match api_key::revoke_api_key(db, admin.principal.tenant(), oid).await {
+        Ok(Some(api_key_doc)) => {
+            keys.evict(oid);
+            tracing::info!(
+                admin = %admin.principal.identity(),
+                prefix = %api_key_doc.prefix,
+                "API key revoked"
+            );
+            Ok(Json(ApiKeyInfo::from(api_key_doc)))
+        }
+        _ => Err(MyError::build(
+            400,
+            Some(format!("Active API key not found with _id {}", &id)),
+        )),
+    }
+}
+
+/// register an OAuth2 client
+///
+/// The client obtains tokens from `/oauth/token` with the client-credentials
+/// grant. The secret is only returned in this response and cannot be
+/// retrieved later.
+#[openapi(tag = "Admin")]
+#[post("/admin/oauth-clients", data = "<input>")]
+// every argument is a request guard or parameter
+#[allow(clippy::too_many_arguments)]
+pub async fn post_oauth_client(
+    db: &State<Database>,
+    issuer: &State<TokenIssuer>,
+    tenants: &State<Tenants>,
+    _limit: RateLimit,
+    admin: Authorized<Admin>,
+    _available: DbAvailable,
+    input: Json<OAuthClientInput>,
+) -> Result<Json<RegisteredOAuthClient>, MyError> {
+    if input.name.is_empty() || input.owner.is_empty() || input.scopes.is_empty() {
+        return Err(MyError::build(
+            400,
+            Some("name, owner and scopes are required.".to_string()),
+        ));
+    }
+
+    let tenant = issued_tenant(tenants, &admin.principal, input.tenant.as_deref())?;
+
+    let (client_id, client_secret, secret_hash) = issuer.generate_client();
+    match oauth::insert_oauth_client(
+        db,
+        &input.name,
+        &input.owner,
+        &input.scopes,
+        input.team.as_deref(),
+        tenant.as_deref(),
+        &client_id,
+        &secret_hash,
+    )
+    .await
+    {
+        Ok(client_doc) => {
+            tracing::info!(
+                admin = %admin.principal.identity(),
+                client_id = %client_doc.client_id,
+                "OAuth2 client registered"
+            );
+            Ok(Json(RegisteredOAuthClient {
+                client_secret,
+                info: OAuthClientInfo::from(client_doc),
+            }))
+        }
+        Err(error) => Err(MyError::build(400, Some(error.to_string()))),
+    }
+}
+
+/// get OAuth2 clients, without their secrets
+///
+/// Admins bound to a tenant only see the clients of their tenant.
+#[openapi(tag = "Admin")]
+#[get("/admin/oauth-clients")]
+pub async fn get_oauth_clients(
+    db: &State<Database>,
+    _limit: RateLimit,
+    admin: Authorized<Admin>,
+    _available: DbAvailable,
+) -> Result<Json<Vec<OAuthClientInfo>>, MyError> {
+    match oauth::find_oauth_clients(db, admin.principal.tenant()).await {
+        Ok(client_docs) => Ok(Json(
+            client_docs.into_iter().map(OAuthClientInfo::from).collect(),
+        )),
+        Err(error) => Err(MyError::build(400, Some(error.to_string()))),
+    }
+}
+
+/// revoke an OAuth2 client by _id
+///
+/// The client cannot obtain tokens anymore; tokens already issued keep working
+/// until they expire, unless revoked through `/oauth/revoke`.
+#[openapi(tag = "Admin")]
+#[delete("/admin/oauth-clients/<id>")]
+pub async fn revoke_oauth_client(
+    db: &State<Database>,
+    _limit: RateLimit,
+    admin: Authorized<Admin>,
+    _available: DbAvailable,
+    id: &str,
+) -> Result<Json<OAuthClientInfo>, MyError> {
+    let Ok(oid) = ObjectId::parse_str(id) else {
+        return Err(MyError::build(400, Some("Invalid _id format.".to_string())));
+    };
+
+    match oauth::revoke_oauth_client(db, admin.principal.tenant(), oid).await {
+        Ok(Some(client_doc)) => {
+            tracing::info!(
+                admin = %admin.principal.identity(),
+                client_id = %client_doc.client_id,
+                "OAuth2 client revoked"
+            );
+            Ok(Json(OAuthClientInfo::from(client_doc)))
+        }
+        _ => Err(MyError::build(
+            400,
+            Some(format!("Active OAuth2 client not found with _id {}", &id)),
+        )),
+    }
+}
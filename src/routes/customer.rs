@@ -1,28 +1,67 @@
-use mongodb::{
-    bson::{doc, oid::ObjectId},
-    Database,
+use mongodb::bson::{doc, oid::ObjectId};
+use opentelemetry::trace::FutureExt;
+use rocket::{
+    futures::{SinkExt, Stream, StreamExt},
+    response::status::BadRequest,
+    response::stream::{Event, EventStream},
+    serde::json::Json,
+    tokio::{
+        select,
+        sync::broadcast::error::RecvError,
+        time::{sleep, Duration},
+    },
+    Shutdown, State,
 };
-use rocket::{response::status::BadRequest, serde::json::Json, State};
 use rocket_okapi::openapi;
+use rocket_ws::{
+    frame::{CloseCode, CloseFrame},
+    Channel, Message, WebSocket,
+};
 
 use crate::{
-    db::customer,
+    auth::ApiKeyStore,
+    config::{page_offset, AppConfig},
+    db::{
+        change_feed::ChangeFeed,
+        customer,
+        tenant::{Tenant, TenantError, Tenants},
+    },
     errors::response::MyError,
+    formats::negotiated::Negotiated,
     models::{
-        customer::{Customer, CustomerInput},
+        api_key::Scope,
+        customer::{Customer, CustomerInput, Ownership},
+        event::CustomerEvent,
         response::MessageResponse,
+        subscription::{ClientMessage, ServerMessage, Subscription},
+    },
+    request_guards::{
+        authorized::{Authorized, CustomerDelete, CustomerRead, CustomerWrite},
+        basic::AuthError,
+        db_available::DbAvailable,
+        last_event_id::LastEventId,
+        principal::Principal,
+        rate_limit::RateLimit,
+        tenant::TenantHeader,
+        trace_context::TraceContext,
     },
-    request_guards::basic::ApiKey,
 };
 
 /// get customer documents
 #[openapi(tag = "Customer")]
 #[get("/customer?<limit>&<page>")]
+// every argument is a request guard or parameter
+#[allow(clippy::too_many_arguments)]
 pub async fn get_customers(
-    db: &State<Database>,
+    tenant: Tenant,
+    config: &State<AppConfig>,
+    _limit: RateLimit,
+    key: Authorized<CustomerRead>,
+    _available: DbAvailable,
+    trace: TraceContext,
     limit: Option<i64>,
     page: Option<i64>,
-) -> Result<Json<Vec<Customer>>, MyError> {
+) -> Result<Negotiated<Vec<Customer>>, MyError> {
     // Error handling
     // This is also valid when strict checking is necessary.
     // if limit < 0 {
@@ -30,36 +69,305 @@ pub async fn get_customers(
     //         message: "limit cannot be less than 0".to_string(),
     //     }))));
     // }
-    // if !page.is_none() && page.unwrap() < 1 {
-    //     return Err(BadRequest(Some(Json(MessageResponse {
-    //         message: "page cannot be less than 1".to_string(),
-    //     }))));
-    // }
 
     // Setting default values
-    let limit: i64 = limit.unwrap_or(12);
+    let limit: i64 = config.pagination.limit(limit);
     let page: i64 = page.unwrap_or(1);
-    match customer::find_customer(db, limit, page).await {
-        Ok(customer_docs) => Ok(Json(customer_docs)),
+    let skip =
+        page_offset(page, limit).map_err(|error| MyError::build(400, Some(error.to_string())))?;
+    match customer::find_customer(&tenant, &key.principal.ownership(), limit, skip)
+        .with_context(trace.0)
+        .await
+    {
+        Ok(customer_docs) => Ok(Negotiated(customer_docs)),
         Err(error) => Err(MyError::build(400, Some(error.to_string()))),
     }
 }
 
+/// stream customer changes as Server-Sent Events
+///
+/// Each event is named `created`, `updated` or `deleted` and carries its id
+/// as the SSE id. Send `Last-Event-ID` to replay the events missed since
+/// then, as far as the server still remembers them; ids from before a
+/// restart replay nothing. Only events of customers the credentials may
+/// access are sent.
+#[openapi(tag = "Customer")]
+#[get("/customer/events")]
+pub fn get_customer_events(
+    feed: &State<ChangeFeed>,
+    key: Authorized<CustomerRead>,
+    tenant: Tenant,
+    last_event_id: LastEventId,
+    mut shutdown: Shutdown,
+) -> EventStream<impl Stream<Item = Event>> {
+    let audience = Audience {
+        tenant: tenant.id().map(str::to_owned),
+        ownership: key.principal.ownership(),
+    };
+    // subscribe before reading the history so no event falls in between
+    let mut receiver = feed.subscribe();
+    let missed = last_event_id
+        .0
+        .map(|last_event_id| feed.since(&last_event_id))
+        .unwrap_or_default();
+    // events published since subscribing are both replayed and received
+    let mut last_sent = missed.last().map_or(0, |event| event.seq);
+
+    fn to_sse(event: &CustomerEvent) -> Event {
+        Event::json(event)
+            .id(event.id.clone())
+            .event(event.kind.as_str())
+    }
+
+    EventStream! {
+        for event in missed.into_iter().filter(|event| audience.sees(event)) {
+            yield to_sse(&event);
+        }
+        loop {
+            let event = select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => event,
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(_)) => continue,
+                },
+                _ = &mut shutdown => break,
+            };
+            if event.seq <= last_sent || !audience.sees(&event) {
+                continue;
+            }
+            last_sent = event.seq;
+            yield to_sse(&event);
+        }
+    }
+}
+
+/// Whose customers the events sent to a client concern.
+struct Audience {
+    tenant: Option<String>,
+    ownership: Ownership,
+}
+
+impl Audience {
+    fn new(
+        tenants: &Tenants,
+        header: Option<&str>,
+        principal: &Principal,
+    ) -> Result<Audience, TenantError> {
+        let tenant = tenants.resolve(Some(principal), header)?;
+        Ok(Audience {
+            tenant: tenant.id().map(str::to_owned),
+            ownership: principal.ownership(),
+        })
+    }
+
+    fn sees(&self, event: &CustomerEvent) -> bool {
+        event.is_visible_to(self.tenant.as_deref(), &self.ownership)
+    }
+}
+
+const MISSING_READ_SCOPE: &str = "The API key lacks the customer:read scope.";
+
+/// subscribe to customer changes over a WebSocket
+///
+/// Authenticate with the `x-api-key` header, a bearer token or, where headers
+/// cannot be set, with `{"action": "auth", "apiKey": "..."}` as the first
+/// message, with the `customer:read` scope, within
+/// `auth.websocket_auth_timeout_secs` or the socket is closed with code 1008.
+/// Then send
+/// `{"action": "subscribe", "ids": [...], "kinds": [...]}` (no ids means every
+/// customer, no kinds every kind) or `unsubscribe` with the same fields to
+/// receive
+/// `{"type": "event", ...}` messages for matching changes of customers the
+/// credentials may access, in the tenant of the credentials or of
+/// `X-Tenant-Id`.
+#[openapi(tag = "Customer")]
+#[get("/customer/ws")]
+// every argument is a request guard
+#[allow(clippy::too_many_arguments)]
+pub fn customer_updates(
+    ws: WebSocket,
+    principal: Result<Principal, AuthError>,
+    header: TenantHeader,
+    feed: &State<ChangeFeed>,
+    keys: &State<ApiKeyStore>,
+    tenants: &State<Tenants>,
+    config: &State<AppConfig>,
+    mut shutdown: Shutdown,
+) -> Result<Channel<'static>, MyError> {
+    // set once authenticated
+    let mut audience = match principal {
+        Ok(principal) if principal.has_scope(Scope::CustomerRead) => {
+            match Audience::new(tenants, header.0.as_deref(), &principal) {
+                Ok(audience) => Some(audience),
+                Err(error @ (TenantError::Mismatch | TenantError::Unbound)) => {
+                    return Err(MyError::build(403, Some(error.to_string())))
+                }
+                Err(error) => return Err(MyError::build(400, Some(error.to_string()))),
+            }
+        }
+        Ok(_) => return Err(MyError::build(403, Some(MISSING_READ_SCOPE.to_string()))),
+        Err(AuthError::Missing) => None,
+        Err(AuthError::Invalid | AuthError::Forbidden) => {
+            return Err(MyError::build(
+                401,
+                Some("Invalid API key or bearer token.".to_string()),
+            ))
+        }
+        Err(AuthError::Unavailable) => {
+            return Err(MyError::build(
+                503,
+                Some("Cannot verify credentials.".to_string()),
+            ))
+        }
+    };
+    // unauthenticated sockets get no events, not even buffered ones
+    let mut receiver = audience.as_ref().map(|_| feed.subscribe());
+    let feed = feed.inner().clone();
+    let keys = keys.inner().clone();
+    let tenants = tenants.inner().clone();
+    let header = header.0;
+    let auth_timeout = Duration::from_secs(config.auth.websocket_auth_timeout_secs);
+
+    Ok(ws.channel(move |mut stream| {
+        Box::pin(async move {
+            let mut subscription = Subscription::default();
+            let auth_deadline = sleep(auth_timeout);
+            rocket::tokio::pin!(auth_deadline);
+            loop {
+                if receiver.is_none() && audience.is_some() {
+                    receiver = Some(feed.subscribe());
+                }
+                let reply = select! {
+                    message = stream.next() => match message {
+                        Some(Ok(Message::Text(text))) => {
+                            handle_client_message(
+                                &text,
+                                &keys,
+                                &tenants,
+                                header.as_deref(),
+                                &mut audience,
+                                &mut subscription,
+                            )
+                            .await
+                        }
+                        Some(Ok(Message::Close(_))) | None => break,
+                        Some(Ok(_)) => continue,
+                        Some(Err(error)) => return Err(error),
+                    },
+                    Some(event) = async { Some(receiver.as_mut()?.recv().await) } => match event {
+                        Ok(event)
+                            if audience.as_ref().is_some_and(|audience| audience.sees(&event))
+                                && subscription.matches(&event) =>
+                        {
+                            ServerMessage::Event { event }
+                        }
+                        Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = &mut auth_deadline, if audience.is_none() => {
+                        let frame = CloseFrame {
+                            code: CloseCode::Policy,
+                            reason: "Authentication timed out.".into(),
+                        };
+                        return stream.close(Some(frame)).await;
+                    }
+                    _ = &mut shutdown => break,
+                };
+                let reply = serde_json::to_string(&reply).unwrap();
+                stream.send(Message::Text(reply)).await?;
+            }
+            Ok(())
+        })
+    }))
+}
+
+async fn handle_client_message(
+    text: &str,
+    keys: &ApiKeyStore,
+    tenants: &Tenants,
+    header: Option<&str>,
+    audience: &mut Option<Audience>,
+    subscription: &mut Subscription,
+) -> ServerMessage {
+    let message = match serde_json::from_str::<ClientMessage>(text) {
+        Ok(message) => message,
+        Err(error) => {
+            return ServerMessage::Error {
+                message: format!("Invalid message: {}", error),
+            }
+        }
+    };
+
+    let action = match message {
+        ClientMessage::Auth { api_key } => match keys.verify(&api_key).await {
+            Ok(key) if key.has_scope(Scope::CustomerRead) => {
+                match Audience::new(tenants, header, &Principal::ApiKey(key)) {
+                    Ok(authenticated) => *audience = Some(authenticated),
+                    Err(error) => {
+                        return ServerMessage::Error {
+                            message: error.to_string(),
+                        }
+                    }
+                }
+                "auth"
+            }
+            Ok(_) => {
+                return ServerMessage::Error {
+                    message: MISSING_READ_SCOPE.to_string(),
+                }
+            }
+            Err(_) => {
+                return ServerMessage::Error {
+                    message: "Invalid API key.".to_string(),
+                }
+            }
+        },
+        _ if audience.is_none() => {
+            return ServerMessage::Error {
+                message: "Authenticate before subscribing.".to_string(),
+            }
+        }
+        ClientMessage::Subscribe(filter) => {
+            subscription.subscribe(filter);
+            "subscribe"
+        }
+        ClientMessage::Unsubscribe(filter) => {
+            subscription.unsubscribe(filter);
+            "unsubscribe"
+        }
+    };
+
+    ServerMessage::Ack {
+        action: action.to_string(),
+        subscription: subscription.clone(),
+    }
+}
+
 /// get customer document by _id
 #[openapi(tag = "Customer")]
 #[get("/customer/<id>")]
-pub async fn get_customer_by_id(db: &State<Database>, id: &str) -> Result<Json<Customer>, MyError> {
+pub async fn get_customer_by_id(
+    tenant: Tenant,
+    _limit: RateLimit,
+    key: Authorized<CustomerRead>,
+    _available: DbAvailable,
+    trace: TraceContext,
+    id: &str,
+) -> Result<Negotiated<Customer>, MyError> {
     let Ok(oid) = ObjectId::parse_str(id) else {
         return Err(MyError::build(400, Some("Invalid _id format.".to_string())));
     };
 
-    match customer::find_customer_by_id(db, oid).await {
+    match customer::find_customer_by_id(&tenant, &key.principal.ownership(), oid)
+        .with_context(trace.0)
+        .await
+    {
         Ok(customer_doc) => match customer_doc {
             None => Err(MyError::build(
-                400,
+                404,
                 Some(format!("Customer not found with _id {}", &id)),
             )),
-            Some(customer_doc) => Ok(Json(customer_doc)),
+            Some(customer_doc) => Ok(Negotiated(customer_doc)),
         },
         Err(_error) => Err(MyError::build(
             400,
@@ -72,13 +380,26 @@ pub async fn get_customer_by_id(db: &State<Database>, id: &str) -> Result<Json<C
 #[openapi(tag = "Customer")]
 #[post("/customer", data = "<input>")]
 pub async fn post_customer(
-    db: &State<Database>,
-    input: Json<CustomerInput>,
-) -> Result<Json<String>, BadRequest<Json<MessageResponse>>> {
+    tenant: Tenant,
+    _limit: RateLimit,
+    key: Authorized<CustomerWrite>,
+    _available: DbAvailable,
+    feed: &State<ChangeFeed>,
+    trace: TraceContext,
+    input: Negotiated<CustomerInput>,
+) -> Result<Negotiated<String>, BadRequest<Negotiated<MessageResponse>>> {
     // can set with a single error like this.
-    match customer::insert_customer(db, input).await {
-        Ok(customer_doc_id) => Ok(Json(customer_doc_id)),
-        Err(_error) => Err(BadRequest(Json(MessageResponse {
+    match customer::insert_customer(
+        &tenant,
+        feed,
+        &key.principal.ownership(),
+        Json(input.into_inner()),
+    )
+    .with_context(trace.0)
+    .await
+    {
+        Ok(customer_doc) => Ok(Negotiated(customer_doc.id)),
+        Err(_error) => Err(BadRequest(Negotiated(MessageResponse {
             message: "Invalid input".to_string(),
         }))),
     }
@@ -87,21 +408,36 @@ pub async fn post_customer(
 /// update a customer document by _id
 #[openapi(tag = "Customer")]
 #[patch("/customer/<id>", data = "<input>")]
+// every argument is a request guard or parameter
+#[allow(clippy::too_many_arguments)]
 pub async fn patch_customer_by_id(
-    db: &State<Database>,
-    _key: ApiKey,
+    tenant: Tenant,
+    _limit: RateLimit,
+    feed: &State<ChangeFeed>,
+    trace: TraceContext,
+    key: Authorized<CustomerWrite>,
+    _available: DbAvailable,
     id: &str,
-    input: Json<CustomerInput>,
-) -> Result<Json<Customer>, MyError> {
+    input: Negotiated<CustomerInput>,
+) -> Result<Negotiated<Customer>, MyError> {
     let Ok(oid) = ObjectId::parse_str(id) else {
         return Err(MyError::build(400, Some("Invalid id format.".to_string())));
     };
 
-    match customer::update_customer_by_id(db, oid, input).await {
+    match customer::update_customer_by_id(
+        &tenant,
+        feed,
+        &key.principal.ownership(),
+        oid,
+        Json(input.into_inner()),
+    )
+    .with_context(trace.0)
+    .await
+    {
         Ok(customer_doc) => match customer_doc {
-            Some(customer_doc) => Ok(Json(customer_doc)),
+            Some(customer_doc) => Ok(Negotiated(customer_doc)),
             None => Err(MyError::build(
-                400,
+                404,
                 Some(format!("Customer not found with id {}", &id)),
             )),
         },
@@ -116,19 +452,26 @@ pub async fn patch_customer_by_id(
 #[openapi(tag = "Customer")]
 #[delete("/customer/<id>")]
 pub async fn delete_customer_by_id(
-    db: &State<Database>,
+    tenant: Tenant,
+    _limit: RateLimit,
+    feed: &State<ChangeFeed>,
+    trace: TraceContext,
     id: &str,
-    _key: ApiKey,
-) -> Result<Json<Customer>, MyError> {
+    key: Authorized<CustomerDelete>,
+    _available: DbAvailable,
+) -> Result<Negotiated<Customer>, MyError> {
     let Ok(oid) = ObjectId::parse_str(id) else {
         return Err(MyError::build(400, Some("Invalid id format.".to_string())));
     };
 
-    match customer::delete_customer_by_id(db, oid).await {
+    match customer::delete_customer_by_id(&tenant, feed, &key.principal.ownership(), oid)
+        .with_context(trace.0)
+        .await
+    {
         Ok(customer_doc) => match customer_doc {
-            Some(customer_doc) => Ok(Json(customer_doc)),
+            Some(customer_doc) => Ok(Negotiated(customer_doc)),
             None => Err(MyError::build(
-                400,
+                404,
                 Some(format!("Customer not found with _id {}", &id)),
             )),
         },
@@ -0,0 +1,65 @@
+use async_graphql::http::GraphiQLSource;
+use async_graphql_rocket::{GraphQLQuery, GraphQLRequest, GraphQLResponse};
+use rocket::{response::content::RawHtml, State};
+
+use crate::{
+    db::tenant::{Tenant, TenantError},
+    graphql::CustomerSchema,
+    request_guards::{
+        db_available::DbAvailable, principal::Principal, rate_limit::RateLimit,
+        signature::SignedRequest,
+    },
+};
+
+/// GraphiQL playground for the `/graphql` endpoint
+#[get("/")]
+pub fn graphiql() -> RawHtml<String> {
+    RawHtml(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+/// Hand the caller and its tenant, or why it has none, to the resolvers.
+fn with_caller(
+    request: GraphQLRequest,
+    principal: Option<Principal>,
+    tenant: Result<Tenant, TenantError>,
+) -> GraphQLRequest {
+    let request = match tenant {
+        Ok(tenant) => request.data(tenant),
+        Err(error) => request.data(error),
+    };
+    match principal {
+        Some(principal) => request.data(principal),
+        None => request,
+    }
+}
+
+#[get("/graphql?<query..>")]
+pub async fn get_graphql(
+    schema: &State<CustomerSchema>,
+    _limit: RateLimit,
+    _available: DbAvailable,
+    principal: Option<Principal>,
+    tenant: Result<Tenant, TenantError>,
+    query: GraphQLQuery,
+) -> GraphQLResponse {
+    with_caller(GraphQLRequest::from(query), principal, tenant)
+        .execute(schema.inner())
+        .await
+}
+
+#[post("/graphql", data = "<request>", format = "application/json")]
+pub async fn post_graphql(
+    schema: &State<CustomerSchema>,
+    _limit: RateLimit,
+    _available: DbAvailable,
+    principal: Option<Principal>,
+    signed: Option<SignedRequest>,
+    tenant: Result<Tenant, TenantError>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    // the signature does not cover the body read by `GraphQLRequest`
+    let principal = principal.filter(|_| signed.is_none());
+    with_caller(request, principal, tenant)
+        .execute(schema.inner())
+        .await
+}
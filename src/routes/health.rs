@@ -0,0 +1,40 @@
+use std::collections::BTreeMap;
+
+use mongodb::Database;
+use rocket::State;
+use rocket_okapi::openapi;
+
+use crate::db::change_feed::ChangeFeed;
+use crate::grpc::GrpcServer;
+use crate::health::{self, HealthConfig, Readiness};
+use crate::models::health::{ComponentHealth, HealthReport};
+
+/// liveness probe: the process is up and serving requests
+#[openapi(tag = "Health")]
+#[get("/health/live")]
+pub fn live() -> HealthReport {
+    let mut components = BTreeMap::new();
+    components.insert("server".to_string(), ComponentHealth::up(None));
+    HealthReport::new(components)
+}
+
+/// readiness probe: MongoDB answers, gRPC is served when enabled and the
+/// instance is not shutting down
+#[openapi(tag = "Health")]
+#[get("/health/ready")]
+pub async fn ready(
+    readiness: &State<Readiness>,
+    config: &State<HealthConfig>,
+    db: Option<&State<Database>>,
+    feed: Option<&State<ChangeFeed>>,
+    grpc: Option<&State<GrpcServer>>,
+) -> HealthReport {
+    health::readiness(
+        readiness,
+        config,
+        db.map(|db| db.inner()),
+        feed.map(|feed| feed.inner()),
+        grpc.map(|grpc| grpc.inner()),
+    )
+    .await
+}
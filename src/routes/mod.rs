@@ -1,15 +1,20 @@
-use rocket::serde::json::Json;
 use rocket_okapi::openapi;
 
+use crate::formats::negotiated::Negotiated;
 use crate::models::response::MessageResponse;
 
+pub mod admin;
 pub mod customer;
+pub mod graphql;
+pub mod health;
+pub mod oauth;
+pub mod webhook;
 
 /// This is a description. <br />You can do simple html <br /> like <b>this<b/>
 #[openapi(tag = "Hello World")]
 #[get("/")]
-pub fn index() -> Json<MessageResponse> {
-    Json(MessageResponse {
+pub fn index() -> Negotiated<MessageResponse> {
+    Negotiated(MessageResponse {
         message: "Hello World!".to_string(),
     })
 }
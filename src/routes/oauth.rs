@@ -0,0 +1,176 @@
+use rocket::form::Form;
+use rocket::http::Header;
+use rocket::response::Responder;
+use rocket::{serde::json::Json, State};
+use rocket_okapi::{
+    gen::OpenApiGenerator, okapi::openapi3::Responses, openapi, response::OpenApiResponderInner,
+    OpenApiError,
+};
+
+use crate::{
+    auth::oauth::TokenIssuer,
+    errors::oauth::OAuthError,
+    models::{
+        api_key::Scope,
+        oauth::{
+            IntrospectionResponse, OAuthClientDocument, TokenInput, TokenRequest, TokenResponse,
+        },
+    },
+    request_guards::{client_credentials::ClientCredentials, rate_limit::RateLimit},
+};
+
+/// A response that must not be cached, as it carries or describes a token.
+pub struct NoStore<R>(pub R);
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for NoStore<R> {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'o> {
+        let mut response = self.0.respond_to(req)?;
+        response.set_header(Header::new("Cache-Control", "no-store"));
+        response.set_header(Header::new("Pragma", "no-cache"));
+        Ok(response)
+    }
+}
+
+impl<R: OpenApiResponderInner> OpenApiResponderInner for NoStore<R> {
+    fn responses(gen: &mut OpenApiGenerator) -> Result<Responses, OpenApiError> {
+        R::responses(gen)
+    }
+}
+
+/// The client authenticated with HTTP Basic or with the form fields, not both.
+async fn authenticate(
+    issuer: &TokenIssuer,
+    basic: Option<ClientCredentials>,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<OAuthClientDocument, OAuthError> {
+    match (basic, client_id, client_secret) {
+        (Some(basic), None, None) => {
+            issuer
+                .authenticate(&basic.client_id, &basic.client_secret)
+                .await
+        }
+        (None, Some(client_id), Some(client_secret)) => {
+            issuer.authenticate(client_id, client_secret).await
+        }
+        (Some(_), _, _) => Err(OAuthError::invalid_request(
+            "Use a single client authentication method.",
+        )),
+        _ => Err(OAuthError::invalid_client()),
+    }
+}
+
+/// obtain an access token
+///
+/// The client-credentials grant (RFC 6749 section 4.4). Authenticate with HTTP
+/// Basic or the `client_id` and `client_secret` fields, and narrow the granted
+/// scopes with `scope`. Send the token as `Authorization: Bearer`.
+#[openapi(tag = "OAuth2")]
+#[post("/oauth/token", data = "<request>")]
+pub async fn token(
+    issuer: &State<TokenIssuer>,
+    _limit: RateLimit,
+    basic: Option<ClientCredentials>,
+    request: Form<TokenRequest>,
+) -> Result<NoStore<Json<TokenResponse>>, OAuthError> {
+    if request.grant_type != "client_credentials" {
+        return Err(OAuthError::unsupported_grant_type());
+    }
+    let client = authenticate(
+        issuer,
+        basic,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+
+    let scopes = match request.scope.as_deref() {
+        None => client.scopes.clone(),
+        Some(scope) => {
+            let mut scopes = Vec::new();
+            for value in scope.split_whitespace() {
+                match Scope::parse(value) {
+                    Some(scope) if client.scopes.contains(&scope) => scopes.push(scope),
+                    _ => {
+                        return Err(OAuthError::invalid_scope(&format!(
+                            "The client is not granted {}.",
+                            value
+                        )))
+                    }
+                }
+            }
+            scopes
+        }
+    };
+
+    tracing::info!(client_id = %client.client_id, "access token issued");
+    Ok(NoStore(Json(issuer.issue(&client, &scopes))))
+}
+
+/// introspect an access token
+///
+/// RFC 7662. Clients see their own tokens, clients with the `admin` scope
+/// every token issued here; other tokens are reported as not active.
+#[openapi(tag = "OAuth2")]
+#[post("/oauth/introspect", data = "<request>")]
+pub async fn introspect(
+    issuer: &State<TokenIssuer>,
+    _limit: RateLimit,
+    basic: Option<ClientCredentials>,
+    request: Form<TokenInput>,
+) -> Result<NoStore<Json<IntrospectionResponse>>, OAuthError> {
+    let client = authenticate(
+        issuer,
+        basic,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+
+    let claims = issuer.decode(&request.token).filter(|claims| {
+        claims.client_id == client.client_id || client.scopes.contains(&Scope::Admin)
+    });
+    let response = match claims {
+        Some(claims) => match issuer.is_revoked(&claims).await {
+            Ok(false) => IntrospectionResponse::from(claims),
+            Ok(true) => IntrospectionResponse::default(),
+            Err(_) => return Err(OAuthError::temporarily_unavailable()),
+        },
+        None => IntrospectionResponse::default(),
+    };
+    Ok(NoStore(Json(response)))
+}
+
+/// revoke an access token
+///
+/// RFC 7009. Clients revoke their own tokens; the answer is the same for
+/// tokens that are unknown, expired or already revoked.
+#[openapi(tag = "OAuth2")]
+#[post("/oauth/revoke", data = "<request>")]
+pub async fn revoke(
+    issuer: &State<TokenIssuer>,
+    _limit: RateLimit,
+    basic: Option<ClientCredentials>,
+    request: Form<TokenInput>,
+) -> Result<(), OAuthError> {
+    let client = authenticate(
+        issuer,
+        basic,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+
+    let Some(claims) = issuer
+        .decode(&request.token)
+        .filter(|claims| claims.client_id == client.client_id)
+    else {
+        return Ok(());
+    };
+    if let Err(error) = issuer.revoke(&claims).await {
+        tracing::error!(%error, "cannot revoke access token");
+        return Err(OAuthError::temporarily_unavailable());
+    }
+    tracing::info!(client_id = %client.client_id, jti = %claims.jti, "access token revoked");
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use mongodb::bson::oid::ObjectId;
+use rocket::{serde::json::Json, State};
+use rocket_okapi::openapi;
+
+use crate::{
+    config::AppConfig,
+    db::{tenant::Tenant, webhook},
+    errors::response::MyError,
+    models::webhook::{Webhook, WebhookInput},
+    request_guards::{
+        authorized::{Admin, Authorized},
+        db_available::DbAvailable,
+        rate_limit::RateLimit,
+    },
+};
+
+/// get webhook subscriptions
+#[openapi(tag = "Webhook")]
+#[get("/webhook")]
+pub async fn get_webhooks(
+    tenant: Tenant,
+    _limit: RateLimit,
+    _key: Authorized<Admin>,
+    _available: DbAvailable,
+) -> Result<Json<Vec<Webhook>>, MyError> {
+    match webhook::find_webhooks(&tenant).await {
+        Ok(webhook_docs) => Ok(Json(webhook_docs)),
+        Err(error) => Err(MyError::build(400, Some(error.to_string()))),
+    }
+}
+
+/// get webhook subscription by _id
+#[openapi(tag = "Webhook")]
+#[get("/webhook/<id>")]
+pub async fn get_webhook_by_id(
+    tenant: Tenant,
+    _limit: RateLimit,
+    _key: Authorized<Admin>,
+    _available: DbAvailable,
+    id: &str,
+) -> Result<Json<Webhook>, MyError> {
+    let Ok(oid) = ObjectId::parse_str(id) else {
+        return Err(MyError::build(400, Some("Invalid _id format.".to_string())));
+    };
+
+    match webhook::find_webhook_by_id(&tenant, oid).await {
+        Ok(Some(webhook_doc)) => Ok(Json(Webhook::from(webhook_doc))),
+        _ => Err(MyError::build(
+            400,
+            Some(format!("Webhook not found with _id {}", &id)),
+        )),
+    }
+}
+
+/// subscribe a URL to customer events
+///
+/// Every event is POSTed as JSON with an `X-Webhook-Signature: sha256=<hex>`
+/// header, the HMAC-SHA256 of `"{X-Webhook-Timestamp}.{body}"` keyed with
+/// `secret`. Failed deliveries are retried with exponential backoff.
+#[openapi(tag = "Webhook")]
+#[post("/webhook", data = "<input>")]
+pub async fn post_webhook(
+    tenant: Tenant,
+    config: &State<AppConfig>,
+    _limit: RateLimit,
+    _key: Authorized<Admin>,
+    _available: DbAvailable,
+    input: Json<WebhookInput>,
+) -> Result<Json<String>, MyError> {
+    config
+        .webhooks
+        .check_url(&input.url)
+        .map_err(|error| MyError::build(400, Some(error)))?;
+
+    match webhook::insert_webhook(&tenant, input).await {
+        Ok(webhook_doc_id) => Ok(Json(webhook_doc_id)),
+        Err(error) => Err(MyError::build(400, Some(error.to_string()))),
+    }
+}
+
+/// update a webhook subscription by _id
+#[openapi(tag = "Webhook")]
+#[patch("/webhook/<id>", data = "<input>")]
+pub async fn patch_webhook_by_id(
+    tenant: Tenant,
+    config: &State<AppConfig>,
+    _limit: RateLimit,
+    _key: Authorized<Admin>,
+    _available: DbAvailable,
+    id: &str,
+    input: Json<WebhookInput>,
+) -> Result<Json<Webhook>, MyError> {
+    let Ok(oid) = ObjectId::parse_str(id) else {
+        return Err(MyError::build(400, Some("Invalid id format.".to_string())));
+    };
+    config
+        .webhooks
+        .check_url(&input.url)
+        .map_err(|error| MyError::build(400, Some(error)))?;
+
+    match webhook::update_webhook_by_id(&tenant, oid, input).await {
+        Ok(Some(webhook_doc)) => Ok(Json(webhook_doc)),
+        _ => Err(MyError::build(
+            400,
+            Some(format!("Webhook not found with id {}", &id)),
+        )),
+    }
+}
+
+/// delete a webhook subscription by _id
+#[openapi(tag = "Webhook")]
+#[delete("/webhook/<id>")]
+pub async fn delete_webhook_by_id(
+    tenant: Tenant,
+    _limit: RateLimit,
+    _key: Authorized<Admin>,
+    _available: DbAvailable,
+    id: &str,
+) -> Result<Json<Webhook>, MyError> {
+    let Ok(oid) = ObjectId::parse_str(id) else {
+        return Err(MyError::build(400, Some("Invalid id format.".to_string())));
+    };
+
+    match webhook::delete_webhook_by_id(&tenant, oid).await {
+        Ok(Some(webhook_doc)) => Ok(Json(webhook_doc)),
+        _ => Err(MyError::build(
+            400,
+            Some(format!("Webhook not found with _id {}", &id)),
+        )),
+    }
+}
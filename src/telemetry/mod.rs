@@ -0,0 +1,91 @@
+use std::future::Future;
+
+use opentelemetry::trace::{FutureExt, Status, TraceContextExt, TraceError, Tracer};
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_otlp::{Protocol, WithExportConfig};
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use serde::Deserialize;
+
+pub mod mongo;
+
+/// Name of the tracer creating every span of this service.
+pub const TRACER: &str = "rust-rocket-sample";
+
+/// `[default.telemetry]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    pub enabled: bool,
+    /// Base URL of the OTLP/HTTP collector, spans are sent to `{endpoint}/v1/traces`.
+    pub endpoint: String,
+    /// `http/protobuf` or `http/json`.
+    pub protocol: String,
+    pub service_name: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        TelemetryConfig {
+            enabled: false,
+            endpoint: "http://localhost:4318".into(),
+            protocol: "http/protobuf".into(),
+            service_name: "rust-rocket-sample".into(),
+        }
+    }
+}
+
+impl TelemetryConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        self.otlp_protocol().map(|_| ())
+    }
+
+    fn otlp_protocol(&self) -> Result<Protocol, String> {
+        match self.protocol.as_str() {
+            "http/protobuf" => Ok(Protocol::HttpBinary),
+            "http/json" => Ok(Protocol::HttpJson),
+            other => Err(format!("unsupported OTLP protocol {}", other)),
+        }
+    }
+
+    /// A provider exporting spans in batches to the configured collector.
+    pub fn provider(&self) -> Result<TracerProvider, TraceError> {
+        let protocol = self.otlp_protocol()?;
+        let exporter = opentelemetry_otlp::new_exporter()
+            .http()
+            .with_endpoint(format!("{}/v1/traces", self.endpoint.trim_end_matches('/')))
+            .with_protocol(protocol)
+            .build_span_exporter()?;
+        let resource = Resource::new([KeyValue::new("service.name", self.service_name.clone())]);
+
+        // the exporter runs on its own thread, so spans are still sent
+        // while Rocket's runtime is busy or being shut down
+        Ok(TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::TokioCurrentThread)
+            .with_config(opentelemetry_sdk::trace::Config::default().with_resource(resource))
+            .build())
+    }
+}
+
+/// Run a database operation in a span, child of the current context.
+/// Mongo commands sent by `future` become children of this span.
+pub async fn in_span<T, F>(operation: &'static str, future: F) -> mongodb::error::Result<T>
+where
+    F: Future<Output = mongodb::error::Result<T>>,
+{
+    let tracer = global::tracer(TRACER);
+    let span = tracer
+        .span_builder(operation)
+        .with_attributes([
+            KeyValue::new("db.system", "mongodb"),
+            KeyValue::new("code.function", operation),
+        ])
+        .start(&tracer);
+    let cx = Context::current_with_span(span);
+
+    let result = future.with_context(cx.clone()).await;
+    if let Err(error) = &result {
+        cx.span().set_status(Status::error(error.to_string()));
+    }
+    cx.span().end();
+    result
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use mongodb::event::command::{
+    CommandEventHandler, CommandFailedEvent, CommandStartedEvent, CommandSucceededEvent,
+};
+use opentelemetry::global::{self, BoxedSpan};
+use opentelemetry::trace::{Span, SpanKind, Status, Tracer};
+use opentelemetry::KeyValue;
+
+use super::TRACER;
+
+/// Trace every command sent to MongoDB as a client span,
+/// child of the span current when the command was started.
+#[derive(Default)]
+pub struct CommandTracer {
+    spans: Mutex<HashMap<i32, BoxedSpan>>,
+}
+
+impl CommandTracer {
+    fn finish(&self, request_id: i32) -> Option<BoxedSpan> {
+        self.spans.lock().unwrap().remove(&request_id)
+    }
+}
+
+impl CommandEventHandler for CommandTracer {
+    fn handle_command_started_event(&self, event: CommandStartedEvent) {
+        // the driver calls this on the task sending the command, where
+        // the context of the database operation is the current one
+        let tracer = global::tracer(TRACER);
+        let span = tracer
+            .span_builder(event.command_name.clone())
+            .with_kind(SpanKind::Client)
+            .with_attributes([
+                KeyValue::new("db.system", "mongodb"),
+                KeyValue::new("db.name", event.db),
+                KeyValue::new("db.operation.name", event.command_name),
+                KeyValue::new("server.address", event.connection.address.to_string()),
+            ])
+            .start(&tracer);
+        self.spans.lock().unwrap().insert(event.request_id, span);
+    }
+
+    fn handle_command_succeeded_event(&self, event: CommandSucceededEvent) {
+        if let Some(mut span) = self.finish(event.request_id) {
+            span.end();
+        }
+    }
+
+    fn handle_command_failed_event(&self, event: CommandFailedEvent) {
+        if let Some(mut span) = self.finish(event.request_id) {
+            span.set_status(Status::error(event.failure.to_string()));
+            span.end();
+        }
+    }
+}
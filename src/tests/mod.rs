@@ -1,8 +1,41 @@
 use super::rocket;
-use crate::models::customer::Customer;
+use crate::auth::{
+    jwt::{JwtConfig, JwtVerifier},
+    oauth::{OAuthConfig, TokenIssuer},
+    signature::{body_hash, string_to_sign, SignatureHeader, SignatureVerifier, SigningConfig},
+    ApiKeyStore,
+};
+use crate::config::{page_offset, AppConfig, AuthConfig, PAGE_TOO_LARGE};
+use crate::db::{
+    change_feed::ChangeFeed,
+    customer,
+    tenant::{
+        credentials_filter, Isolation, TenancyConfig, Tenant, TenantConfig, TenantError, Tenants,
+    },
+    DbStatus,
+};
+use crate::fairings::cors::CorsConfig;
+use crate::grpc::{
+    proto::{customer_service_client::CustomerServiceClient, DeleteCustomerRequest},
+    CustomerGrpc,
+};
+use crate::models::api_key::{Role, Scope};
+use crate::models::customer::{Customer, CustomerInput, Ownership};
+use crate::models::event::{CustomerEvent, CustomerEventKind};
+use crate::models::oauth::OAuthClientDocument;
 use crate::models::response::MessageResponse;
-use rocket::{http::Status, local::blocking::Client};
-use serde_json;
+use crate::models::subscription::{Subscription, SubscriptionFilter};
+use crate::rate_limit::RateLimiter;
+use crate::request_guards::{basic::AuthError, bearer::BearerToken, principal::Principal};
+use crate::webhooks::{signature, Dispatcher, WebhookConfig};
+use rocket::{
+    http::{ContentType, Header, Status},
+    local::blocking::Client,
+    serde::json::Json,
+};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread;
 
 #[test]
 fn hello_world() {
@@ -21,9 +54,1721 @@ fn hello_world() {
 #[test]
 fn get_all_users() {
     let client = Client::tracked(rocket()).expect("valid rocket instance");
-    let response = client.get("/customer").dispatch();
+    let response = client.get("/customer").header(api_key()).dispatch();
     assert_eq!(response.status(), Status::Ok);
     let customer: Option<Vec<Customer>> = response.into_json();
 
     assert!(customer.is_some());
 }
+
+#[test]
+fn customer_updates_go_through_the_ownership_filter() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let feed = client.rocket().state::<ChangeFeed>().unwrap();
+    let db = client.rocket().state::<mongodb::Database>().unwrap();
+    let tenant = Tenant::single(db.clone());
+    let input = |name: &str| {
+        Json(CustomerInput {
+            name: name.to_string(),
+            team: Some("blue".to_string()),
+        })
+    };
+    let (blue, red) = (
+        Ownership::Team(Some("blue".to_string())),
+        Ownership::Team(Some("red".to_string())),
+    );
+
+    let runtime = rocket::tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let created = customer::insert_customer(&tenant, feed, &Ownership::All, input("before"))
+            .await
+            .unwrap();
+        let oid = mongodb::bson::oid::ObjectId::parse_str(&created.id).unwrap();
+
+        let updated = customer::update_customer_by_id(&tenant, feed, &blue, oid, input("after"))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.name, "after");
+        assert_eq!(updated.team.as_deref(), Some("blue"));
+        let other_team = customer::update_customer_by_id(&tenant, feed, &red, oid, input("red"))
+            .await
+            .unwrap();
+        assert!(other_team.is_none());
+
+        customer::delete_customer_by_id(&tenant, feed, &Ownership::All, oid)
+            .await
+            .unwrap();
+    });
+}
+
+/// The configured key, granted every scope.
+fn api_key() -> Header<'static> {
+    Header::new("x-api-key", std::env::var("API_KEY").unwrap())
+}
+
+/// Lets requests through to the handlers even when MongoDB is down,
+/// for tests of answers given before the database is queried.
+fn assume_db_available(client: &Client) {
+    client.rocket().state::<DbStatus>().unwrap().set_available();
+}
+
+#[test]
+fn customer_events_resume_from_last_event_id() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let feed = client.rocket().state::<ChangeFeed>().unwrap();
+    let db = client.rocket().state::<mongodb::Database>().unwrap();
+    let tenant = Tenant::single(db.clone());
+    let mut receiver = feed.subscribe();
+    feed.record(
+        &tenant,
+        CustomerEventKind::Created,
+        "first".to_string(),
+        None,
+    );
+    feed.record(
+        &tenant,
+        CustomerEventKind::Deleted,
+        "second".to_string(),
+        None,
+    );
+    let first = receiver.try_recv().unwrap();
+    let second = receiver.try_recv().unwrap();
+    assert_ne!(first.id, second.id);
+
+    // ids of another feed, such as the one before a restart, replay nothing
+    let (epoch, _) = first.id.split_once('-').unwrap();
+    assert!(feed.since("0-1").is_empty());
+    assert_eq!(feed.since(&format!("{}-0", epoch)).len(), 2);
+
+    // end the otherwise infinite stream once the replayed events are sent
+    client.rocket().shutdown().notify();
+    let response = client
+        .get("/customer/events")
+        .header(api_key())
+        .header(Header::new("Last-Event-ID", first.id))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let body = response.into_string().unwrap();
+    assert!(!body.contains("first"));
+    assert!(body.contains("event:deleted"));
+    assert!(body.contains(&format!("id:{}", second.id)));
+    assert!(body.contains("second"));
+}
+
+fn websocket_upgrade(client: &Client, api_key: &str) -> Status {
+    client
+        .get("/customer/ws")
+        .header(Header::new("Connection", "Upgrade"))
+        .header(Header::new("Upgrade", "websocket"))
+        .header(Header::new("Sec-WebSocket-Version", "13"))
+        .header(Header::new("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="))
+        .header(Header::new("x-api-key", api_key.to_string()))
+        .dispatch()
+        .status()
+}
+
+#[test]
+fn customer_websocket_requires_valid_api_key() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let api_key = std::env::var("API_KEY").unwrap();
+
+    assert_eq!(websocket_upgrade(&client, "wrong"), Status::Unauthorized);
+    // the local client accepts the upgrade without switching protocols
+    assert_eq!(websocket_upgrade(&client, &api_key), Status::Ok);
+}
+
+#[test]
+fn unauthenticated_websockets_are_closed_after_the_auth_timeout() {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let figment = rocket::Config::figment()
+        .merge(("address", "127.0.0.1"))
+        .merge(("port", port))
+        .merge(("grpc.enabled", false))
+        .merge(("auth.websocket_auth_timeout_secs", 1));
+    let server = rocket().configure(figment);
+    thread::spawn(move || {
+        let runtime = rocket::tokio::runtime::Runtime::new().unwrap();
+        let _ = runtime.block_on(server.launch());
+    });
+
+    let mut socket = (0..50)
+        .find_map(|_| {
+            thread::sleep(std::time::Duration::from_millis(100));
+            std::net::TcpStream::connect(("127.0.0.1", port)).ok()
+        })
+        .expect("server listening");
+    socket
+        .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+        .unwrap();
+    let handshake = format!(
+        "GET /customer/ws HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nConnection: Upgrade\r\n\
+         Upgrade: websocket\r\nSec-WebSocket-Version: 13\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+        port
+    );
+    socket.write_all(handshake.as_bytes()).unwrap();
+
+    let mut reader = BufReader::new(socket);
+    let mut status = String::new();
+    reader.read_line(&mut status).unwrap();
+    assert!(status.contains("101"), "{}", status);
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line.trim().is_empty() {
+            break;
+        }
+    }
+    // an unmasked close frame carrying code 1008
+    let mut frame = [0; 4];
+    reader.read_exact(&mut frame).unwrap();
+    assert_eq!(frame[0], 0x88);
+    assert_eq!(u16::from_be_bytes([frame[2], frame[3]]), 1008);
+}
+
+#[test]
+fn subscriptions_narrow_on_unsubscribe() {
+    let event = |customer_id: &str, kind| CustomerEvent {
+        id: "epoch-1".to_string(),
+        seq: 1,
+        kind,
+        customer_id: customer_id.to_string(),
+        customer: None,
+        tenant_id: None,
+    };
+    let filter = |ids: &[&str], kinds: &[CustomerEventKind]| SubscriptionFilter {
+        ids: ids.iter().map(|id| id.to_string()).collect(),
+        kinds: kinds.to_vec(),
+    };
+    let (created, updated) = (CustomerEventKind::Created, CustomerEventKind::Updated);
+
+    let mut subscription = Subscription::default();
+    assert!(!subscription.matches(&event("a", created)));
+
+    // unsubscribing the last kind leaves nothing rather than every kind
+    subscription.subscribe(filter(&["a"], &[created]));
+    assert!(subscription.matches(&event("a", created)));
+    assert!(!subscription.matches(&event("a", updated)));
+    assert!(!subscription.matches(&event("b", created)));
+    subscription.unsubscribe(filter(&[], &[created]));
+    assert!(!subscription.matches(&event("a", created)));
+    assert!(!subscription.matches(&event("a", updated)));
+
+    // ids can be left out of a subscription to every customer
+    subscription.subscribe(filter(&[], &[]));
+    subscription.unsubscribe(filter(&["a"], &[]));
+    assert!(!subscription.matches(&event("a", updated)));
+    assert!(subscription.matches(&event("b", updated)));
+
+    // each id keeps its own kinds
+    subscription.subscribe(filter(&["a"], &[created]));
+    subscription.unsubscribe(filter(&["b"], &[updated]));
+    assert!(subscription.matches(&event("a", created)));
+    assert!(!subscription.matches(&event("a", updated)));
+    assert!(subscription.matches(&event("b", created)));
+    assert!(!subscription.matches(&event("b", updated)));
+    assert!(subscription.matches(&event("c", updated)));
+
+    subscription.unsubscribe(filter(&[], &[]));
+    assert_eq!(subscription, Subscription::default());
+}
+
+#[test]
+fn webhooks_require_api_key() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client
+        .post("/webhook")
+        .header(rocket::http::ContentType::JSON)
+        .body(r#"{"url": "http://127.0.0.1:1/hook", "secret": "s3cret"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn webhooks_cannot_target_private_addresses() {
+    let config = WebhookConfig::default();
+    for url in [
+        "http://169.254.169.254/latest/meta-data",
+        "http://localhost:8000/hook",
+        "http://127.0.0.1/hook",
+        "http://10.0.0.1/hook",
+        "http://[::1]/hook",
+        "http://[::ffff:192.168.0.1]/hook",
+        "ftp://example.com/hook",
+    ] {
+        assert!(config.check_url(url).is_err(), "{}", url);
+    }
+    assert_eq!(config.check_url("https://example.com/hook"), Ok(()));
+    let config = WebhookConfig {
+        allow_private_targets: true,
+        ..config
+    };
+    assert_eq!(config.check_url("http://127.0.0.1/hook"), Ok(()));
+
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    assume_db_available(&client);
+    let response = client
+        .post("/webhook")
+        .header(rocket::http::ContentType::JSON)
+        .header(api_key())
+        .body(r#"{"url": "http://169.254.169.254/", "secret": "s3cret"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let response = client
+        .patch("/webhook/000000000000000000000000")
+        .header(rocket::http::ContentType::JSON)
+        .header(api_key())
+        .body(r#"{"url": "http://localhost/", "secret": "s3cret"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(
+        body["error"]["description"],
+        "The url must not point to a private address."
+    );
+}
+
+/// Accept a single request and answer it with `status`,
+/// returning its lower-cased headers and body.
+fn receive_one(
+    listener: TcpListener,
+    status: &'static str,
+) -> thread::JoinHandle<(Vec<String>, String)> {
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut headers = vec![];
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            headers.push(line.trim().to_lowercase());
+        }
+        let length: usize = headers
+            .iter()
+            .find_map(|header| header.strip_prefix("content-length: "))
+            .map(|length| length.parse().unwrap())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+        (headers, String::from_utf8(body).unwrap())
+    })
+}
+
+#[test]
+fn webhook_delivery_is_signed() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let receiver = receive_one(listener, "204 No Content");
+
+    let payload = r#"{"kind":"created"}"#;
+    let dispatcher = Dispatcher::new(WebhookConfig::default());
+    let runtime = rocket::tokio::runtime::Runtime::new().unwrap();
+    let result = runtime.block_on(dispatcher.deliver(&url, "s3cret", "1", "created", payload));
+    assert_eq!(result, Ok(()));
+
+    let (headers, body) = receiver.join().unwrap();
+    assert_eq!(body, payload);
+    assert!(headers.contains(&"x-webhook-event: created".to_string()));
+    let timestamp: i64 = headers
+        .iter()
+        .find_map(|header| header.strip_prefix("x-webhook-timestamp: "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    let expected = signature::sign("s3cret", timestamp, payload);
+    assert!(headers.contains(&format!("x-webhook-signature: {}", expected)));
+}
+
+#[test]
+fn webhook_delivery_fails_on_error_status() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let receiver = receive_one(listener, "500 Internal Server Error");
+
+    let dispatcher = Dispatcher::new(WebhookConfig::default());
+    let runtime = rocket::tokio::runtime::Runtime::new().unwrap();
+    let result = runtime.block_on(dispatcher.deliver(&url, "s3cret", "1", "deleted", "{}"));
+    assert!(result.is_err());
+    receiver.join().unwrap();
+
+    let config = WebhookConfig::default();
+    assert_eq!(config.backoff(1).as_secs(), config.backoff_base);
+    assert_eq!(config.backoff(3).as_secs(), config.backoff_base * 4);
+    assert_eq!(config.backoff(100).as_secs(), config.backoff_max);
+}
+
+#[test]
+fn graphql_mutations_require_api_key() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    assume_db_available(&client);
+    let response = client
+        .post("/graphql")
+        .header(rocket::http::ContentType::JSON)
+        .body(
+            r#"{"query": "mutation { deleteCustomer(id: \"000000000000000000000000\") { id } }"}"#,
+        )
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["errors"][0]["message"], "Unauthorized");
+}
+
+#[test]
+fn graphql_requests_are_rate_limited() {
+    let figment = rocket::Config::figment().merge((
+        "rate_limit.routes",
+        serde_json::json!({
+            "POST /graphql": {"anonymous": {"capacity": 1, "per_second": 0.01}}
+        }),
+    ));
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    assume_db_available(&client);
+    let query = || {
+        client
+            .post("/graphql")
+            .header(rocket::http::ContentType::JSON)
+            .body(r#"{"query": "{ customers { id } }"}"#)
+            .dispatch()
+            .status()
+    };
+    assert_eq!(query(), Status::Ok);
+    assert_eq!(query(), Status::TooManyRequests);
+}
+
+#[test]
+fn pages_before_the_first_are_rejected() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    assume_db_available(&client);
+    let response = client
+        .post("/graphql")
+        .header(rocket::http::ContentType::JSON)
+        .header(api_key())
+        .body(r#"{"query": "{ customers(page: 0) { id } }"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["errors"][0]["message"], "page cannot be less than 1");
+
+    let response = client.get("/customer?page=-1").header(api_key()).dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["error"]["description"], "page cannot be less than 1");
+}
+
+#[test]
+fn pages_too_far_to_be_counted_are_rejected() {
+    assert_eq!(page_offset(3, 12), Ok(24));
+    assert_eq!(page_offset(i64::MAX, 12), Err(PAGE_TOO_LARGE));
+
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    assume_db_available(&client);
+    let response = client
+        .get(format!("/customer?page={}", i64::MAX))
+        .header(api_key())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["error"]["description"], PAGE_TOO_LARGE);
+
+    let response = client
+        .post("/graphql")
+        .header(rocket::http::ContentType::JSON)
+        .header(api_key())
+        .body(format!(
+            r#"{{"query": "{{ customers(page: {}) {{ id }} }}"}}"#,
+            i64::MAX
+        ))
+        .dispatch();
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["errors"][0]["message"], PAGE_TOO_LARGE);
+}
+
+#[test]
+fn graphiql_playground() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client.get("/graphiql").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.into_string().unwrap().contains("/graphql"));
+}
+
+#[test]
+fn grpc_mutations_require_api_key() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let tenants = client.rocket().state::<Tenants>().unwrap().clone();
+    let feed = client.rocket().state::<ChangeFeed>().unwrap().clone();
+    let keys = client.rocket().state::<ApiKeyStore>().unwrap().clone();
+    let config = client.rocket().state::<AppConfig>().unwrap().clone();
+
+    let runtime = rocket::tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let listener = rocket::tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let incoming =
+            tonic::transport::server::TcpIncoming::from_listener(listener, true, None).unwrap();
+        rocket::tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(
+                    CustomerGrpc::new(tenants, feed, keys, None, None, &config).into_server(),
+                )
+                .serve_with_incoming(incoming),
+        );
+
+        let mut grpc = CustomerServiceClient::connect(url).await.unwrap();
+        let delete = || DeleteCustomerRequest {
+            id: "000000000000000000000000".to_string(),
+        };
+
+        let status = grpc.delete_customer(delete()).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let mut request = tonic::Request::new(delete());
+        request
+            .metadata_mut()
+            .insert("x-api-key", "wrong".parse().unwrap());
+        let status = grpc.delete_customer(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    });
+}
+
+#[test]
+fn hello_world_msgpack() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client
+        .get("/")
+        .header(Header::new(
+            "Accept",
+            "application/json;q=0.5, application/msgpack",
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.content_type(),
+        Some(rocket::http::ContentType::MsgPack)
+    );
+
+    let message: MessageResponse = rmp_serde::from_slice(&response.into_bytes().unwrap()).unwrap();
+    assert_eq!(message.message, "Hello World!");
+}
+
+#[test]
+fn errors_are_negotiated() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    assume_db_available(&client);
+    let response = client
+        .get("/customer/not-an-id")
+        .header(api_key())
+        .header(Header::new("Accept", "application/cbor"))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(
+        response.content_type().unwrap().to_string(),
+        "application/cbor"
+    );
+
+    let body: serde_json::Value =
+        ciborium::from_reader(&response.into_bytes().unwrap()[..]).unwrap();
+    assert_eq!(body["error"]["code"], 400);
+}
+
+#[test]
+fn openapi_lists_alternative_media_types() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client.get("/openapi.json").dispatch();
+    let spec: serde_json::Value = response.into_json().unwrap();
+
+    let content = &spec["paths"]["/customer"]["get"]["responses"]["200"]["content"];
+    for media_type in [
+        "application/json",
+        "application/msgpack",
+        "application/cbor",
+    ] {
+        assert!(content.get(media_type).is_some(), "{} missing", media_type);
+    }
+    let body = &spec["paths"]["/customer"]["post"]["requestBody"]["content"];
+    assert!(body.get("application/cbor").is_some());
+}
+
+#[test]
+fn large_responses_are_compressed() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client
+        .get("/openapi.json")
+        .header(Header::new("Accept-Encoding", "gzip, br;q=0"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+
+    let mut spec = String::new();
+    flate2::read::GzDecoder::new(&response.into_bytes().unwrap()[..])
+        .read_to_string(&mut spec)
+        .unwrap();
+    assert!(spec.contains("\"openapi\""));
+
+    // small bodies are not worth it
+    let response = client
+        .get("/")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch();
+    assert_eq!(response.headers().get_one("Content-Encoding"), None);
+}
+
+fn cors_client() -> Client {
+    let figment = rocket::Config::figment()
+        .merge(("cors.allowed_origins", ["https://*.example.com"]))
+        .merge(("cors.allow_credentials", true));
+    Client::tracked(rocket().configure(figment)).expect("valid rocket instance")
+}
+
+#[test]
+fn cors_preflight_lists_route_methods() {
+    let client = cors_client();
+    let response = client
+        .options("/customer/000000000000000000000000")
+        .header(Header::new("Origin", "https://admin.example.com"))
+        .header(Header::new("Access-Control-Request-Method", "DELETE"))
+        .header(Header::new("Access-Control-Request-Headers", "x-api-key"))
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+
+    let headers = response.headers();
+    assert_eq!(
+        headers.get_one("Access-Control-Allow-Origin"),
+        Some("https://admin.example.com")
+    );
+    assert_eq!(
+        headers.get_one("Access-Control-Allow-Methods"),
+        Some("DELETE, GET, PATCH")
+    );
+    assert_eq!(
+        headers.get_one("Access-Control-Allow-Headers"),
+        Some("x-api-key")
+    );
+    assert_eq!(
+        headers.get_one("Access-Control-Allow-Credentials"),
+        Some("true")
+    );
+    assert!(headers.get("Vary").any(|vary| vary.contains("Origin")));
+}
+
+#[test]
+fn cors_rejects_unknown_origins() {
+    let client = cors_client();
+    let response = client
+        .options("/customer")
+        .header(Header::new("Origin", "https://example.com.evil.org"))
+        .header(Header::new("Access-Control-Request-Method", "POST"))
+        .dispatch();
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        None
+    );
+
+    let response = client
+        .get("/")
+        .header(Header::new("Origin", "https://evil.org"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        None
+    );
+}
+
+#[test]
+fn cors_never_reflects_any_origin_with_credentials() {
+    let figment = rocket::Config::figment()
+        .merge(("cors.allowed_origins", ["*"]))
+        .merge(("cors.allow_credentials", true));
+    let Err(error) = Client::tracked(rocket().configure(figment)) else {
+        panic!("ignite should fail");
+    };
+    assert!(matches!(
+        error.kind(),
+        rocket::error::ErrorKind::FailedFairings(_)
+    ));
+
+    // even when handed such a configuration, only listed origins pass
+    let config = CorsConfig {
+        allowed_origins: vec!["*".into(), "https://app.example.com".into()],
+        allow_credentials: true,
+        ..Default::default()
+    };
+    assert!(!config.allows_origin("https://evil.org"));
+    assert!(config.allows_origin("https://app.example.com"));
+    assert!(CorsConfig::default().allows_origin("https://evil.org"));
+}
+
+#[test]
+fn security_headers_relax_the_policy_of_the_swagger_ui() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client.get("/").dispatch();
+    let headers = response.headers();
+    assert_eq!(
+        headers.get_one("Content-Security-Policy"),
+        Some("default-src 'none'; frame-ancestors 'none'")
+    );
+    assert_eq!(headers.get_one("X-Frame-Options"), Some("DENY"));
+    assert_eq!(headers.get_one("X-Content-Type-Options"), Some("nosniff"));
+    assert_eq!(headers.get_one("Referrer-Policy"), Some("no-referrer"));
+    assert!(headers
+        .get_one("Strict-Transport-Security")
+        .is_some_and(|hsts| hsts.starts_with("max-age=")));
+
+    let response = client.get("/api-docs/index.html").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let policy = response
+        .headers()
+        .get_one("Content-Security-Policy")
+        .unwrap();
+    assert!(policy.contains("style-src 'self' 'unsafe-inline'"));
+    // a path merely starting like a relaxed one keeps the strict policy
+    let response = client.get("/api-docs-old").dispatch();
+    assert_eq!(
+        response.headers().get_one("Content-Security-Policy"),
+        Some("default-src 'none'; frame-ancestors 'none'")
+    );
+
+    let figment = rocket::Config::figment().merge(("security_headers.frame_options", ""));
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    let response = client.get("/").dispatch();
+    assert_eq!(response.headers().get_one("X-Frame-Options"), None);
+}
+
+#[test]
+fn metrics_are_labelled_by_route() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    assume_db_available(&client);
+    client.get("/").dispatch();
+    client
+        .get("/customer/not-an-id")
+        .header(api_key())
+        .dispatch();
+
+    let response = client.get("/metrics").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body = response.into_string().unwrap();
+    assert!(body.contains(r#"http_requests_total{method="GET",route="/",status="2xx"}"#));
+    assert!(body.contains(r#"route="/customer/<id>",status="4xx""#));
+    assert!(body.contains("http_request_duration_seconds_bucket"));
+    assert!(body.contains("http_requests_in_flight"));
+}
+
+#[test]
+fn metrics_can_require_api_key() {
+    let figment = rocket::Config::figment().merge(("metrics.require_api_key", true));
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    let response = client.get("/metrics").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let api_key = std::env::var("API_KEY").unwrap();
+    let response = client
+        .get("/metrics")
+        .header(Header::new("x-api-key", api_key))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[test]
+fn request_ids_are_echoed() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    assume_db_available(&client);
+    let response = client
+        .get("/")
+        .header(Header::new("X-Request-Id", "abc-123"))
+        .dispatch();
+    assert_eq!(response.headers().get_one("X-Request-Id"), Some("abc-123"));
+
+    // unprintable ids are replaced with a generated one
+    let response = client
+        .get("/customer/not-an-id")
+        .header(api_key())
+        .header(Header::new("X-Request-Id", "a b"))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let request_id = response
+        .headers()
+        .get_one("X-Request-Id")
+        .unwrap()
+        .to_owned();
+    assert_eq!(request_id.len(), 36);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["error"]["requestId"], request_id.as_str());
+}
+
+#[test]
+fn request_spans_continue_the_caller_trace() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let collector = receive_one(listener, "200 OK");
+
+    let figment = rocket::Config::figment()
+        .merge(("telemetry.enabled", true))
+        .merge(("telemetry.endpoint", endpoint))
+        .merge(("telemetry.protocol", "http/json"));
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    let response = client
+        .get("/")
+        .header(Header::new(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    let provider = client
+        .rocket()
+        .state::<opentelemetry_sdk::trace::TracerProvider>()
+        .unwrap();
+    provider.force_flush();
+
+    let (headers, body) = collector.join().unwrap();
+    assert!(headers[0].starts_with("post /v1/traces"));
+    let export: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let spans = export["resourceSpans"][0]["scopeSpans"][0]["spans"]
+        .as_array()
+        .unwrap();
+    let span = spans
+        .iter()
+        .find(|span| span["traceId"] == "4bf92f3577b34da6a3ce929d0e0e4736")
+        .expect("request span in the caller's trace");
+    assert_eq!(span["name"], "GET /");
+    assert_eq!(span["parentSpanId"], "00f067aa0ba902b7");
+}
+
+#[test]
+fn rate_limit_answers_429_once_the_bucket_is_empty() {
+    let figment = rocket::Config::figment().merge((
+        "rate_limit.routes",
+        serde_json::json!({
+            "GET /customer/<id>": {"anonymous": {"capacity": 2, "per_second": 0.01}}
+        }),
+    ));
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    assume_db_available(&client);
+    for remaining in ["1", "0"] {
+        let response = client.get("/customer/not-an-id").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(response.headers().get_one("RateLimit-Limit"), Some("2"));
+        assert_eq!(
+            response.headers().get_one("RateLimit-Remaining"),
+            Some(remaining)
+        );
+    }
+
+    let response = client.get("/customer/not-an-id").dispatch();
+    assert_eq!(response.status(), Status::TooManyRequests);
+    assert_eq!(response.headers().get_one("Retry-After"), Some("100"));
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["error"]["code"], 429);
+
+    // API key holders have buckets of their own
+    let response = client
+        .get("/customer/not-an-id")
+        .header(api_key())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn grpc_calls_share_the_rest_buckets() {
+    let figment = rocket::Config::figment().merge((
+        "rate_limit.anonymous",
+        serde_json::json!({"capacity": 2, "per_second": 0.01}),
+    ));
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    let tenants = client.rocket().state::<Tenants>().unwrap().clone();
+    let feed = client.rocket().state::<ChangeFeed>().unwrap().clone();
+    let keys = client.rocket().state::<ApiKeyStore>().unwrap().clone();
+    let limiter = client.rocket().state::<RateLimiter>().cloned();
+    let config = client.rocket().state::<AppConfig>().unwrap().clone();
+    let service = CustomerGrpc::new(tenants, feed, keys, None, limiter, &config);
+
+    let runtime = rocket::tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let listener = rocket::tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let incoming =
+            tonic::transport::server::TcpIncoming::from_listener(listener, true, None).unwrap();
+        rocket::tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(service.into_server())
+                .serve_with_incoming(incoming),
+        );
+
+        let mut grpc = CustomerServiceClient::connect(url).await.unwrap();
+        let delete = || DeleteCustomerRequest {
+            id: "000000000000000000000000".to_string(),
+        };
+        for _ in 0..2 {
+            let status = grpc.delete_customer(delete()).await.unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        }
+        let status = grpc.delete_customer(delete()).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(status.metadata().get("retry-after").unwrap(), "100");
+    });
+
+    // the same client over REST finds its bucket empty
+    let response = client
+        .get("/customer/not-an-id")
+        .remote("127.0.0.1:8000".parse().unwrap())
+        .dispatch();
+    assert_eq!(response.status(), Status::TooManyRequests);
+}
+
+#[test]
+fn degraded_start_answers_503() {
+    let figment = rocket::Config::figment()
+        .merge(("mongodb.degraded_start", true))
+        .merge(("mongodb.connect_deadline_secs", 0));
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    let response = client.get("/customer").header(api_key()).dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["error"]["code"], 503);
+
+    let response = client
+        .post("/graphql")
+        .header(rocket::http::ContentType::JSON)
+        .header(api_key())
+        .body(r#"{"query": "{ customers { id } }"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+
+    // routes not touching MongoDB keep working
+    assert_eq!(client.get("/").dispatch().status(), Status::Ok);
+}
+
+#[test]
+fn openapi_documents_rate_limits() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let openapi: serde_json::Value = client.get("/openapi.json").dispatch().into_json().unwrap();
+    let response = &openapi["paths"]["/customer"]["get"]["responses"]["429"];
+    assert!(response["headers"]["Retry-After"].is_object());
+}
+
+#[test]
+fn readiness_fails_when_grpc_cannot_listen() {
+    let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+    let figment = rocket::Config::figment()
+        .merge(("grpc.address", "127.0.0.1"))
+        .merge(("grpc.port", taken.local_addr().unwrap().port()));
+    let client =
+        Client::tracked(rocket().configure(figment.clone())).expect("valid rocket instance");
+    let response = client.get("/health/ready").dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["components"]["grpc"]["status"], "down");
+
+    drop(taken);
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    let response = client.get("/health/ready").dispatch();
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["components"]["grpc"]["status"], "up");
+}
+
+#[test]
+fn readiness_fails_during_shutdown() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client.get("/health/live").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["status"], "up");
+
+    client
+        .rocket()
+        .state::<crate::health::Readiness>()
+        .unwrap()
+        .shut_down();
+    let response = client.get("/health/ready").dispatch();
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["status"], "down");
+    assert_eq!(body["components"]["server"]["details"], "shutting down");
+    assert!(body["components"]["mongodb"]["latencyMs"].is_u64());
+
+    // probes stay out of the request metrics
+    let metrics = client.get("/metrics").dispatch().into_string().unwrap();
+    assert!(!metrics.contains("/health/"));
+}
+
+#[test]
+fn configuration_reads_the_legacy_env_vars() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let config = client.rocket().state::<AppConfig>().unwrap();
+    assert_eq!(config.auth.api_key, std::env::var("API_KEY").unwrap());
+    assert_eq!(
+        config.mongodb.database,
+        std::env::var("MONGO_DB_NAME").unwrap()
+    );
+    assert_eq!(config.pagination.limit(None), 12);
+    assert_eq!(config.pagination.limit(Some(1000)), 100);
+}
+
+#[test]
+fn invalid_configuration_aborts_ignite() {
+    let figment = rocket::Config::figment()
+        .merge(("pagination.default_limit", 50))
+        .merge(("pagination.max_limit", 10));
+    let Err(error) = Client::tracked(rocket().configure(figment)) else {
+        panic!("ignite should fail");
+    };
+    assert!(matches!(
+        error.kind(),
+        rocket::error::ErrorKind::FailedFairings(_)
+    ));
+}
+
+#[test]
+fn api_keys_are_peppered_and_verified() {
+    let auth = AuthConfig {
+        api_key: "bootstrap-key".to_string(),
+        pepper: "a pepper of sixteen bytes".to_string(),
+        ..Default::default()
+    };
+    let store = ApiKeyStore::new(auth.clone(), None);
+    let other = ApiKeyStore::new(
+        AuthConfig {
+            pepper: "another pepper entirely".to_string(),
+            ..auth
+        },
+        None,
+    );
+    assert_ne!(store.hash("secret"), other.hash("secret"));
+
+    let runtime = rocket::tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let key = store.verify("bootstrap-key").await.unwrap();
+        assert_eq!(key.scopes, Scope::ALL.to_vec());
+        assert!(key.identity().starts_with("sha256:"));
+
+        assert!(matches!(
+            store.verify("bootstrap-kez").await,
+            Err(AuthError::Invalid)
+        ));
+        assert!(matches!(
+            store.verify("rrs_0123456789ab_secret").await,
+            Err(AuthError::Invalid)
+        ));
+    });
+}
+
+#[test]
+fn api_keys_are_not_cached_without_a_change_stream() {
+    let auth = AuthConfig {
+        pepper: "a pepper of sixteen bytes".to_string(),
+        cache_ttl_secs: 30,
+        ..Default::default()
+    };
+    // revocations by other instances would go unnoticed
+    let store = ApiKeyStore::new(auth, None);
+    assert_eq!(store.cache_ttl(), std::time::Duration::ZERO);
+}
+
+#[test]
+fn admin_routes_require_an_admin_key() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client.get("/admin/api-keys").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let openapi: serde_json::Value = client.get("/openapi.json").dispatch().into_json().unwrap();
+    let operation = &openapi["paths"]["/admin/api-keys/{id}/rotate"]["post"];
+    assert_eq!(operation["tags"][0], "Admin");
+    assert!(operation["responses"]["403"].is_object());
+}
+
+#[test]
+fn customer_routes_require_scopes() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client.get("/customer").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["error"]["reason"], "Unauthorized");
+
+    let openapi: serde_json::Value = client.get("/openapi.json").dispatch().into_json().unwrap();
+    let operation = &openapi["paths"]["/customer/{id}"]["patch"];
+    assert_eq!(
+        operation["security"][0]["ApiKey"],
+        serde_json::json!(["customer:write"])
+    );
+    assert!(operation["responses"]["403"].is_object());
+}
+
+#[test]
+fn bearer_tokens_are_verified_against_the_jwks() {
+    let jwks = std::env::temp_dir().join(format!("jwks-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(
+        &jwks,
+        r#"{"keys": [{"kty": "oct", "kid": "test", "alg": "HS256",
+            "k": "YSBzaGFyZWQgc2VjcmV0IGZvciBiZWFyZXIgdG9rZW5z"}]}"#,
+    )
+    .unwrap();
+    let token = |claims: serde_json::Value, kid: &str| {
+        let mut header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS256);
+        header.kid = Some(kid.to_string());
+        let key = jsonwebtoken::EncodingKey::from_secret(b"a shared secret for bearer tokens");
+        Header::new(
+            "Authorization",
+            format!(
+                "Bearer {}",
+                jsonwebtoken::encode(&header, &claims, &key).unwrap()
+            ),
+        )
+    };
+    let exp = chrono::Utc::now().timestamp() + 600;
+    let claims = |aud: &str, scope: &str, exp: i64| {
+        serde_json::json!({
+            "iss": "https://issuer.test/", "aud": aud, "sub": "client-1",
+            "scope": scope, "exp": exp,
+        })
+    };
+
+    let figment = rocket::Config::figment()
+        .merge(("auth.jwt.enabled", true))
+        .merge(("auth.jwt.jwks_path", jwks.to_str().unwrap()))
+        .merge(("auth.jwt.issuer", "https://issuer.test/"))
+        .merge(("auth.jwt.audience", "rust-rocket-sample"))
+        .merge((
+            "auth.jwt.scope_map",
+            serde_json::json!({"writer": ["customer:write"]}),
+        ));
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    assume_db_available(&client);
+    let post = |authorization: Header<'static>| {
+        client
+            .post("/customer")
+            .header(authorization)
+            .json(&serde_json::json!({"name": 1}))
+            .dispatch()
+            .status()
+    };
+
+    let reader = claims("rust-rocket-sample", "customer:read", exp);
+    assert_eq!(post(token(reader, "test")), Status::Forbidden);
+    // `writer` is mapped to customer:write, so the guard lets the bad body through
+    let writer = claims("rust-rocket-sample", "openid writer", exp);
+    assert_eq!(
+        post(token(writer.clone(), "test")),
+        Status::UnprocessableEntity
+    );
+    assert_eq!(post(token(writer, "rotated")), Status::Unauthorized);
+    // the editor role grants customer:write
+    let mut editor = claims("rust-rocket-sample", "", exp);
+    editor["roles"] = serde_json::json!(["editor"]);
+    assert_eq!(post(token(editor, "test")), Status::UnprocessableEntity);
+    let other_audience = claims("another-api", "writer", exp);
+    assert_eq!(post(token(other_audience, "test")), Status::Unauthorized);
+    let expired = claims("rust-rocket-sample", "writer", exp - 3600);
+    assert_eq!(post(token(expired, "test")), Status::Unauthorized);
+
+    let openapi: serde_json::Value = client.get("/openapi.json").dispatch().into_json().unwrap();
+    let operation = &openapi["paths"]["/customer"]["post"];
+    assert_eq!(
+        operation["security"][1]["Bearer"],
+        serde_json::json!(["customer:write"])
+    );
+    assert_eq!(
+        openapi["components"]["securitySchemes"]["Bearer"]["scheme"],
+        "bearer"
+    );
+    std::fs::remove_file(jwks).unwrap();
+}
+
+/// A registered client, as read from the `oauth_clients` collection.
+fn oauth_client(scopes: Vec<Scope>) -> OAuthClientDocument {
+    OAuthClientDocument {
+        id: mongodb::bson::oid::ObjectId::new(),
+        client_id: "rrc_0123456789ab".to_string(),
+        name: "partner".to_string(),
+        owner: "partner team".to_string(),
+        secret_hash: String::new(),
+        scopes,
+        team: None,
+        tenant: None,
+        revoked_at: None,
+        created_at: chrono::Utc::now(),
+    }
+}
+
+#[test]
+fn oauth_tokens_are_verified_until_revoked() {
+    let store = ApiKeyStore::new(AuthConfig::default(), None);
+    let issuer = TokenIssuer::new(
+        OAuthConfig::default(),
+        b"a signing key of thirty-two bytes",
+        store.clone(),
+        None,
+    );
+    let verifier = JwtVerifier::new(JwtConfig::default(), issuer.clone());
+    let forger = TokenIssuer::new(
+        OAuthConfig::default(),
+        b"another key of at least 32 bytes",
+        store,
+        None,
+    );
+    let client = oauth_client(vec![Scope::CustomerRead, Scope::CustomerWrite]);
+
+    let runtime = rocket::tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let issued = issuer.issue(&client, &[Scope::CustomerRead]);
+        assert_eq!(issued.token_type, "Bearer");
+        assert_eq!(issued.scope, "customer:read");
+        let token = verifier.verify(&issued.access_token).await.unwrap();
+        assert_eq!(token.scopes, vec![Scope::CustomerRead]);
+        assert_eq!(token.identity(), "jwt:rrc_0123456789ab");
+
+        let forged = forger.issue(&client, &[Scope::CustomerWrite]);
+        assert!(matches!(
+            verifier.verify(&forged.access_token).await,
+            Err(AuthError::Invalid)
+        ));
+
+        let claims = issuer.decode(&issued.access_token).unwrap();
+        issuer.revoke(&claims).await.unwrap();
+        assert!(matches!(
+            verifier.verify(&issued.access_token).await,
+            Err(AuthError::Invalid)
+        ));
+    });
+}
+
+#[test]
+fn oauth_token_endpoint_follows_rfc_6749() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let form = ContentType::Form;
+
+    let response = client
+        .post("/oauth/token")
+        .header(form.clone())
+        .body("grant_type=password&username=a&password=b")
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(
+        response.headers().get_one("Cache-Control"),
+        Some("no-store")
+    );
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["error"], "unsupported_grant_type");
+
+    let response = client
+        .post("/oauth/token")
+        .header(form)
+        .body("grant_type=client_credentials")
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert!(response.headers().get_one("WWW-Authenticate").is_some());
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["error"], "invalid_client");
+
+    // tokens issued by this instance are accepted by the scope guards
+    let issuer = client.rocket().state::<TokenIssuer>().unwrap();
+    let issued = issuer.issue(
+        &oauth_client(vec![Scope::CustomerRead]),
+        &[Scope::CustomerRead],
+    );
+    let bearer = Header::new("Authorization", format!("Bearer {}", issued.access_token));
+    let response = client.get("/admin/api-keys").header(bearer).dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let openapi: serde_json::Value = client.get("/openapi.json").dispatch().into_json().unwrap();
+    let flow = &openapi["components"]["securitySchemes"]["OAuth2"]["flows"]["clientCredentials"];
+    assert_eq!(flow["tokenUrl"], "/oauth/token");
+    assert!(flow["scopes"]["customer:read"].is_string());
+    assert_eq!(
+        openapi["paths"]["/customer/{id}"]["delete"]["security"][2]["OAuth2"],
+        serde_json::json!(["customer:delete"])
+    );
+    assert!(openapi["paths"]["/oauth/introspect"]["post"].is_object());
+}
+
+#[test]
+fn principals_only_access_customers_of_their_team() {
+    let store = ApiKeyStore::new(
+        AuthConfig {
+            api_key: "bootstrap-key".to_string(),
+            ..Default::default()
+        },
+        None,
+    );
+    let issuer = TokenIssuer::new(
+        OAuthConfig::default(),
+        b"a signing key of thirty-two bytes",
+        store.clone(),
+        None,
+    );
+    let verifier = JwtVerifier::new(JwtConfig::default(), issuer.clone());
+    let client = OAuthClientDocument {
+        team: Some("blue".to_string()),
+        ..oauth_client(vec![Scope::CustomerRead])
+    };
+    let customer = |team: Option<&str>| Customer {
+        id: "65f1a0c2e4b0a1b2c3d4e5f6".to_string(),
+        name: "customer".to_string(),
+        created_at: chrono::Utc::now().to_string(),
+        team: team.map(str::to_string),
+    };
+    let event = |customer: Option<Customer>| CustomerEvent {
+        id: "epoch-1".to_string(),
+        seq: 1,
+        kind: CustomerEventKind::Deleted,
+        customer_id: "65f1a0c2e4b0a1b2c3d4e5f6".to_string(),
+        customer,
+        tenant_id: None,
+    };
+
+    let runtime = rocket::tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let admin = Principal::ApiKey(store.verify("bootstrap-key").await.unwrap());
+        assert_eq!(admin.ownership(), Ownership::All);
+        assert_eq!(
+            Ownership::All.filter(mongodb::bson::doc! {"name": "a"}),
+            mongodb::bson::doc! {"name": "a"}
+        );
+
+        let issued = issuer.issue(&client, &[Scope::CustomerRead]);
+        let member = Principal::Bearer(verifier.verify(&issued.access_token).await.unwrap());
+        let blue = member.ownership();
+        assert_eq!(blue, Ownership::Team(Some("blue".to_string())));
+        assert_eq!(
+            blue.filter(mongodb::bson::doc! {"name": "a"}),
+            mongodb::bson::doc! {"name": "a", "team": "blue"}
+        );
+        assert!(blue.includes(&customer(Some("blue"))));
+        assert!(!blue.includes(&customer(Some("red"))));
+        assert!(!blue.includes(&customer(None)));
+        assert!(!event(None).is_visible_to(None, &blue));
+        assert!(event(None).is_visible_to(None, &Ownership::All));
+
+        // a team member cannot hand a customer to another team
+        let input = CustomerInput {
+            name: "customer".to_string(),
+            team: Some("red".to_string()),
+        };
+        assert_eq!(blue.team_for(&input), Some("blue".to_string()));
+        assert_eq!(Ownership::All.team_for(&input), Some("red".to_string()));
+    });
+
+    let viewer = BearerToken::new(
+        "user-1",
+        "issuer",
+        Vec::new(),
+        vec![Role::Viewer],
+        None,
+        None,
+    );
+    assert!(viewer.has_scope(Scope::CustomerRead));
+    assert!(!viewer.has_scope(Scope::CustomerWrite));
+    assert_eq!(Principal::Bearer(viewer).ownership(), Ownership::Team(None));
+}
+
+#[test]
+fn tenants_are_resolved_from_credentials_or_header() {
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let db = client
+        .rocket()
+        .state::<mongodb::Database>()
+        .unwrap()
+        .clone();
+    let config = TenancyConfig {
+        enabled: true,
+        tenants: [("acme", None), ("globex", Some("globex_customers"))]
+            .into_iter()
+            .map(|(id, database)| {
+                let tenant = TenantConfig {
+                    database: database.map(str::to_string),
+                    ..Default::default()
+                };
+                (id.to_string(), tenant)
+            })
+            .collect(),
+        ..Default::default()
+    };
+    assert!(config.validate().is_ok());
+
+    let tenants = Tenants::new(config.clone(), db.clone());
+    let acme = tenants.resolve(None, Some("acme")).unwrap();
+    assert_eq!(acme.id(), Some("acme"));
+    assert!(!acme.has_own_database());
+    assert_eq!(
+        acme.filter(mongodb::bson::doc! {"name": "a"}),
+        mongodb::bson::doc! {"name": "a", "tenantId": "acme"}
+    );
+    let member = |tenant: Option<&str>| {
+        Principal::Bearer(BearerToken::new(
+            "user-1",
+            "issuer",
+            Vec::new(),
+            vec![Role::Viewer],
+            Some("blue".to_string()),
+            tenant.map(str::to_string),
+        ))
+    };
+    let (globex_member, unbound_member) = (member(Some("globex")), member(None));
+    assert_eq!(
+        tenants.resolve(Some(&globex_member), None).unwrap().id(),
+        Some("globex")
+    );
+    assert!(matches!(
+        tenants.resolve(Some(&globex_member), Some("acme")),
+        Err(TenantError::Mismatch)
+    ));
+    // only admins bound to no tenant may choose one
+    assert!(matches!(
+        tenants.resolve(Some(&unbound_member), Some("acme")),
+        Err(TenantError::Unbound)
+    ));
+    assert!(matches!(
+        tenants.resolve(Some(&unbound_member), None),
+        Err(TenantError::Unbound)
+    ));
+    assert!(matches!(
+        tenants.resolve(None, Some("initech")),
+        Err(TenantError::Unknown(_))
+    ));
+    assert!(matches!(
+        tenants.resolve(None, None),
+        Err(TenantError::Missing)
+    ));
+
+    let tenants = Tenants::new(
+        TenancyConfig {
+            isolation: Isolation::Database,
+            default_tenant: "acme".to_string(),
+            ..config
+        },
+        db.clone(),
+    );
+    let acme = tenants.resolve(Some(&unbound_member), None).unwrap();
+    assert_eq!(acme.db().name(), format!("{}_acme", db.name()));
+    assert!(tenants.resolve(Some(&unbound_member), Some("acme")).is_ok());
+    assert!(matches!(
+        tenants.resolve(Some(&unbound_member), Some("globex")),
+        Err(TenantError::Unbound)
+    ));
+    assert_eq!(
+        acme.filter(mongodb::bson::doc! {"name": "a"}),
+        mongodb::bson::doc! {"name": "a"}
+    );
+    let globex = tenants.resolve(None, Some("globex")).unwrap();
+    assert_eq!(globex.db().name(), "globex_customers");
+    assert!(globex.has_own_database());
+
+    // webhooks are dispatched from the database of every tenant
+    let mut databases: Vec<_> = tenants
+        .all()
+        .iter()
+        .map(|tenant| tenant.db().name().to_owned())
+        .collect();
+    databases.sort();
+    assert_eq!(
+        databases,
+        [format!("{}_acme", db.name()), "globex_customers".to_owned()]
+    );
+
+    // without tenancy every request is served from the shared database
+    let single = Tenants::new(TenancyConfig::default(), db.clone())
+        .resolve(None, Some("acme"))
+        .unwrap();
+    assert_eq!(single.id(), None);
+    assert!(!single.has_own_database());
+}
+
+#[test]
+fn customer_routes_require_a_tenant_once_enabled() {
+    let figment = rocket::Config::figment()
+        .merge(("tenancy.enabled", true))
+        .merge(("tenancy.tenants.acme", serde_json::json!({})));
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    assume_db_available(&client);
+    let response = client
+        .get("/customer/not-an-id")
+        .header(api_key())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(
+        body["error"]["description"],
+        "The X-Tenant-Id header is required."
+    );
+
+    let response = client
+        .get("/customer/not-an-id")
+        .header(api_key())
+        .header(Header::new("X-Tenant-Id", "initech"))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["error"]["description"], "Unknown tenant initech.");
+
+    let openapi: serde_json::Value = client.get("/openapi.json").dispatch().into_json().unwrap();
+    let parameters = &openapi["paths"]["/customer"]["get"]["parameters"];
+    assert!(parameters
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|parameter| parameter["name"] == "X-Tenant-Id"));
+
+    let figment = rocket::Config::figment()
+        .merge(("tenancy.enabled", true))
+        .merge(("tenancy.tenants.not a tenant", serde_json::json!({})));
+    let Err(error) = Client::tracked(rocket().configure(figment)) else {
+        panic!("ignite should fail");
+    };
+    assert!(matches!(
+        error.kind(),
+        rocket::error::ErrorKind::FailedFairings(_)
+    ));
+}
+
+#[test]
+fn signed_requests_are_verified_within_the_window() {
+    let store = ApiKeyStore::new(AuthConfig::default(), None);
+    let generated = store.generate();
+    assert_ne!(generated.signing_secret, generated.hash);
+    assert_eq!(
+        generated.signing_secret,
+        store.signing_secret(&generated.hash)
+    );
+
+    let header = SignatureHeader::parse(
+        "HMAC-SHA256 KeyId=0123456789ab, Timestamp=1700000000, Nonce=n-1, Signature=00ff",
+    )
+    .unwrap();
+    assert_eq!(header.key_id, "0123456789ab");
+    assert_eq!(header.timestamp, 1_700_000_000);
+    assert!(SignatureHeader::parse("HMAC-SHA256 KeyId=0123456789ab").is_none());
+    assert!(SignatureHeader::parse("Bearer token").is_none());
+    assert_eq!(
+        string_to_sign(
+            "POST",
+            "/customer?x=1",
+            1_700_000_000,
+            "n-1",
+            &body_hash(b"")
+        ),
+        "HMAC-SHA256\nPOST\n/customer?x=1\n1700000000\nn-1\n\
+         e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert!(SigningConfig {
+        window_secs: 0,
+        ..Default::default()
+    }
+    .validate()
+    .is_err());
+
+    // stale requests are rejected before any lookup
+    let verifier = SignatureVerifier::new(SigningConfig::default(), store, None);
+    let runtime = rocket::tokio::runtime::Runtime::new().unwrap();
+    let result = runtime.block_on(verifier.verify("GET", "/customer", &header, ""));
+    assert!(matches!(result, Err(AuthError::Invalid)));
+
+    let client = Client::tracked(rocket()).expect("valid rocket instance");
+    let response = client
+        .get("/customer")
+        .header(Header::new(
+            "Authorization",
+            "HMAC-SHA256 KeyId=0123456789ab, Timestamp=1700000000, Nonce=n-1, Signature=00ff",
+        ))
+        .dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let openapi: serde_json::Value = client.get("/openapi.json").dispatch().into_json().unwrap();
+    assert_eq!(
+        openapi["components"]["securitySchemes"]["Signature"]["name"],
+        "Authorization"
+    );
+    let security = |path: &str, method: &str| openapi["paths"][path][method]["security"].clone();
+    assert!(security("/customer/{id}", "patch")
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|requirement| requirement["Signature"] == serde_json::json!(["customer:write"])));
+    assert!(!security("/admin/api-keys", "get")
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|requirement| requirement.get("Signature").is_some()));
+}
+
+/// Self-signed certificate of `CN=partner.example.com`, valid until 2126.
+const PARTNER_CERTIFICATE: &str = "\
+-----BEGIN CERTIFICATE-----
+MIIBtzCCAV2gAwIBAgIUJO2PJQqFTgY2zsHEspTgaeIXko4wCgYIKoZIzj0EAwIw
+MDEQMA4GA1UECgwHUGFydG5lcjEcMBoGA1UEAwwTcGFydG5lci5leGFtcGxlLmNv
+bTAgFw0yNjEwMTkwMzU1NDNaGA8yMTI2MDkyNTAzNTU0M1owMDEQMA4GA1UECgwH
+UGFydG5lcjEcMBoGA1UEAwwTcGFydG5lci5leGFtcGxlLmNvbTBZMBMGByqGSM49
+AgEGCCqGSM49AwEHA0IABFfT5alizVoONrZWUVySPItLgcO4Ufi1VtJb3Wr0mmr9
+hDDwq5ML4GIfux5LAc81YbS558quuRTbqx7u2MLRcuqjUzBRMB0GA1UdDgQWBBTB
+bCYv+nOus3zJshpxR0m8t8eTZzAfBgNVHSMEGDAWgBTBbCYv+nOus3zJshpxR0m8
+t8eTZzAPBgNVHRMBAf8EBTADAQH/MAoGCCqGSM49BAMCA0gAMEUCIH9MjItQk86k
+E7awUa6e/kwAuBdxo6eqE5a2RMSIACXOAiEA5NYmrG370jTf4Lqy3uQ3Mu0Z22+y
+0+5dnLuyewDDL+0=
+-----END CERTIFICATE-----
+";
+
+#[test]
+fn client_certificates_map_to_principals() {
+    let figment = rocket::Config::figment()
+        .merge(("auth.mtls.enabled", true))
+        .merge((
+            "auth.mtls.clients",
+            serde_json::json!({"partner.example.com": {"roles": ["viewer"], "team": "blue"}}),
+        ));
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    assume_db_available(&client);
+    let response = client
+        .get("/customer/not-an-id")
+        .identity(PARTNER_CERTIFICATE.as_bytes())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let response = client
+        .delete("/customer/not-an-id")
+        .identity(PARTNER_CERTIFICATE.as_bytes())
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    let response = client.get("/customer/not-an-id").dispatch();
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    // certificates of other subjects are not clients
+    let config = client.rocket().state::<AppConfig>().unwrap();
+    let partner = config.auth.mtls.principal("partner.example.com").unwrap();
+    assert_eq!(partner.identity(), "cert:partner.example.com");
+    assert_eq!(partner.team.as_deref(), Some("blue"));
+    assert!(config.auth.mtls.principal("other.example.com").is_none());
+}
+
+#[test]
+fn tenant_admins_only_manage_credentials_of_their_tenant() {
+    let figment = rocket::Config::figment()
+        .merge(("tenancy.enabled", true))
+        .merge(("tenancy.tenants.acme", serde_json::json!({})))
+        .merge(("tenancy.tenants.globex", serde_json::json!({})))
+        .merge(("auth.mtls.enabled", true))
+        .merge((
+            "auth.mtls.clients",
+            serde_json::json!({"partner.example.com": {"scopes": ["admin"], "tenant": "acme"}}),
+        ));
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    assume_db_available(&client);
+
+    // neither unbound credentials, which could choose any tenant, nor those
+    // of another tenant
+    let issue = |path: &str, tenant: serde_json::Value| {
+        client
+            .post(path.to_string())
+            .identity(PARTNER_CERTIFICATE.as_bytes())
+            .header(ContentType::JSON)
+            .body(
+                serde_json::json!({
+                    "name": "escalation",
+                    "owner": "acme",
+                    "scopes": ["admin"],
+                    "tenant": tenant,
+                })
+                .to_string(),
+            )
+            .dispatch()
+            .status()
+    };
+    for path in ["/admin/api-keys", "/admin/oauth-clients"] {
+        assert_eq!(issue(path, serde_json::Value::Null), Status::Forbidden);
+        assert_eq!(issue(path, "globex".into()), Status::Forbidden);
+    }
+
+    // deployment-wide admins only name tenants that exist
+    let response = client
+        .post("/admin/api-keys")
+        .header(api_key())
+        .header(ContentType::JSON)
+        .body(r#"{"name": "n", "owner": "o", "scopes": ["admin"], "tenant": "initech"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(body["error"]["description"], "Unknown tenant initech.");
+
+    // listing, rotating and revoking only match keys of the tenant
+    assert_eq!(
+        credentials_filter(Some("acme"), mongodb::bson::doc! {"revokedAt": null}),
+        mongodb::bson::doc! {"revokedAt": null, "tenant": "acme"}
+    );
+    assert_eq!(
+        credentials_filter(None, mongodb::bson::doc! {}),
+        mongodb::bson::doc! {}
+    );
+}
+
+#[test]
+fn unbound_principals_cannot_choose_a_tenant() {
+    let figment = rocket::Config::figment()
+        .merge(("tenancy.enabled", true))
+        .merge(("tenancy.default_tenant", "acme"))
+        .merge(("tenancy.tenants.acme", serde_json::json!({})))
+        .merge(("tenancy.tenants.globex", serde_json::json!({})))
+        .merge(("auth.mtls.enabled", true))
+        .merge((
+            "auth.mtls.clients",
+            serde_json::json!({"partner.example.com": {"roles": ["viewer"], "team": "blue"}}),
+        ));
+    let client = Client::tracked(rocket().configure(figment)).expect("valid rocket instance");
+    assume_db_available(&client);
+    let response = client
+        .get("/customer/not-an-id")
+        .identity(PARTNER_CERTIFICATE.as_bytes())
+        .header(Header::new("X-Tenant-Id", "globex"))
+        .dispatch();
+    assert_eq!(response.status(), Status::Forbidden);
+    let body: serde_json::Value = response.into_json().unwrap();
+    assert_eq!(
+        body["error"]["description"],
+        "The credentials are not bound to a tenant and cannot choose one."
+    );
+
+    // served for the default tenant, or any with the admin scope
+    let response = client
+        .get("/customer/not-an-id")
+        .identity(PARTNER_CERTIFICATE.as_bytes())
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+    let response = client
+        .get("/customer/not-an-id")
+        .header(api_key())
+        .header(Header::new("X-Tenant-Id", "globex"))
+        .dispatch();
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[test]
+fn certificates_are_reloaded_once_complete() {
+    let path = std::env::temp_dir().join(format!("cert-{}.pem", uuid::Uuid::new_v4()));
+    let paths = [path.clone()];
+    assert!(crate::tls::fingerprint(&paths).is_none());
+
+    std::fs::write(&path, PARTNER_CERTIFICATE).unwrap();
+    let current = crate::tls::fingerprint(&paths).unwrap();
+    // a file being written is not picked up
+    std::fs::write(&path, &PARTNER_CERTIFICATE[..200]).unwrap();
+    assert!(crate::tls::fingerprint(&paths).is_none());
+    std::fs::write(
+        &path,
+        format!("{}\n{}", PARTNER_CERTIFICATE, PARTNER_CERTIFICATE),
+    )
+    .unwrap();
+    assert_ne!(crate::tls::fingerprint(&paths).unwrap(), current);
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use rocket::fairing::AdHoc;
+use rocket::tokio;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::AppConfig;
+
+/// Set when the server stopped to be launched again with new certificates.
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+/// Settings of `[default.tls]` next to Rocket's own `certs`, `key` and
+/// `mutual`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// Seconds between checks of the certificate files, 0 to never reload.
+    pub reload_interval_secs: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            reload_interval_secs: 30,
+        }
+    }
+}
+
+/// Whether the server stopped for new certificates, clearing the request.
+pub fn take_reload() -> bool {
+    RELOAD.swap(false, Ordering::SeqCst)
+}
+
+/// Digest of the files, `None` while one of them is missing or not a
+/// complete PEM file, as when it is being written.
+pub fn fingerprint(paths: &[PathBuf]) -> Option<Vec<u8>> {
+    let mut digest = Sha256::new();
+    for path in paths {
+        let contents = std::fs::read(path).ok()?;
+        let pem = std::str::from_utf8(&contents).ok()?;
+        if !pem.contains("-----BEGIN ") || !pem.trim_end().ends_with("-----") {
+            return None;
+        }
+        digest.update(&contents);
+    }
+    Some(digest.finalize().to_vec())
+}
+
+/// Watch the certificate chain, key and CA certificates of `tls` and, once
+/// they are replaced, stop gracefully for `main` to launch the server again
+/// with them: Rocket 0.5 reads them only at launch and its TLS acceptor
+/// cannot be swapped. In-memory state such as the change feed history and
+/// memory rate limit buckets starts over.
+pub fn init() -> AdHoc {
+    AdHoc::on_liftoff("TLS certificate reload", |rocket| {
+        Box::pin(async move {
+            let interval = rocket
+                .state::<AppConfig>()
+                .and_then(|config| config.tls.as_ref())
+                .map_or(0, |tls| tls.reload_interval_secs);
+            let Some(tls) = rocket.config().tls.as_ref() else {
+                return;
+            };
+            if !rocket.config().tls_enabled() || interval == 0 {
+                return;
+            }
+            // certificates given inline cannot change
+            let paths: Vec<PathBuf> = [
+                Some(tls.certs()),
+                Some(tls.key()),
+                tls.mutual().map(|mutual| mutual.ca_certs()),
+            ]
+            .into_iter()
+            .flatten()
+            .filter_map(|file| file.left())
+            .collect();
+            let Some(current) = fingerprint(&paths) else {
+                return;
+            };
+
+            let (shutdown, mut stopped) = (rocket.shutdown(), rocket.shutdown());
+            tokio::spawn(async move {
+                let mut ticks = tokio::time::interval(Duration::from_secs(interval));
+                loop {
+                    tokio::select! {
+                        _ = ticks.tick() => {}
+                        _ = &mut stopped => break,
+                    }
+                    if fingerprint(&paths).is_some_and(|new| new != current) {
+                        tracing::info!("TLS certificates changed, relaunching");
+                        RELOAD.store(true, Ordering::SeqCst);
+                        shutdown.notify();
+                        break;
+                    }
+                }
+            });
+        })
+    })
+}
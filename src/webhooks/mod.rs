@@ -0,0 +1,260 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use chrono::Utc;
+use rocket::fairing::AdHoc;
+use rocket::tokio::{select, time::sleep};
+use serde::Deserialize;
+use url::{Host, Url};
+
+use crate::config::AppConfig;
+use crate::db::{
+    outbox,
+    tenant::{Tenant, Tenants},
+    webhook,
+};
+use crate::models::webhook::{WebhookDeliveryDocument, WebhookPayload};
+
+pub mod signature;
+
+/// `[default.webhooks]` section of `Rocket.toml`.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// Seconds between two scans of the outbox and of due deliveries.
+    pub poll_interval: u64,
+    /// Attempts made before a delivery is dead-lettered.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after each failure.
+    pub backoff_base: u64,
+    /// Upper bound for the delay between retries.
+    pub backoff_max: u64,
+    /// Seconds to wait for the receiver to answer.
+    pub timeout: u64,
+    /// Seconds dispatched outbox events are kept before MongoDB removes them.
+    pub outbox_retention: u64,
+    /// Accept webhooks on loopback, private and link-local addresses, which
+    /// would otherwise let admins reach services behind the firewall.
+    pub allow_private_targets: bool,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            poll_interval: 1,
+            max_attempts: 8,
+            backoff_base: 5,
+            backoff_max: 3600,
+            timeout: 10,
+            outbox_retention: 7 * 24 * 3600,
+            allow_private_targets: false,
+        }
+    }
+}
+
+impl WebhookConfig {
+    /// Delay before retrying a delivery that already failed `attempts` times.
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let delay = self
+            .backoff_base
+            .saturating_mul(2u64.saturating_pow(attempts.saturating_sub(1)));
+        Duration::from_secs(delay.min(self.backoff_max))
+    }
+
+    /// Why events cannot be sent to `url`: not an HTTP(S) URL or, unless
+    /// `allow_private_targets`, a host on a loopback, private or link-local
+    /// address. Names are not resolved, only `localhost` is known to be local.
+    pub fn check_url(&self, url: &str) -> Result<(), String> {
+        let url = Url::parse(url).map_err(|_| "Invalid url.".to_string())?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err("The url must use http or https.".into());
+        }
+        let private = match url.host() {
+            None => return Err("Invalid url.".into()),
+            Some(Host::Domain(domain)) => {
+                let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+                domain == "localhost" || domain.ends_with(".localhost")
+            }
+            Some(Host::Ipv4(ip)) => is_private(IpAddr::V4(ip)),
+            Some(Host::Ipv6(ip)) => is_private(IpAddr::V6(ip)),
+        };
+        if private && !self.allow_private_targets {
+            return Err("The url must not point to a private address.".into());
+        }
+        Ok(())
+    }
+}
+
+/// Whether `ip` is not reachable from the internet.
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            // 100.64.0.0/10, shared by carrier-grade NATs
+            let shared = ip.octets()[0] == 100 && (ip.octets()[1] & 0xc0) == 64;
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || shared
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_private(IpAddr::V4(ip)),
+            None => {
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+            }
+        },
+    }
+}
+
+/// Sends outbox events to the subscribed webhooks.
+pub struct Dispatcher {
+    config: WebhookConfig,
+    http: reqwest::Client,
+}
+
+impl Dispatcher {
+    pub fn new(config: WebhookConfig) -> Dispatcher {
+        // a redirect could lead to an address `check_url` rejects
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("valid HTTP client configuration");
+        Dispatcher { config, http }
+    }
+
+    /// POST a signed payload to `url`, succeeding on any 2xx answer.
+    pub async fn deliver(
+        &self,
+        url: &str,
+        secret: &str,
+        delivery_id: &str,
+        event: &str,
+        payload: &str,
+    ) -> Result<(), String> {
+        let timestamp = Utc::now().timestamp();
+        let response = self
+            .http
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("X-Webhook-Id", delivery_id)
+            .header("X-Webhook-Event", event)
+            .header(signature::TIMESTAMP_HEADER, timestamp.to_string())
+            .header(
+                signature::SIGNATURE_HEADER,
+                signature::sign(secret, timestamp, payload),
+            )
+            .body(payload.to_owned())
+            .send()
+            .await
+            .map_err(|error| error.to_string())?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("Receiver answered {}", response.status()))
+        }
+    }
+
+    /// Fan the outbox events of `tenant` out to the deliveries of its
+    /// webhooks, then attempt every due delivery of the tenant.
+    pub async fn run_once(&self, tenant: &Tenant) -> mongodb::error::Result<()> {
+        let lease = chrono::Duration::seconds(self.config.timeout as i64 * 2);
+        let db = tenant.db();
+
+        while let Some(event) = outbox::claim_pending_event(tenant, lease).await? {
+            let webhooks =
+                webhook::find_subscribed_webhooks(db, event.kind, event.tenant_id.as_deref())
+                    .await?;
+            let payload = serde_json::to_string(&WebhookPayload::from(event.clone())).unwrap();
+            for webhook_doc in webhooks {
+                webhook::insert_delivery(tenant, event.id, webhook_doc.id, event.kind, &payload)
+                    .await?;
+            }
+            let retention = Duration::from_secs(self.config.outbox_retention);
+            outbox::mark_dispatched(db, &event, retention).await?;
+        }
+
+        while let Some(delivery) = webhook::claim_due_delivery(tenant, lease).await? {
+            self.attempt(tenant, delivery).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn attempt(
+        &self,
+        tenant: &Tenant,
+        delivery: WebhookDeliveryDocument,
+    ) -> mongodb::error::Result<()> {
+        let db = tenant.db();
+        // the webhook may have been deleted or disabled since the event was queued
+        let webhook_doc = match webhook::find_webhook_by_id(tenant, delivery.webhook_id).await? {
+            Some(webhook_doc) if webhook_doc.active => webhook_doc,
+            _ => return webhook::delete_delivery(db, delivery.id).await,
+        };
+        // webhooks registered before their url was checked
+        if let Err(error) = self.config.check_url(&webhook_doc.url) {
+            return webhook::dead_letter_delivery(db, &delivery, &error).await;
+        }
+
+        let result = self
+            .deliver(
+                &webhook_doc.url,
+                &webhook_doc.secret,
+                &delivery.id.to_string(),
+                delivery.kind.as_str(),
+                &delivery.payload,
+            )
+            .await;
+
+        match result {
+            Ok(()) => webhook::delete_delivery(db, delivery.id).await,
+            Err(error) if delivery.attempts + 1 >= self.config.max_attempts => {
+                webhook::dead_letter_delivery(db, &delivery, &error).await
+            }
+            Err(error) => {
+                let delay = self.config.backoff(delivery.attempts + 1);
+                let next_attempt_at = Utc::now() + chrono::Duration::from_std(delay).unwrap();
+                webhook::reschedule_delivery(db, delivery.id, &error, next_attempt_at).await
+            }
+        }
+    }
+}
+
+/// Dispatch the webhooks of every tenant, so it has to be attached after
+/// `db::tenant::init`.
+pub fn init() -> AdHoc {
+    AdHoc::on_liftoff("Dispatching webhooks", |rocket| {
+        Box::pin(async move {
+            let Some(tenants) = rocket.state::<Tenants>() else {
+                return;
+            };
+            let config = rocket
+                .state::<AppConfig>()
+                .map(|config| config.webhooks.clone())
+                .unwrap_or_default();
+            let (tenants, mut shutdown) = (tenants.clone(), rocket.shutdown());
+
+            rocket::tokio::spawn(async move {
+                let interval = Duration::from_secs(config.poll_interval);
+                let dispatcher = Dispatcher::new(config);
+                loop {
+                    for tenant in tenants.all() {
+                        if let Err(error) = dispatcher.run_once(&tenant).await {
+                            tracing::error!(%error, tenant = tenant.id(), "webhook dispatch failed");
+                        }
+                    }
+                    select! {
+                        _ = sleep(interval) => {}
+                        _ = &mut shutdown => break,
+                    }
+                }
+            });
+        })
+    })
+}
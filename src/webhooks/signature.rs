@@ -0,0 +1,20 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Header carrying the HMAC-SHA256 signature, as `sha256=<hex>`.
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+/// Header carrying the unix timestamp covered by the signature.
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+
+/// Sign `"{timestamp}.{body}"` with the webhook secret.
+///
+/// Receivers recompute it with their copy of the secret and reject stale
+/// timestamps to prevent replays.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}